@@ -0,0 +1,87 @@
+// glzip is a graph compression library for graph learning systems
+// Copyright (C) 2022 Jacob Konrad
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Size and decode throughput of the codecs on the same graphs as the
+//! `neighbors` bench, run with `cargo bench --bench codecs` to also see the
+//! bytes per edge of each codec, and with `--features simd` for the SIMD
+//! decoders.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use glzip::{codec::Codec, Edge, CSR};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+const SIZE: usize = 2_000_000;
+
+/// The codecs, with a Rice parameter close to the log of the mean
+/// difference, which is about 5000 in the sparse graph and 50 in the dense
+/// graph.
+const CODECS: [(&str, Codec, Codec); 9] = [
+    ("byte_rle", Codec::ByteRle, Codec::ByteRle),
+    ("byte", Codec::Byte, Codec::Byte),
+    ("nibble", Codec::Nibble, Codec::Nibble),
+    ("stream_vbyte", Codec::StreamVByte, Codec::StreamVByte),
+    ("gamma", Codec::Gamma, Codec::Gamma),
+    ("delta", Codec::Delta, Codec::Delta),
+    ("zeta_3", Codec::Zeta(3), Codec::Zeta(3)),
+    ("rice", Codec::Rice(12), Codec::Rice(5)),
+    ("rans", Codec::Rans, Codec::Rans),
+];
+
+/// A random graph, with average degree 20 for 100k vertices and 200 for 10k
+/// vertices.
+fn graph(order: u32, codec: Codec) -> CSR
+{
+    let mut rng = StdRng::seed_from_u64(0);
+    let csr = CSR::builder()
+        .codec(codec)
+        .build((0..SIZE).map(|_| Edge(rng.gen_range(0..order), rng.gen_range(0..order))));
+    eprintln!(
+        "{:?}, {} vertices: {:.3} bytes per edge",
+        codec,
+        order,
+        csr.nbytes() as f64 / csr.size() as f64
+    );
+    csr
+}
+
+fn codecs(c: &mut Criterion)
+{
+    for (name, order, dense) in [("sparse", 100_000, false), ("dense", 10_000, true)] {
+        let mut group = c.benchmark_group(format!("codecs_{}", name));
+        for (codec_name, sparse_codec, dense_codec) in CODECS {
+            let csr = graph(order, if dense { dense_codec } else { sparse_codec });
+            group.throughput(Throughput::Bytes((csr.size() * 4) as u64));
+            let mut buf = Vec::new();
+            group.bench_function(codec_name, |b| {
+                b.iter(|| {
+                    for u in 0..order {
+                        buf.clear();
+                        csr.neighbors_into(u, &mut buf);
+                        black_box(&buf);
+                    }
+                })
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = codecs
+}
+criterion_main!(benches);
@@ -0,0 +1,69 @@
+// glzip is a graph compression library for graph learning systems
+// Copyright (C) 2022 Jacob Konrad
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Decode throughput of the neighbor iterator against the buffer decoder,
+//! run with `cargo bench`, and `cargo bench --features simd` for the SIMD
+//! decoder.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use glzip::{Edge, CSR};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+const SIZE: usize = 2_000_000;
+
+/// A random graph, with average degree 20 for 100k vertices and 200 for 10k
+/// vertices, where most diffs fit in a byte and the groups are full.
+fn graph(order: u32) -> CSR
+{
+    let mut rng = StdRng::seed_from_u64(0);
+    CSR::from_edges((0..SIZE).map(|_| Edge(rng.gen_range(0..order), rng.gen_range(0..order))))
+}
+
+fn neighbors(c: &mut Criterion)
+{
+    for (name, order) in [("sparse", 100_000), ("dense", 10_000)] {
+        let csr = graph(order);
+        let mut group = c.benchmark_group(format!("neighbors_{}", name));
+        group.throughput(Throughput::Bytes((csr.size() * 4) as u64));
+        let mut buf = Vec::new();
+        group.bench_function("iter", |b| {
+            b.iter(|| {
+                for u in 0..order {
+                    buf.clear();
+                    buf.extend(csr.neighbors(u));
+                    black_box(&buf);
+                }
+            })
+        });
+        group.bench_function("into", |b| {
+            b.iter(|| {
+                for u in 0..order {
+                    buf.clear();
+                    csr.neighbors_into(u, &mut buf);
+                    black_box(&buf);
+                }
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = neighbors
+}
+criterion_main!(benches);
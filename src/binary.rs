@@ -0,0 +1,502 @@
+// glzip is a graph compression library for graph learning systems
+// Copyright (C) 2022 Jacob Konrad
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Binary edge lists: raw little endian `u32` or `u64` pairs and NumPy `.npy`
+//! arrays of shape `[2, E]` (PyG's `edge_index`) or `[E, 2]`.
+//!
+//! The edges are streamed in chunks, so the file is never held in memory, and
+//! the number of edges is known up front from the file length or the `.npy`
+//! header.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::{error::Error, vertex::VertexId, Edge, CSR};
+
+const CHUNK: usize = 1 << 16;
+
+const NPY_MAGIC: &[u8; 6] = b"\x93NUMPY";
+
+/// The longest `.npy` header that is read, as NumPy's own `max_header_size`.
+/// The header of an edge list is well under 100 bytes.
+const NPY_MAX_HEADER_LEN: usize = 10_000;
+
+#[derive(Clone, Copy)]
+enum Dtype
+{
+    I32,
+    I64,
+    U32,
+    U64,
+}
+
+impl Dtype
+{
+    fn width(self) -> usize
+    {
+        match self {
+            Dtype::I32 | Dtype::U32 => 4,
+            Dtype::I64 | Dtype::U64 => 8,
+        }
+    }
+
+    fn read(self, bytes: &[u8]) -> i128
+    {
+        match self {
+            Dtype::I32 => i32::from_le_bytes(bytes.try_into().unwrap()) as i128,
+            Dtype::I64 => i64::from_le_bytes(bytes.try_into().unwrap()) as i128,
+            Dtype::U32 => u32::from_le_bytes(bytes.try_into().unwrap()) as i128,
+            Dtype::U64 => u64::from_le_bytes(bytes.try_into().unwrap()) as i128,
+        }
+    }
+}
+
+/// An iterator over the edges of a binary edge list.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Cursor;
+///
+/// use glzip::{binary::BinaryEdges, Edge};
+///
+/// let bytes: Vec<u8> = [0u32, 1, 2, 1].iter().flat_map(|x| x.to_le_bytes()).collect();
+///
+/// let edges: BinaryEdges<_> = BinaryEdges::u32_pairs(Cursor::new(bytes)).unwrap();
+///
+/// assert_eq!(2, edges.len());
+/// assert_eq!(vec![Edge(0, 1), Edge(2, 1)], edges.collect::<Result<Vec<_>, _>>().unwrap());
+/// ```
+pub struct BinaryEdges<R, V = u32>
+{
+    reader: R,
+    dtype: Dtype,
+    // Whether all sources precede all targets, rather than interleaved pairs.
+    planar: bool,
+    data_start: u64,
+    len: u64,
+    pos: u64,
+    bytes: Vec<u8>,
+    chunk: Vec<Edge<V>>,
+    next: usize,
+}
+
+impl<R: Read + Seek, V: VertexId> BinaryEdges<R, V>
+{
+    fn new(mut reader: R, dtype: Dtype, planar: bool, data_start: u64, len: u64) -> io::Result<Self>
+    {
+        reader.seek(SeekFrom::Start(data_start))?;
+        Ok(Self {
+            reader,
+            dtype,
+            planar,
+            data_start,
+            len,
+            pos: 0,
+            bytes: Vec::new(),
+            chunk: Vec::new(),
+            next: 0,
+        })
+    }
+
+    fn pairs(mut reader: R, dtype: Dtype) -> Result<Self, Error>
+    {
+        let start = reader.stream_position()?;
+        let end = reader.seek(SeekFrom::End(0))?;
+        let pair = 2 * dtype.width() as u64;
+        if (end - start) % pair != 0 {
+            return Err(Error::Header(format!(
+                "length {} is not a multiple of {}",
+                end - start,
+                pair
+            )));
+        }
+        Ok(Self::new(reader, dtype, false, start, (end - start) / pair)?)
+    }
+
+    /// Little endian `u32` pairs, `source target source target ...`.
+    pub fn u32_pairs(reader: R) -> Result<Self, Error>
+    {
+        Self::pairs(reader, Dtype::U32)
+    }
+
+    /// Little endian `u64` pairs, `source target source target ...`.
+    pub fn u64_pairs(reader: R) -> Result<Self, Error>
+    {
+        Self::pairs(reader, Dtype::U64)
+    }
+
+    /// A NumPy `.npy` array of little endian 32 or 64 bit integers with shape
+    /// `[2, E]` or `[E, 2]`, in C or Fortran order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    ///
+    /// use glzip::{binary::BinaryEdges, Edge};
+    ///
+    /// // np.save(f, np.array([[0, 0, 2], [1, 2, 1]], dtype=np.int64))
+    /// let header = b"{'descr': '<i8', 'fortran_order': False, 'shape': (2, 3), }";
+    /// let mut npy = b"\x93NUMPY\x01\x00".to_vec();
+    /// npy.extend((header.len() as u16).to_le_bytes());
+    /// npy.extend(header);
+    /// npy.extend([0i64, 0, 2, 1, 2, 1].iter().flat_map(|x| x.to_le_bytes()));
+    ///
+    /// let edges: BinaryEdges<_> = BinaryEdges::npy(Cursor::new(npy)).unwrap();
+    ///
+    /// assert_eq!(
+    ///     vec![Edge(0, 1), Edge(0, 2), Edge(2, 1)],
+    ///     edges.collect::<Result<Vec<_>, _>>().unwrap()
+    /// );
+    /// ```
+    pub fn npy(mut reader: R) -> Result<Self, Error>
+    {
+        let invalid = |msg: &str| Error::Header(msg.to_string());
+
+        let mut preamble = [0u8; 8];
+        reader.read_exact(&mut preamble)?;
+        if &preamble[..6] != NPY_MAGIC {
+            return Err(invalid("not a .npy file"));
+        }
+
+        let header_len = match preamble[6] {
+            1 => {
+                let mut len = [0u8; 2];
+                reader.read_exact(&mut len)?;
+                u16::from_le_bytes(len) as usize
+            }
+            2 | 3 => {
+                let mut len = [0u8; 4];
+                reader.read_exact(&mut len)?;
+                u32::from_le_bytes(len) as usize
+            }
+            _ => return Err(invalid("unsupported .npy version")),
+        };
+
+        if header_len > NPY_MAX_HEADER_LEN {
+            return Err(invalid(&format!("header of {} bytes is too long", header_len)));
+        }
+        let mut header = vec![0u8; header_len];
+        reader.read_exact(&mut header)?;
+        let header = String::from_utf8_lossy(&header);
+
+        let value = |key: &str| -> Option<&str> {
+            let start = header.find(key)? + key.len();
+            let rest = header[start..].trim_start_matches(|c: char| c == '\'' || c == ':' || c.is_whitespace());
+            let end = if rest.starts_with('(') {
+                rest.find(')')? + 1
+            }
+            else {
+                rest.find([',', '\'', '}'])?
+            };
+            Some(&rest[..end])
+        };
+
+        let dtype = match value("'descr'") {
+            Some("<i4") => Dtype::I32,
+            Some("<i8") => Dtype::I64,
+            Some("<u4") => Dtype::U32,
+            Some("<u8") => Dtype::U64,
+            Some(descr) => return Err(invalid(&format!("unsupported dtype {}", descr))),
+            None => return Err(invalid("missing descr")),
+        };
+
+        let fortran_order = match value("'fortran_order'") {
+            Some("False") => false,
+            Some("True") => true,
+            _ => return Err(invalid("missing fortran_order")),
+        };
+
+        let shape: Vec<u64> = value("'shape'")
+            .ok_or_else(|| invalid("missing shape"))?
+            .trim_matches(|c| c == '(' || c == ')')
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid("malformed shape"))?;
+
+        // [2, E] in C order and [E, 2] in Fortran order store all the sources
+        // first, the other two interleave the pairs.
+        let (len, planar) = match shape[..] {
+            [2, e] => (e, !fortran_order),
+            [e, 2] => (e, fortran_order),
+            _ => return Err(invalid("shape must be [2, E] or [E, 2]")),
+        };
+
+        // The shape decides how many edges are reserved for, so it can't
+        // claim more than the file holds.
+        let data_start = reader.stream_position()?;
+        let end = reader.seek(SeekFrom::End(0))?;
+        if len
+            .checked_mul(2 * dtype.width() as u64)
+            .is_none_or(|data_len| data_len > end.saturating_sub(data_start))
+        {
+            return Err(invalid(&format!("shape of {} edges is larger than the file", len)));
+        }
+
+        Ok(Self::new(reader, dtype, planar, data_start, len)?)
+    }
+
+    /// The number of edges that have not been read yet.
+    pub fn len(&self) -> usize
+    {
+        (self.len - self.pos) as usize + (self.chunk.len() - self.next)
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.len() == 0
+    }
+
+    fn vertex(&self, index: u64, bytes: &[u8]) -> Result<V, Error>
+    {
+        let id = self.dtype.read(bytes);
+        u64::try_from(id)
+            .ok()
+            .and_then(V::try_from_u64)
+            .ok_or(Error::IdOverflow { edge: Some(index), id })
+    }
+
+    fn fill(&mut self) -> Result<(), Error>
+    {
+        let n = std::cmp::min(CHUNK as u64, self.len - self.pos) as usize;
+        let width = self.dtype.width();
+
+        self.bytes.resize(2 * n * width, 0);
+        if self.planar {
+            let (sources, targets) = self.bytes.split_at_mut(n * width);
+            self.reader
+                .seek(SeekFrom::Start(self.data_start + self.pos * width as u64))?;
+            self.reader.read_exact(sources)?;
+            self.reader.seek(SeekFrom::Start(
+                self.data_start + (self.len + self.pos) * width as u64,
+            ))?;
+            self.reader.read_exact(targets)?;
+        }
+        else {
+            self.reader.read_exact(&mut self.bytes)?;
+        }
+
+        let mut chunk = std::mem::take(&mut self.chunk);
+        chunk.clear();
+        for i in 0..n {
+            let (u, v) = if self.planar {
+                (i * width, (n + i) * width)
+            }
+            else {
+                (2 * i * width, (2 * i + 1) * width)
+            };
+            let index = self.pos + i as u64;
+            let u = self.vertex(index, &self.bytes[u..u + width])?;
+            let v = self.vertex(index, &self.bytes[v..v + width])?;
+            chunk.push(Edge(u, v));
+        }
+        self.chunk = chunk;
+        self.next = 0;
+        self.pos += n as u64;
+
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek, V: VertexId> Iterator for BinaryEdges<R, V>
+{
+    type Item = Result<Edge<V>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item>
+    {
+        if self.next == self.chunk.len() {
+            if self.pos == self.len {
+                return None;
+            }
+            if let Err(err) = self.fill() {
+                // Stop after the first error.
+                self.pos = self.len;
+                self.chunk.clear();
+                self.next = 0;
+                return Some(Err(err));
+            }
+        }
+        let edge = self.chunk[self.next];
+        self.next += 1;
+        Some(Ok(edge))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>)
+    {
+        (self.len(), Some(self.len()))
+    }
+}
+
+impl<V: VertexId> CSR<V>
+{
+    /// Builds a graph from a binary edge list, see [`BinaryEdges`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    ///
+    /// use glzip::{binary::BinaryEdges, CSR, Error};
+    ///
+    /// let bytes: Vec<u8> = [0u64, 1, 0, 2, 1, 0, 2, 1].iter().flat_map(|x| x.to_le_bytes()).collect();
+    ///
+    /// let csr: CSR = CSR::from_binary_edges(BinaryEdges::u64_pairs(Cursor::new(bytes.clone())).unwrap()).unwrap();
+    ///
+    /// assert_eq!(vec![1,2], csr.neighbors(0).collect::<Vec<_>>());
+    ///
+    /// let wide: CSR<u64> = CSR::from_binary_edges(BinaryEdges::u64_pairs(Cursor::new(bytes)).unwrap()).unwrap();
+    ///
+    /// assert_eq!(vec![1,2], wide.neighbors(0).collect::<Vec<_>>());
+    ///
+    /// let bytes: Vec<u8> = [0u64, 1 << 32].iter().flat_map(|x| x.to_le_bytes()).collect();
+    ///
+    /// let err = CSR::<u32>::from_binary_edges(BinaryEdges::u64_pairs(Cursor::new(bytes)).unwrap());
+    ///
+    /// assert!(matches!(err, Err(Error::IdOverflow { edge: Some(0), .. })));
+    /// ```
+    pub fn from_binary_edges<R: Read + Seek>(edges: BinaryEdges<R, V>) -> Result<Self, Error>
+    {
+        Self::try_from_edges_with_capacity(edges.len(), edges).map_err(Error::flatten)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::io::Cursor;
+
+    use super::*;
+
+    // The ids as little endian integers of `width` bytes.
+    fn le(ids: &[i64], width: usize) -> Vec<u8>
+    {
+        ids.iter().flat_map(|x| x.to_le_bytes()[..width].to_vec()).collect()
+    }
+
+    fn npy(version: u8, header: &[u8], data: &[u8]) -> Vec<u8>
+    {
+        let mut npy = NPY_MAGIC.to_vec();
+        npy.extend([version, 0]);
+        if version == 1 {
+            npy.extend((header.len() as u16).to_le_bytes());
+        }
+        else {
+            npy.extend((header.len() as u32).to_le_bytes());
+        }
+        npy.extend(header);
+        npy.extend(data);
+        npy
+    }
+
+    fn read(bytes: Vec<u8>) -> Result<BinaryEdges<Cursor<Vec<u8>>>, Error>
+    {
+        BinaryEdges::npy(Cursor::new(bytes))
+    }
+
+    fn decode(edges: Result<BinaryEdges<Cursor<Vec<u8>>>, Error>) -> Vec<Edge>
+    {
+        edges.unwrap().collect::<Result<_, _>>().unwrap()
+    }
+
+    const EDGES: [Edge; 4] = [Edge(0, 1), Edge(0, 2), Edge(2, 1), Edge(3, 0)];
+    const PLANAR: [i64; 8] = [0, 0, 2, 3, 1, 2, 1, 0];
+    const PAIRS: [i64; 8] = [0, 1, 0, 2, 2, 1, 3, 0];
+
+    fn header(descr: &str, fortran_order: bool, shape: &str) -> Vec<u8>
+    {
+        let fortran_order = if fortran_order { "True" } else { "False" };
+        format!("{{'descr': '{}', 'fortran_order': {}, 'shape': {}, }}", descr, fortran_order, shape).into_bytes()
+    }
+
+    #[test]
+    fn npy_header_lengths_are_capped()
+    {
+        let mut bytes = NPY_MAGIC.to_vec();
+        bytes.extend([2, 0]);
+        bytes.extend(u32::MAX.to_le_bytes());
+        assert!(matches!(read(bytes), Err(Error::Header(_))));
+    }
+
+    #[test]
+    fn npy_shapes_must_fit_the_file()
+    {
+        let header = b"{'descr': '<i8', 'fortran_order': False, 'shape': (2, 3), }";
+        assert_eq!(3, read(npy(3, header, &le(&[0, 0, 2, 1, 2, 1], 8))).unwrap().len());
+        assert!(matches!(read(npy(1, header, &le(&[0, 0, 2, 1, 2], 8))), Err(Error::Header(_))));
+
+        let header = b"{'descr': '<i8', 'fortran_order': False, 'shape': (2, 4611686018427387904), }";
+        assert!(matches!(read(npy(1, header, &le(&[0, 1], 8))), Err(Error::Header(_))));
+        let header = b"{'descr': '<i8', 'fortran_order': False, 'shape': (2, 1152921504606846976), }";
+        assert!(matches!(read(npy(1, header, &le(&[0, 1], 8))), Err(Error::Header(_))));
+    }
+
+    #[test]
+    fn npy_layouts_decode_to_the_same_edges()
+    {
+        // [2, E] in C order and [E, 2] in Fortran order are planar.
+        let layouts = [
+            (false, "(2, 4)", PLANAR),
+            (true, "(2, 4)", PAIRS),
+            (false, "(4, 2)", PAIRS),
+            (true, "(4, 2)", PLANAR),
+        ];
+        for (fortran_order, shape, ids) in layouts {
+            let bytes = npy(1, &header("<i8", fortran_order, shape), &le(&ids, 8));
+            assert_eq!(EDGES.to_vec(), decode(read(bytes)), "{} {}", fortran_order, shape);
+        }
+    }
+
+    #[test]
+    fn npy_dtypes_decode_to_the_same_edges()
+    {
+        for (descr, width) in [("<i4", 4), ("<i8", 8), ("<u4", 4), ("<u8", 8)] {
+            let bytes = npy(1, &header(descr, false, "(2, 4)"), &le(&PLANAR, width));
+            assert_eq!(EDGES.to_vec(), decode(read(bytes)), "{}", descr);
+        }
+    }
+
+    #[test]
+    fn planar_edges_span_chunks()
+    {
+        let len = CHUNK as i64 + 5;
+        let ids: Vec<i64> = (0..len).chain((0..len).map(|v| 3 * v)).collect();
+        let bytes = npy(1, &header("<i4", false, &format!("({}, {})", 2, len)), &le(&ids, 4));
+        let edges = decode(read(bytes));
+        assert_eq!(len as usize, edges.len());
+        assert!(edges.iter().enumerate().all(|(u, &e)| e == Edge(u as u32, 3 * u as u32)));
+    }
+
+    #[test]
+    fn pairs_decode_to_the_edges()
+    {
+        assert_eq!(EDGES.to_vec(), decode(BinaryEdges::u32_pairs(Cursor::new(le(&PAIRS, 4)))));
+        assert_eq!(EDGES.to_vec(), decode(BinaryEdges::u64_pairs(Cursor::new(le(&PAIRS, 8)))));
+        assert!(matches!(BinaryEdges::<_>::u64_pairs(Cursor::new(le(&PAIRS[..3], 8))), Err(Error::Header(_))));
+    }
+
+    #[test]
+    fn negative_ids_overflow()
+    {
+        for (descr, width) in [("<i4", 4), ("<i8", 8)] {
+            let bytes = npy(1, &header(descr, false, "(2, 2)"), &le(&[0, 1, 2, -1], width));
+            let edges: Result<Vec<Edge>, Error> = read(bytes).unwrap().collect();
+            assert!(matches!(edges, Err(Error::IdOverflow { edge: Some(1), id: -1 })), "{}", descr);
+        }
+    }
+}
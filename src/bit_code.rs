@@ -0,0 +1,412 @@
+// glzip is a graph compression library for graph learning systems
+// Copyright (C) 2022 Jacob Konrad
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Bit granular instantaneous codes for the differences between neighbors,
+// after the first neighbor as in `encoder::first_edge`. The bits are written
+// most significant first and the last byte is padded with zeros. Every code
+// here starts with a unary number, zeros ended by a one, so the padding is
+// never mistaken for a difference and the neighbors end with the bytes.
+//
+// Elias γ:  the bit length n - 1 of d in unary, then the n - 1 bits of d
+//           after its leading one.
+// Elias δ:  as γ with the bit length in γ.
+// ζ_k:      from WebGraph, h = ⌊log2 d⌋ / k in unary, then d - 2^hk in a
+//           minimal binary code for the interval [0, 2^(h+1)k - 2^hk).
+// Rice(k):  (d - 1) >> k in unary, then the k low bits of d - 1. Quotients
+//           of RICE_ESCAPE or more are written as RICE_ESCAPE and then d in
+//           γ, so that a large difference costs a few bytes rather than
+//           2^(64 - k) bits.
+//
+// The smallest codes for the skewed differences of real graphs, and the
+// slowest to decode, meant for graphs that are stored more than queried.
+
+use std::slice;
+
+use crate::{
+    checked::{self, Checked, Cursor, Sink, OVERFLOW, TRUNCATED},
+    decoder, encoder,
+    vertex::VertexId,
+};
+
+const RICE_ESCAPE: u32 = 63;
+
+#[derive(Clone, Copy)]
+pub enum Code
+{
+    Gamma,
+    Delta,
+    Zeta(u8),
+    Rice(u8),
+}
+
+pub struct BitWriter<'a>
+{
+    bytes: &'a mut Vec<u8>,
+    // The `len` < 8 bits not yet written, in the low bits.
+    acc: u128,
+    len: u32,
+}
+
+impl<'a> BitWriter<'a>
+{
+    pub fn new(bytes: &'a mut Vec<u8>) -> Self
+    {
+        Self { bytes, acc: 0, len: 0 }
+    }
+
+    /// Writes the `n` <= 120 low bits of `value`.
+    pub fn write(&mut self, value: u128, n: u32)
+    {
+        if n == 0 {
+            return;
+        }
+        self.acc = self.acc << n | value & (u128::MAX >> (128 - n));
+        self.len += n;
+        while self.len >= 8 {
+            self.len -= 8;
+            self.bytes.push((self.acc >> self.len) as u8);
+        }
+    }
+
+    fn unary(&mut self, n: u32)
+    {
+        self.write(1, n + 1);
+    }
+
+    fn gamma(&mut self, x: u64)
+    {
+        let n = x.ilog2();
+        self.unary(n);
+        self.write(x as u128, n);
+    }
+
+    fn delta(&mut self, x: u64)
+    {
+        let n = x.ilog2();
+        self.gamma(n as u64 + 1);
+        self.write(x as u128, n);
+    }
+
+    fn zeta(&mut self, x: u64, k: u32)
+    {
+        let h = x.ilog2() / k;
+        self.unary(h);
+        let left = 1u128 << (h * k);
+        let (s, m) = minimal_binary((left << k) - left);
+        let v = x as u128 - left;
+        if v < m {
+            self.write(v, s);
+        }
+        else {
+            self.write(v + m, s + 1);
+        }
+    }
+
+    fn rice(&mut self, x: u64, k: u32)
+    {
+        let q = (x - 1) >> k;
+        if q >= RICE_ESCAPE as u64 {
+            self.unary(RICE_ESCAPE);
+            self.gamma(x);
+        }
+        else {
+            self.unary(q as u32);
+            self.write((x - 1) as u128, k);
+        }
+    }
+
+    pub fn finish(self)
+    {
+        if self.len > 0 {
+            self.bytes.push((self.acc << (8 - self.len)) as u8);
+        }
+    }
+}
+
+/// The minimal binary code of the interval [0, z) writes the values below
+/// `m` in `s` bits and the others, plus `m`, in `s + 1` bits.
+#[inline]
+fn minimal_binary(z: u128) -> (u32, u128)
+{
+    let s = z.ilog2();
+    (s, (2 << s) - z)
+}
+
+pub fn encode<V, I>(bytes: &mut Vec<u8>, source: V, edges: I, code: Code)
+where
+    V: VertexId,
+    I: IntoIterator<Item = V>,
+{
+    let mut edges = edges.into_iter().map(V::to_u64);
+
+    let mut prev_edge = match edges.next() {
+        Some(edge) => edge,
+        None => return,
+    };
+
+    encoder::first_edge(bytes, source.to_u64(), prev_edge);
+
+    let mut writer = BitWriter::new(bytes);
+    for edge in edges {
+        let diff = edge - prev_edge;
+        prev_edge = edge;
+        match code {
+            Code::Gamma => writer.gamma(diff),
+            Code::Delta => writer.delta(diff),
+            Code::Zeta(k) => writer.zeta(diff, k as u32),
+            Code::Rice(k) => writer.rice(diff, k as u32),
+        }
+    }
+    writer.finish();
+}
+
+pub struct BitReader<'a>
+{
+    bytes: slice::Iter<'a, u8>,
+    // The next `avail` bits in the high bits, the rest are zero.
+    bits: u64,
+    avail: u32,
+}
+
+impl<'a> BitReader<'a>
+{
+    pub fn new(bytes: &'a [u8]) -> Self
+    {
+        Self {
+            bytes: bytes.iter(),
+            bits: 0,
+            avail: 0,
+        }
+    }
+
+    #[inline]
+    fn refill(&mut self)
+    {
+        while self.avail <= 56 {
+            match self.bytes.next() {
+                Some(&byte) => {
+                    self.bits |= (byte as u64) << (56 - self.avail);
+                    self.avail += 8;
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// Reads `n` <= 32 bits.
+    #[inline]
+    fn read(&mut self, n: u32) -> Option<u64>
+    {
+        if n == 0 {
+            return Some(0);
+        }
+        self.refill();
+        if self.avail < n {
+            return None;
+        }
+        let value = self.bits >> (64 - n);
+        self.bits <<= n;
+        self.avail -= n;
+        Some(value)
+    }
+
+    pub fn read_wide(&mut self, mut n: u32) -> Option<u128>
+    {
+        let mut value = 0u128;
+        while n > 0 {
+            let chunk = n.min(32);
+            value = value << chunk | self.read(chunk)? as u128;
+            n -= chunk;
+        }
+        Some(value)
+    }
+
+    /// The number of zeros before the next one, `None` at the end.
+    #[inline]
+    fn unary(&mut self) -> Option<u32>
+    {
+        let mut zeros = 0;
+        loop {
+            self.refill();
+            if self.avail == 0 {
+                return None;
+            }
+            let lz = self.bits.leading_zeros();
+            if lz < self.avail {
+                self.bits = self.bits.checked_shl(lz + 1).unwrap_or(0);
+                self.avail -= lz + 1;
+                return Some(zeros + lz);
+            }
+            zeros += self.avail;
+            self.bits = 0;
+            self.avail = 0;
+        }
+    }
+
+    #[inline]
+    fn gamma(&mut self) -> Option<u64>
+    {
+        let n = self.unary()?;
+        Some(1 << n | self.read_wide(n)? as u64)
+    }
+
+    fn delta(&mut self) -> Option<u64>
+    {
+        let n = self.gamma()? as u32 - 1;
+        Some(1 << n | self.read_wide(n)? as u64)
+    }
+
+    fn zeta(&mut self, k: u32) -> Option<u64>
+    {
+        let h = self.unary()?;
+        let left = 1u128 << (h * k);
+        let (s, m) = minimal_binary((left << k) - left);
+        let mut v = self.read_wide(s)?;
+        if v >= m {
+            v = (v << 1 | self.read_wide(1)?) - m;
+        }
+        Some((left + v) as u64)
+    }
+
+    fn rice(&mut self, k: u32) -> Option<u64>
+    {
+        let q = self.unary()?;
+        if q == RICE_ESCAPE {
+            return self.gamma();
+        }
+        Some(((q as u64) << k | self.read_wide(k)? as u64) + 1)
+    }
+
+    /// The number of bits not read yet.
+    pub fn unread_bits(&self) -> usize
+    {
+        8 * self.bytes.len() + self.avail as usize
+    }
+
+    /// γ after its bit length `n` in unary, failing rather than
+    /// overflowing.
+    fn try_gamma(&mut self, n: u32) -> Result<u64, &'static str>
+    {
+        if n >= 64 {
+            return Err(OVERFLOW);
+        }
+        Ok(1 << n | self.read_wide(n).ok_or(TRUNCATED)? as u64)
+    }
+
+    /// A difference after the unary number `n` that every code starts
+    /// with, failing rather than overflowing.
+    fn try_code(&mut self, code: Code, n: u32) -> Result<u64, &'static str>
+    {
+        match code {
+            Code::Gamma => self.try_gamma(n),
+            Code::Delta => {
+                let n = self.try_gamma(n)? - 1;
+                self.try_gamma(n.min(64) as u32)
+            }
+            Code::Zeta(k) => {
+                let k = k as u32;
+                if n as u64 * k as u64 >= 64 {
+                    return Err(OVERFLOW);
+                }
+                let left = 1u128 << (n * k);
+                let (s, m) = minimal_binary((left << k) - left);
+                let mut v = self.read_wide(s).ok_or(TRUNCATED)?;
+                if v >= m {
+                    v = (v << 1 | self.read_wide(1).ok_or(TRUNCATED)?) - m;
+                }
+                u64::try_from(left + v).map_err(|_| OVERFLOW)
+            }
+            Code::Rice(k) => {
+                if n == RICE_ESCAPE {
+                    let n = self.unary().ok_or(TRUNCATED)?;
+                    return self.try_gamma(n);
+                }
+                if n > RICE_ESCAPE {
+                    return Err(OVERFLOW);
+                }
+                let q = (n as u128) << k | self.read_wide(k as u32).ok_or(TRUNCATED)?;
+                u64::try_from(q + 1).map_err(|_| OVERFLOW)
+            }
+        }
+    }
+}
+
+pub struct Iter<'a, V>
+{
+    first: Option<V>,
+    prev_edge: u64,
+    reader: BitReader<'a>,
+    code: Code,
+}
+
+impl<'a, V: VertexId> Iterator for Iter<'a, V>
+{
+    type Item = V;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item>
+    {
+        if let Some(first) = self.first.take() {
+            return Some(first);
+        }
+        self.prev_edge += match self.code {
+            Code::Gamma => self.reader.gamma(),
+            Code::Delta => self.reader.delta(),
+            Code::Zeta(k) => self.reader.zeta(k as u32),
+            Code::Rice(k) => self.reader.rice(k as u32),
+        }?;
+        Some(V::from_u64(self.prev_edge))
+    }
+}
+
+pub fn decode<V: VertexId>(source: V, bytes: &[u8], code: Code) -> Iter<'_, V>
+{
+    let mut bytes = bytes.iter();
+    let first = decoder::first_edge(source.to_u64(), &mut bytes);
+    Iter {
+        first: first.map(V::from_u64),
+        prev_edge: first.unwrap_or(0),
+        reader: BitReader::new(bytes.as_slice()),
+        code,
+    }
+}
+
+pub fn decode_into<V: VertexId>(source: V, bytes: &[u8], out: &mut Vec<V>, code: Code)
+{
+    out.extend(decode(source, bytes, code));
+}
+
+/// Like `decode_into` but fails rather than overflows, see `checked`.
+pub fn try_decode_into<V: VertexId>(source: V, mut bytes: Cursor<'_>, sink: &mut Sink<'_, V>, code: Code) -> Checked<()>
+{
+    let offset = bytes.offset();
+    let mut prev_edge = bytes.first_edge(source.to_u64())?;
+    sink.push(prev_edge, offset)?;
+
+    let rest = bytes.rest();
+    let mut reader = BitReader::new(rest);
+    loop {
+        let offset = bytes.offset() + (8 * rest.len() - reader.unread_bits()) / 8;
+        // The padding has no one.
+        let n = match reader.unary() {
+            Some(n) => n,
+            None => return Ok(()),
+        };
+        let diff = reader.try_code(code, n).map_err(|reason| (offset, reason))?;
+        prev_edge = checked::add(prev_edge, diff, offset)?;
+        sink.push(prev_edge, offset)?;
+    }
+}
@@ -0,0 +1,568 @@
+// glzip is a graph compression library for graph learning systems
+// Copyright (C) 2022 Jacob Konrad
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Options for how a [`CSR`] is encoded, and a builder that encodes edges
+//! that are already sorted as they come.
+
+use std::marker::PhantomData;
+
+use crate::{
+    checked::{Cursor, Sink},
+    codec::{Codec, Coder},
+    error::{DecodeError, EncodeError, Error},
+    vertex::VertexId,
+    Edge,
+    CSR,
+};
+
+/// Builds a [`CSR`] with non-default encoding options, see [`CSR::builder`].
+///
+/// # Examples
+///
+/// ```
+/// use glzip::{CSR, Edge};
+///
+/// let csr = CSR::builder()
+///     .block_len(100)
+///     .build((0..1000u32).map(|v| Edge(0, v)));
+///
+/// assert_eq!(1000, csr.degree(0));
+/// assert_eq!(Some(250), csr.neighbor_at(0, 250));
+/// ```
+#[derive(Clone)]
+pub struct CsrBuilder<V: VertexId = u32>
+{
+    pub(crate) codec: Codec,
+    pub(crate) block_len: Option<usize>,
+    // The window and the longest reference chain.
+    pub(crate) references: Option<(usize, usize)>,
+    // Whether every vertex is decoded and compared once it's encoded.
+    pub(crate) verified: bool,
+    marker: PhantomData<V>,
+}
+
+impl<V: VertexId> Default for CsrBuilder<V>
+{
+    fn default() -> Self
+    {
+        Self {
+            codec: Codec::default(),
+            block_len: None,
+            references: None,
+            verified: false,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<V: VertexId> CsrBuilder<V>
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// Fails if an option is out of range or two can't be combined.
+    pub(crate) fn check(&self) -> Result<(), Error>
+    {
+        if !self.codec.is_valid() {
+            return Err(Error::InvalidOptions("the parameter of the codec is out of range"));
+        }
+        if self.block_len == Some(0) {
+            return Err(Error::InvalidOptions("block_len must be positive"));
+        }
+        if self.references.is_some() && self.block_len.is_some() {
+            return Err(Error::InvalidOptions("block_len can't be combined with references"));
+        }
+        Ok(())
+    }
+
+    /// Appends the bytes of `source` with the sorted, deduplicated `targets`
+    /// to `edges`. If `verified` they are decoded into `decoded` and
+    /// compared with `targets`, nothing is appended if they differ.
+    pub(crate) fn encode_vertex(
+        &self,
+        coder: Coder<'_>,
+        edges: &mut Vec<u8>,
+        source: V,
+        targets: &[V],
+        decoded: &mut Vec<V>,
+    ) -> Result<(), EncodeError>
+    {
+        let start = edges.len();
+        match self.block_len {
+            Some(block_len) if targets.len() > block_len => coder.encode_blocked(edges, source, targets, block_len),
+            _ => coder.encode(edges, source, targets.iter().copied()),
+        }
+        if !self.verified {
+            return Ok(());
+        }
+
+        decoded.clear();
+        let result = coder.try_decode_into(source, Cursor::new(&edges[start..], 0), &mut Sink::new(decoded, u64::MAX));
+        let decode_error = result.err().map(|err| DecodeError::new(source.to_usize(), err));
+        let result = verify(source, targets, decoded, decode_error);
+        if result.is_err() {
+            edges.truncate(start);
+        }
+        result
+    }
+
+    /// The encoding of the neighbors, [`Codec::ByteRle`] by default.
+    ///
+    /// Building fails with [`Error::InvalidOptions`] if the parameter of
+    /// [`Codec::Zeta`] or [`Codec::Rice`] is out of range.
+    pub fn codec(mut self, codec: Codec) -> Self
+    {
+        self.codec = codec;
+        self
+    }
+
+    /// Splits the neighbors of vertices with more than `block_len` neighbors
+    /// into blocks of `block_len` that restart their deltas from the source
+    /// as in Ligra+. The blocks cost a few bytes each but can be decoded
+    /// independently, see [`CSR::par_neighbors`].
+    ///
+    /// Building fails with [`Error::InvalidOptions`] if `block_len` is zero.
+    pub fn block_len(mut self, block_len: usize) -> Self
+    {
+        self.block_len = Some(block_len);
+        self
+    }
+
+    /// Encodes the neighbors of a vertex as a copy of some of the neighbors
+    /// of one of the `window` vertices before it, intervals of consecutive
+    /// ids and the remaining neighbors, as in WebGraph. Suits web and social
+    /// graphs ordered so that similar vertices are close, e.g. with
+    /// [`reorder`](crate::reorder).
+    ///
+    /// Decoding a vertex decodes its reference, whose reference is decoded
+    /// too, for at most `max_chain` references, so a small `max_chain` keeps
+    /// lookups fast. The longest chain is stored with the graph and bounds
+    /// decoding. The skip index is not used, so [`CSR::neighbor_at`] and
+    /// [`CSR::has_edge`] decode the whole vertex.
+    ///
+    /// Can't be combined with [`block_len`](Self::block_len), building fails
+    /// with [`Error::InvalidOptions`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glzip::{CSR, Edge};
+    ///
+    /// // Every vertex links to the same 100 pages and a range of its own.
+    /// let edges: Vec<Edge> = (0..100u32)
+    ///     .flat_map(|u| (0..100).map(|v| 7 * v).chain(1000 + 10 * u..1010 + 10 * u).map(move |v| Edge(u, v)))
+    ///     .collect();
+    ///
+    /// let plain = CSR::from_edges(edges.clone());
+    /// let csr = CSR::builder().references(7, 3).build(edges);
+    ///
+    /// assert!(2 * csr.nbytes() < plain.nbytes());
+    /// assert_eq!(plain.neighbors(42).collect::<Vec<_>>(), csr.neighbors(42).collect::<Vec<_>>());
+    /// assert_eq!(Some(1420), csr.neighbor_at(42, 100));
+    /// ```
+    pub fn references(mut self, window: usize, max_chain: usize) -> Self
+    {
+        self.references = Some((window, max_chain));
+        self
+    }
+
+    /// Panics if the options are invalid or the graph doesn't fit into
+    /// memory, see [`try_build`](Self::try_build).
+    pub fn build<I>(&self, iter: I) -> CSR<V>
+    where
+        I: IntoIterator<Item = Edge<V>>,
+    {
+        let graph: Result<CSR<V>, Error> = self.try_build(iter.into_iter().map(Ok));
+
+        graph.unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Builds a graph from edges that may fail, the first error of an edge
+    /// is returned as [`Error::Source`]. Invalid options fail with
+    /// [`Error::InvalidOptions`].
+    pub fn try_build<E, I>(&self, iter: I) -> Result<CSR<V>, Error<E>>
+    where
+        I: IntoIterator<Item = Result<Edge<V>, E>>,
+    {
+        let mut buf = Vec::new();
+        for edge in iter {
+            buf.try_reserve(1)?;
+            buf.push(edge.map_err(Error::Source)?);
+        }
+        CSR::from_buffer_with(&mut buf[..], self).map_err(Error::widen)
+    }
+
+    /// Like [`build`](Self::build) but decodes every vertex once it's
+    /// encoded and compares it with its sorted, deduplicated neighbors, at
+    /// about the cost of decoding the graph. Fails with the first neighbor
+    /// that differs with [`Error::Encode`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glzip::{codec::Codec, CSR, Edge};
+    ///
+    /// let csr = CSR::builder()
+    ///     .codec(Codec::Nibble)
+    ///     .build_verified((0..1000u32).map(|v| Edge(v % 7, v)))
+    ///     .unwrap();
+    ///
+    /// assert_eq!(143, csr.degree(0));
+    /// ```
+    pub fn build_verified<I>(&self, iter: I) -> Result<CSR<V>, Error>
+    where
+        I: IntoIterator<Item = Edge<V>>,
+    {
+        let options = Self { verified: true, ..self.clone() };
+
+        options.try_build(iter.into_iter().map(Ok))
+    }
+
+    /// A builder for edges that are already sorted, e.g. an adjacency list
+    /// file, that encodes the neighbors of each vertex once they are all
+    /// pushed rather than collecting the edges first, see [`StreamBuilder`].
+    ///
+    /// Fails with [`Error::InvalidOptions`] if the options are invalid, the
+    /// codec is [`Codec::Rans`], whose model needs all the neighbors, or
+    /// combined with [`references`](Self::references).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glzip::{CSR, Edge};
+    ///
+    /// let mut builder = CSR::builder().block_len(2).streaming().unwrap();
+    /// builder.push_vertex(0, [1, 2, 3]).unwrap();
+    /// builder.push_edge(Edge(2, 0)).unwrap();
+    /// builder.push_edge(Edge(2, 1)).unwrap();
+    /// builder.push_vertex(5, []).unwrap();
+    ///
+    /// let csr: CSR = builder.finish().unwrap();
+    ///
+    /// assert_eq!(6, csr.order());
+    /// assert_eq!(vec![1, 2, 3], csr.neighbors(0).collect::<Vec<_>>());
+    /// assert_eq!(vec![0, 1], csr.neighbors(2).collect::<Vec<_>>());
+    /// ```
+    pub fn streaming(&self) -> Result<StreamBuilder<V>, Error>
+    {
+        self.check()?;
+        if self.codec == Codec::Rans {
+            return Err(Error::InvalidOptions("Codec::Rans can't be streamed"));
+        }
+        if self.references.is_some() {
+            return Err(Error::InvalidOptions("references can't be streamed"));
+        }
+        Ok(StreamBuilder {
+            options: self.clone(),
+            source: None,
+            targets: vec![],
+            decoded: vec![],
+            max: None,
+            num_edges: 0,
+            nodes_and_nnzs: vec![],
+            edges: vec![],
+        })
+    }
+
+    /// Like [`streaming`](Self::streaming) but decodes every vertex once
+    /// it's encoded and compares it with its neighbors, see
+    /// [`build_verified`](Self::build_verified). Pushing a new source fails
+    /// with [`Error::Encode`] if the last one differs.
+    pub fn streaming_verified(&self) -> Result<StreamBuilder<V>, Error>
+    {
+        Self { verified: true, ..self.clone() }.streaming()
+    }
+}
+
+/// Builds a [`CSR`] from edges pushed in lexicographic order, see
+/// [`CsrBuilder::streaming`]. Only the neighbors of the last source are held
+/// uncompressed, they are encoded as soon as another source is pushed.
+/// Parallel edges are merged.
+pub struct StreamBuilder<V: VertexId = u32>
+{
+    options: CsrBuilder<V>,
+    // The last source and its neighbors so far, empty once they are encoded.
+    source: Option<V>,
+    targets: Vec<V>,
+    decoded: Vec<V>,
+    max: Option<V>,
+    num_edges: usize,
+    // The encoded vertices with their number of bytes and degree, as in
+    // `CSR::from_encoded`.
+    nodes_and_nnzs: Vec<(V, usize, usize)>,
+    edges: Vec<u8>,
+}
+
+impl<V: VertexId> StreamBuilder<V>
+{
+    /// Pushes neighbors of `source`, which can't be smaller than the last
+    /// source. If it is the last source the neighbors can't be smaller than
+    /// its last neighbor. A source without neighbors still counts towards
+    /// the order of the graph. Nothing is pushed if it fails.
+    pub fn push_vertex<I>(&mut self, source: V, neighbors: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = V>,
+    {
+        let same = match self.source {
+            Some(u) if source < u => {
+                return Err(Error::Unsorted { source: source.to_u64(), target: None });
+            }
+            Some(u) => source == u,
+            None => false,
+        };
+
+        if !same {
+            self.encode()?;
+        }
+        let len = self.targets.len();
+        let mut max = self.max.max(Some(source));
+        if let Err(err) = push_sorted(&mut self.targets, source, neighbors, &mut max) {
+            self.targets.truncate(len);
+            if !same {
+                self.reopen();
+            }
+            return Err(err);
+        }
+
+        self.source = Some(source);
+        self.max = max;
+        Ok(())
+    }
+
+    /// Pushes an edge, which can't be smaller than the last edge.
+    pub fn push_edge(&mut self, Edge(u, v): Edge<V>) -> Result<(), Error>
+    {
+        self.push_vertex(u, [v])
+    }
+
+    /// Encodes the neighbors of the last source, they are kept if it fails.
+    fn encode(&mut self) -> Result<(), EncodeError>
+    {
+        let u = match self.source {
+            Some(u) if !self.targets.is_empty() => u,
+            _ => return Ok(()),
+        };
+        let coder = Coder { codec: self.options.codec, model: &[] };
+        let start = self.edges.len();
+        self.options
+            .encode_vertex(coder, &mut self.edges, u, &self.targets, &mut self.decoded)?;
+        let end = self.edges.len();
+        self.num_edges += self.targets.len();
+        self.nodes_and_nnzs.push((u, end - start, self.targets.len()));
+        self.targets.clear();
+        Ok(())
+    }
+
+    /// Decodes the neighbors of the last source back after a new source
+    /// failed, so that more of them can still be pushed.
+    fn reopen(&mut self)
+    {
+        let u = match self.source {
+            Some(u) => u,
+            None => return,
+        };
+        match self.nodes_and_nnzs.last() {
+            Some(&(v, nnz, degree)) if v == u => {
+                self.nodes_and_nnzs.pop();
+                let start = self.edges.len() - nnz;
+                let coder = Coder { codec: self.options.codec, model: &[] };
+                coder.decode_into(u, &self.edges[start..], &mut self.targets);
+                self.edges.truncate(start);
+                self.num_edges -= degree;
+            }
+            _ => {}
+        }
+    }
+
+    /// Encodes the neighbors of the last source and builds the graph.
+    pub fn finish(mut self) -> Result<CSR<V>, Error>
+    {
+        self.encode()?;
+        match self.max {
+            Some(max) => CSR::from_encoded(
+                CSR::max_vertex(max)?,
+                self.num_edges,
+                self.nodes_and_nnzs,
+                &[],
+                self.edges,
+                &self.options,
+            ),
+            None => CSR::from_buffer_with(&mut [], &self.options),
+        }
+    }
+}
+
+/// Appends `neighbors` to the sorted `targets` of `source`, merging
+/// duplicates and raising `max` to the largest.
+fn push_sorted<V, I>(targets: &mut Vec<V>, source: V, neighbors: I, max: &mut Option<V>) -> Result<(), Error>
+where
+    V: VertexId,
+    I: IntoIterator<Item = V>,
+{
+    for v in neighbors {
+        match targets.last() {
+            Some(&w) if v < w => {
+                return Err(Error::Unsorted { source: source.to_u64(), target: Some(v.to_u64()) });
+            }
+            Some(&w) if v == w => continue,
+            _ => {}
+        }
+        targets.try_reserve(1)?;
+        targets.push(v);
+        *max = (*max).max(Some(v));
+    }
+    Ok(())
+}
+
+/// Fails with the first neighbor where `decoded` differs from `expected`,
+/// the neighbors `source` was encoded from.
+pub(crate) fn verify<V: VertexId>(
+    source: V,
+    expected: &[V],
+    decoded: &[V],
+    decode_error: Option<DecodeError>,
+) -> Result<(), EncodeError>
+{
+    if expected == decoded && decode_error.is_none() {
+        return Ok(());
+    }
+    let index = expected
+        .iter()
+        .zip(decoded)
+        .position(|(v, w)| v != w)
+        .unwrap_or_else(|| expected.len().min(decoded.len()));
+    Err(EncodeError {
+        vertex: source.to_u64(),
+        index,
+        expected: expected.get(index).map(|v| v.to_u64()),
+        decoded: decoded.get(index).map(|v| v.to_u64()),
+        decode_error,
+    })
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::convert::Infallible;
+
+    use super::*;
+
+    #[test]
+    fn unsorted_sources_fail()
+    {
+        let mut builder = CSR::<u32>::builder().streaming().unwrap();
+        builder.push_vertex(0, [1, 2]).unwrap();
+        builder.push_vertex(2, [5]).unwrap();
+
+        assert!(matches!(builder.push_vertex(1, [9]), Err(Error::Unsorted { source: 1, target: None })));
+        assert!(matches!(builder.push_edge(Edge(0, 3)), Err(Error::Unsorted { source: 0, target: None })));
+
+        let csr = builder.finish().unwrap();
+        assert_eq!(6, csr.order());
+        assert_eq!(3, csr.size());
+        assert_eq!(vec![1, 2], csr.neighbors(0).collect::<Vec<_>>());
+        assert_eq!(vec![5], csr.neighbors(2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn unsorted_targets_fail()
+    {
+        let mut builder = CSR::<u32>::builder().block_len(2).streaming().unwrap();
+        builder.push_vertex(2, [5]).unwrap();
+
+        // Neither the targets before the unsorted one nor the largest of
+        // them are kept.
+        let err = builder.push_vertex(2, [6, 9, 7]);
+        assert!(matches!(err, Err(Error::Unsorted { source: 2, target: Some(7) })));
+        let err = builder.push_edge(Edge(2, 4));
+        assert!(matches!(err, Err(Error::Unsorted { source: 2, target: Some(4) })));
+
+        // A new source with unsorted targets doesn't replace the last one.
+        let err = builder.push_vertex(8, [3, 1]);
+        assert!(matches!(err, Err(Error::Unsorted { source: 8, target: Some(1) })));
+        builder.push_vertex(2, [6]).unwrap();
+        builder.push_vertex(3, [0, 1, 2]).unwrap();
+
+        let csr = builder.finish().unwrap();
+        assert_eq!(7, csr.order());
+        assert_eq!(5, csr.size());
+        assert_eq!(vec![5, 6], csr.neighbors(2).collect::<Vec<_>>());
+        assert_eq!(vec![0, 1, 2], csr.neighbors(3).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn streaming_verified_matches_build()
+    {
+        let edges: Vec<Edge> = (0..2000u32).map(|v| Edge(v % 7, v / 3)).collect();
+        for codec in [Codec::ByteRle, Codec::Nibble, Codec::StreamVByte, Codec::Gamma] {
+            let options = CSR::builder().codec(codec).block_len(50);
+            let csr = options.build_verified(edges.clone()).unwrap();
+
+            let mut sorted = edges.clone();
+            sorted.sort();
+            let mut builder = options.streaming_verified().unwrap();
+            for &edge in &sorted {
+                builder.push_edge(edge).unwrap();
+            }
+            let streamed = builder.finish().unwrap();
+            assert_eq!(csr.edges().collect::<Vec<_>>(), streamed.edges().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn verify_reports_the_first_difference()
+    {
+        assert_eq!(Ok(()), verify(3u32, &[1, 2], &[1, 2], None));
+
+        let err = verify(3u32, &[1, 2, 4], &[1, 2, 5], None).unwrap_err();
+        assert_eq!((3, 2, Some(4), Some(5)), (err.vertex, err.index, err.expected, err.decoded));
+
+        // A neighbor lost at the end.
+        let err = verify(3u32, &[1, 2], &[1], None).unwrap_err();
+        assert_eq!((1, Some(2), None), (err.index, err.expected, err.decoded));
+    }
+
+    #[test]
+    fn invalid_options_fail()
+    {
+        let builders = [
+            CSR::<u32>::builder().codec(Codec::Zeta(0)),
+            CSR::builder().codec(Codec::Zeta(17)),
+            CSR::builder().codec(Codec::Rice(64)),
+            CSR::builder().block_len(0),
+            CSR::builder().references(3, 3).block_len(10),
+        ];
+        for builder in builders {
+            let edges = || (0..100u32).map(|v| Edge(v % 3, v));
+            assert!(matches!(builder.try_build::<Infallible, _>(edges().map(Ok)), Err(Error::InvalidOptions(_))));
+            assert!(matches!(builder.build_verified(edges()), Err(Error::InvalidOptions(_))));
+            assert!(matches!(builder.build_verified([]), Err(Error::InvalidOptions(_))));
+            assert!(matches!(builder.streaming(), Err(Error::InvalidOptions(_))));
+            assert!(matches!(builder.streaming_verified(), Err(Error::InvalidOptions(_))));
+        }
+    }
+
+    #[test]
+    fn options_that_cant_be_streamed_fail()
+    {
+        let builder = CSR::<u32>::builder().codec(Codec::Rans);
+        assert!(matches!(builder.streaming(), Err(Error::InvalidOptions(_))));
+        let builder = CSR::<u32>::builder().references(3, 3);
+        assert!(matches!(builder.streaming(), Err(Error::InvalidOptions(_))));
+    }
+}
@@ -0,0 +1,383 @@
+// glzip is a graph compression library for graph learning systems
+// Copyright (C) 2022 Jacob Konrad
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Checked decoding for buffers that may be truncated or corrupted, such as
+// files loaded from disk. The decoders of the codecs trust their bytes, the
+// byte-RLE decoder skips bounds checks, so decoding a bad buffer is
+// undefined behaviour. Every codec also has a `try_decode_into` that
+// follows its layout through a Cursor, which checks every length and
+// varint, and pushes the neighbors into a Sink, which checks that they are
+// increasing and vertices of the graph. The bytes they accept are safe for
+// the unchecked decoders, which decode them to the same neighbors.
+
+use rayon::prelude::*;
+
+use crate::{codec::Coder, error::DecodeError, reference, vertex::VertexId};
+
+/// The offset of the offending byte and what is wrong with it.
+pub type Checked<T> = Result<T, (usize, &'static str)>;
+
+pub const TRUNCATED: &str = "the bytes end early";
+pub const OVERFLOW: &str = "a difference overflows";
+pub const OUT_OF_RANGE: &str = "a neighbor is not a vertex";
+pub const NOT_INCREASING: &str = "the neighbors are not increasing";
+pub const TRAILING: &str = "bytes after the last neighbor";
+
+/// The bytes of a block with the offset of the first in the edges buffer.
+#[derive(Clone, Copy)]
+pub struct Cursor<'a>
+{
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Cursor<'a>
+{
+    pub fn new(bytes: &'a [u8], offset: usize) -> Self
+    {
+        Self { bytes, offset }
+    }
+
+    #[inline]
+    pub fn offset(&self) -> usize
+    {
+        self.offset
+    }
+
+    #[inline]
+    pub fn rest(&self) -> &'a [u8]
+    {
+        self.bytes
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool
+    {
+        self.bytes.is_empty()
+    }
+
+    pub fn fail<T>(&self, reason: &'static str) -> Checked<T>
+    {
+        Err((self.offset, reason))
+    }
+
+    /// Fails unless every byte was read.
+    pub fn finish(&self) -> Checked<()>
+    {
+        if self.is_empty() {
+            Ok(())
+        }
+        else {
+            self.fail(TRAILING)
+        }
+    }
+
+    #[inline]
+    pub fn byte(&mut self) -> Checked<u8>
+    {
+        let (&byte, rest) = self.bytes.split_first().ok_or((self.offset, TRUNCATED))?;
+        self.bytes = rest;
+        self.offset += 1;
+        Ok(byte)
+    }
+
+    /// The next `n` bytes.
+    pub fn take(&mut self, n: u64) -> Checked<Cursor<'a>>
+    {
+        if n > self.bytes.len() as u64 {
+            return self.fail(TRUNCATED);
+        }
+        let (bytes, rest) = self.bytes.split_at(n as usize);
+        let taken = Cursor::new(bytes, self.offset);
+        self.bytes = rest;
+        self.offset += bytes.len();
+        Ok(taken)
+    }
+
+    /// Reads the 7 bit chunks of a varint after the `shift` bits of `x`.
+    fn chunks(&mut self, mut x: u64, mut shift: u32, start: usize) -> Checked<u64>
+    {
+        loop {
+            let byte = self.byte()?;
+            let chunk = (byte & 0x7f) as u64;
+            if shift >= 64 || chunk.leading_zeros() < shift {
+                return Err((start, "a varint overflows 64 bits"));
+            }
+            x |= chunk << shift;
+            if byte & 0x80 == 0 {
+                return Ok(x);
+            }
+            shift += 7;
+        }
+    }
+
+    /// A varint as in `byte_code::read_varint`.
+    pub fn varint(&mut self) -> Checked<u64>
+    {
+        let start = self.offset;
+        self.chunks(0, 0, start)
+    }
+
+    /// The first neighbor as in `decoder::first_edge`.
+    pub fn first_edge(&mut self, source: u64) -> Checked<u64>
+    {
+        let start = self.offset;
+        let first_byte = self.byte()?;
+        let mut diff = (first_byte & 0x3f) as u64;
+        if first_byte & 0x80 > 0 {
+            diff = self.chunks(diff, 6, start)?;
+        }
+        let edge = if first_byte & 0x40 > 0 {
+            source.checked_sub(diff)
+        }
+        else {
+            source.checked_add(diff)
+        };
+        edge.ok_or((start, OUT_OF_RANGE))
+    }
+}
+
+/// `prev_edge + diff` for the difference at `offset`.
+#[inline]
+pub fn add(prev_edge: u64, diff: u64, offset: usize) -> Checked<u64>
+{
+    prev_edge.checked_add(diff).ok_or((offset, OVERFLOW))
+}
+
+/// Appends neighbors that are increasing and smaller than `order`.
+pub struct Sink<'a, V>
+{
+    out: &'a mut Vec<V>,
+    order: u64,
+    prev_edge: Option<V>,
+    len: usize,
+}
+
+impl<'a, V: VertexId> Sink<'a, V>
+{
+    pub fn new(out: &'a mut Vec<V>, order: u64) -> Self
+    {
+        Self {
+            out,
+            order,
+            prev_edge: None,
+            len: 0,
+        }
+    }
+
+    /// The number of neighbors pushed.
+    pub fn len(&self) -> usize
+    {
+        self.len
+    }
+
+    /// Appends the neighbor decoded from the bytes at `offset`.
+    #[inline]
+    pub fn push(&mut self, edge: u64, offset: usize) -> Checked<()>
+    {
+        let edge = V::try_from_u64(edge)
+            .filter(|_| edge < self.order)
+            .ok_or((offset, OUT_OF_RANGE))?;
+        if self.prev_edge >= Some(edge) {
+            return Err((offset, NOT_INCREASING));
+        }
+        self.prev_edge = Some(edge);
+        self.out.push(edge);
+        self.len += 1;
+        Ok(())
+    }
+}
+
+/// Appends the neighbors of `source` to `out`, checking every byte. The
+/// bytes of vertex `u` are `bytes(u)`, which fails if its offsets are out of
+/// bounds. `max_chain` is the longest chain of references if the vertices
+/// are encoded against references. Nothing is appended if it fails.
+pub fn decode_into<'a, V: VertexId>(
+    coder: Coder<'_>,
+    max_chain: Option<usize>,
+    order: usize,
+    source: V,
+    bytes: &dyn Fn(usize) -> Checked<Cursor<'a>>,
+    out: &mut Vec<V>,
+) -> Result<(), DecodeError>
+{
+    let len = out.len();
+    let result = if let Some(max_chain) = max_chain {
+        reference::try_decode_into(coder, order as u64, max_chain, source, bytes, out)
+    }
+    else {
+        let u = source.to_usize();
+        bytes(u)
+            .and_then(|bytes| coder.try_decode_into(source, bytes, &mut Sink::new(out, order as u64)))
+            .map_err(|err| DecodeError::new(u, err))
+    };
+    if result.is_err() {
+        out.truncate(len);
+    }
+    result
+}
+
+/// The degrees of the vertices, each decoded with `decode`.
+pub fn degrees<V, F>(order: usize, decode: F) -> Result<Vec<usize>, DecodeError>
+where
+    V: VertexId,
+    F: Fn(usize, &mut Vec<V>) -> Result<(), DecodeError> + Sync,
+{
+    (0..order)
+        .into_par_iter()
+        .map_init(Vec::new, |buf, u| {
+            buf.clear();
+            decode(u, buf).map(|()| buf.len())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::{codec::Codec, format, rans, CsrBuilder, CsrView, Edge, CSR};
+
+    const CODECS: [Codec; 9] = [
+        Codec::ByteRle,
+        Codec::Byte,
+        Codec::Nibble,
+        Codec::StreamVByte,
+        Codec::Gamma,
+        Codec::Delta,
+        Codec::Zeta(3),
+        Codec::Rice(4),
+        Codec::Rans,
+    ];
+
+    /// A small graph with low and high degree vertices and runs of
+    /// consecutive neighbors.
+    fn edges(rng: &mut StdRng) -> Vec<Edge>
+    {
+        (0..40u32)
+            .flat_map(|u| {
+                let degree = if u % 10 == 0 { 40 } else { rng.gen_range(0..8) };
+                let start = rng.gen_range(0..30);
+                (0..degree).map(move |i| Edge(u, start + i * (1 + u % 3)))
+            })
+            .collect()
+    }
+
+    /// Every codec, each also with blocks and with references.
+    fn builders() -> Vec<CsrBuilder>
+    {
+        CODECS
+            .iter()
+            .flat_map(|&codec| {
+                [
+                    CsrBuilder::new().codec(codec),
+                    CsrBuilder::new().codec(codec).block_len(16),
+                    CsrBuilder::new().codec(codec).references(4, 2),
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn truncated_blocks_fail_or_shrink()
+    {
+        let mut rng = StdRng::seed_from_u64(22);
+        let edges = edges(&mut rng);
+        let csr = CSR::from_edges(edges);
+        for codec in CODECS {
+            for u in 0..csr.order() as u32 {
+                let neighbors: Vec<u32> = csr.neighbors(u).collect();
+                let mut histogram = rans::Histogram::default();
+                histogram.add(neighbors.iter().copied());
+                let model = histogram.model();
+                let coder = Coder { codec, model: &model };
+                let mut bytes = vec![];
+                coder.encode(&mut bytes, u, neighbors.iter().copied());
+
+                for len in 0..bytes.len() {
+                    let mut out = vec![];
+                    let result = coder.try_decode_into(u, Cursor::new(&bytes[..len], 0), &mut Sink::new(&mut out, 100));
+                    if result.is_ok() {
+                        assert!(out.len() < neighbors.len(), "{:?} {} {}", codec, u, len);
+                        let mut unchecked = vec![];
+                        coder.decode_into(u, &bytes[..len], &mut unchecked);
+                        assert_eq!(out, unchecked, "{:?} {} {}", codec, u, len);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_files_fail()
+    {
+        let mut rng = StdRng::seed_from_u64(22);
+        let edges = edges(&mut rng);
+        for builder in builders() {
+            let mut buf = Vec::new();
+            builder.build(edges.clone()).write_to(&mut buf).unwrap();
+            for len in 0..buf.len() {
+                assert!(CSR::<u32>::read_from(&buf[..len]).is_err());
+                assert!(CsrView::<u32>::new(&buf[..len]).is_err());
+            }
+        }
+    }
+
+    // A flipped bit either fails the checks or decodes to the same neighbors
+    // with and without them.
+    #[test]
+    fn flipped_bits_fail_or_decode_the_same()
+    {
+        let mut rng = StdRng::seed_from_u64(22);
+        let edges = edges(&mut rng);
+        for builder in builders() {
+            let mut buf = Vec::new();
+            builder.build(edges.clone()).write_to(&mut buf).unwrap();
+            for _ in 0..200 {
+                let mut corrupt = buf.clone();
+                corrupt[rng.gen_range(format::HEADER_LEN..buf.len())] ^= 1 << rng.gen_range(0..8);
+
+                if let Ok(csr) = CSR::<u32>::read_from(&corrupt[..]) {
+                    csr.validate().unwrap();
+                    let mut checked = vec![];
+                    for u in 0..csr.order() as u32 {
+                        checked.clear();
+                        csr.try_neighbors_into(u, &mut checked).unwrap();
+                        assert_eq!(checked, csr.neighbors(u).collect::<Vec<_>>());
+                    }
+                }
+
+                // Without the checks of `new`, every vertex either fails or
+                // decodes the same.
+                let view = match unsafe { CsrView::<u32>::new_unchecked(&corrupt[..]) } {
+                    Ok(view) => view,
+                    Err(_) => continue,
+                };
+                let valid = view.validate().is_ok();
+                let mut checked = vec![];
+                for u in 0..view.order() as u32 {
+                    checked.clear();
+                    match view.try_neighbors_into(u, &mut checked) {
+                        Ok(()) => assert_eq!(checked, view.neighbors(u).collect::<Vec<_>>()),
+                        Err(_) => assert!(!valid),
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,632 @@
+// glzip is a graph compression library for graph learning systems
+// Copyright (C) 2022 Jacob Konrad
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The encodings of the neighbors of a vertex.
+//!
+//! Every codec writes the neighbors of a vertex as a block that starts with
+//! the first neighbor's difference from the vertex, as in Ligra+, and
+//! continues with the differences between consecutive neighbors in its own
+//! format. With [`CsrBuilder::block_len`](crate::CsrBuilder::block_len), the
+//! neighbors of high degree vertices are split into several such blocks.
+
+use crate::{
+    bit_code::{self, Code},
+    byte_code,
+    checked::{Checked, Cursor, Sink},
+    decoder, encoder, format, nibble_code, rans, stream_vbyte,
+    vertex::VertexId,
+};
+
+/// The encoding of the differences between consecutive neighbors, chosen
+/// when the graph is built with [`CsrBuilder::codec`](crate::CsrBuilder::codec).
+///
+/// # Examples
+///
+/// ```
+/// use glzip::{codec::Codec, CSR, Edge};
+///
+/// let edges: Vec<Edge> = (0..1000).map(|v| Edge(0, 2 * v)).collect();
+///
+/// let rle = CSR::from_edges(edges.clone());
+/// let nibble = CSR::builder().codec(Codec::Nibble).build(edges);
+///
+/// assert_eq!(Codec::Nibble, nibble.codec());
+/// assert!(nibble.nbytes() < rle.nbytes());
+/// assert_eq!(rle.edges().collect::<Vec<_>>(), nibble.edges().collect::<Vec<_>>());
+/// ```
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum Codec
+{
+    /// Groups of up to 64 differences of the same byte width behind a one
+    /// byte header, the byte-RLE code from Ligra+.
+    #[default]
+    ByteRle,
+    /// A base 128 varint per difference, the byte code from Ligra+.
+    Byte,
+    /// A varint of 3 bit chunks per difference, packed two chunks a byte,
+    /// the nibble code from Ligra+.
+    Nibble,
+    /// A control byte with the lengths of every 4 differences, followed by
+    /// the differences, Stream VByte. The fastest to decode into a buffer,
+    /// with the `simd` feature in particular, but larger than byte-RLE.
+    StreamVByte,
+    /// Elias γ, the bit length of a difference in unary followed by its bits.
+    /// Like the other bit codes, smaller than the byte codes for most graphs
+    /// but several times slower to decode, for archiving.
+    Gamma,
+    /// Elias δ, as γ with the bit length in γ, smaller for large differences.
+    Delta,
+    /// ζ_k from WebGraph for `k` in 1..=16, which is γ for `k` = 1. Suits
+    /// the power law differences of web and social graphs, where WebGraph
+    /// uses `k` = 3.
+    Zeta(u8),
+    /// Golomb-Rice with a divisor of 2^`k` for `k` in 0..=63, best for
+    /// geometric differences with `k` close to the log2 of their mean, as in
+    /// random graphs.
+    ///
+    /// ```
+    /// use glzip::{codec::Codec, CSR, Edge};
+    ///
+    /// // The neighbors are 8 apart, so k = 3.
+    /// let csr = CSR::builder()
+    ///     .codec(Codec::Rice(3))
+    ///     .build((0..1000u32).map(|v| Edge(0, 8 * v)));
+    ///
+    /// assert_eq!(Some(800), csr.neighbor_at(0, 100));
+    /// assert!(csr.has_edge(0, 7992));
+    /// ```
+    Rice(u8),
+    /// The bit lengths of the differences entropy coded with rANS, followed
+    /// by the differences' other bits. The model of the bit lengths is built
+    /// from the graph, per degree class, and takes up to about 2 KB, see
+    /// [`CSR::model_nbytes`](crate::CSR::model_nbytes). Close to the bit
+    /// codes in size for skewed differences but byte aligned per vertex.
+    Rans,
+}
+
+impl Codec
+{
+    /// Whether the parameter of the codec is in range.
+    pub(crate) fn is_valid(self) -> bool
+    {
+        match self {
+            Codec::Zeta(k) => (1..=16).contains(&k),
+            Codec::Rice(k) => k <= 63,
+            _ => true,
+        }
+    }
+
+    /// The id of the codec in the binary format, with the parameter in the
+    /// low bits.
+    pub(crate) fn id(self) -> u8
+    {
+        match self {
+            Codec::ByteRle => format::CODEC_BYTE_RLE,
+            Codec::Byte => format::CODEC_BYTE,
+            Codec::Nibble => format::CODEC_NIBBLE,
+            Codec::StreamVByte => format::CODEC_STREAM_VBYTE,
+            Codec::Gamma => format::CODEC_GAMMA,
+            Codec::Delta => format::CODEC_DELTA,
+            Codec::Zeta(k) => format::CODEC_ZETA | k,
+            Codec::Rice(k) => format::CODEC_RICE | k,
+            Codec::Rans => format::CODEC_RANS,
+        }
+    }
+
+    pub(crate) fn from_id(id: u8) -> Option<Self>
+    {
+        let codec = match id & !format::CODEC_PARAM_MASK {
+            format::CODEC_ZETA => Codec::Zeta(id & format::CODEC_PARAM_MASK),
+            format::CODEC_RICE => Codec::Rice(id & format::CODEC_PARAM_MASK),
+            _ => return Self::from_plain_id(id),
+        };
+        Some(codec).filter(|codec| codec.is_valid())
+    }
+
+    fn from_plain_id(id: u8) -> Option<Self>
+    {
+        match id {
+            format::CODEC_BYTE_RLE => Some(Codec::ByteRle),
+            format::CODEC_BYTE => Some(Codec::Byte),
+            format::CODEC_NIBBLE => Some(Codec::Nibble),
+            format::CODEC_STREAM_VBYTE => Some(Codec::StreamVByte),
+            format::CODEC_GAMMA => Some(Codec::Gamma),
+            format::CODEC_DELTA => Some(Codec::Delta),
+            format::CODEC_RANS => Some(Codec::Rans),
+            _ => None,
+        }
+    }
+
+    fn bit_code(self) -> Code
+    {
+        match self {
+            Codec::Gamma => Code::Gamma,
+            Codec::Delta => Code::Delta,
+            Codec::Zeta(k) => Code::Zeta(k),
+            Codec::Rice(k) => Code::Rice(k),
+            _ => unreachable!("{:?} is not a bit code", self),
+        }
+    }
+}
+
+/// A codec with the model of the graph, which is empty but for
+/// [`Codec::Rans`].
+#[derive(Clone, Copy)]
+pub(crate) struct Coder<'a>
+{
+    pub codec: Codec,
+    pub model: &'a [u8],
+}
+
+impl<'a> Coder<'a>
+{
+    /// Encodes the sorted, deduplicated `edges` of `source` as a single block.
+    pub fn encode<V, I>(self, bytes: &mut Vec<u8>, source: V, edges: I)
+    where
+        V: VertexId,
+        I: IntoIterator<Item = V>,
+    {
+        match self.codec {
+            Codec::ByteRle => encoder::encode(bytes, source, edges),
+            Codec::Byte => byte_code::encode(bytes, source, edges),
+            Codec::Nibble => nibble_code::encode(bytes, source, edges),
+            Codec::StreamVByte => stream_vbyte::encode(bytes, source, edges),
+            Codec::Gamma | Codec::Delta | Codec::Zeta(_) | Codec::Rice(_) => {
+                bit_code::encode(bytes, source, edges, self.codec.bit_code())
+            }
+            Codec::Rans => rans::encode(bytes, source, edges, self.model),
+        }
+    }
+
+    /// Encodes the sorted, deduplicated `edges` in blocks of `block_len`, each
+    /// restarting its differences from `source` as in Ligra+, so that the
+    /// blocks can be decoded independently. The layout is
+    ///
+    ///   BLOCKED, the degree and `block_len` as u64, the offsets of the blocks
+    ///   after the first relative to the start as u64, the blocks.
+    pub fn encode_blocked<V: VertexId>(self, bytes: &mut Vec<u8>, source: V, edges: &[V], block_len: usize)
+    {
+        let start = bytes.len();
+        let num_blocks = edges.len().div_ceil(block_len);
+
+        bytes.push(BLOCKED);
+        bytes.extend_from_slice(&(edges.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&(block_len as u64).to_le_bytes());
+        let table = bytes.len();
+        bytes.resize(table + 8 * (num_blocks - 1), 0);
+
+        for (i, block) in edges.chunks(block_len).enumerate() {
+            if i > 0 {
+                let offset = ((bytes.len() - start) as u64).to_le_bytes();
+                bytes[table + 8 * (i - 1)..table + 8 * i].copy_from_slice(&offset);
+            }
+            self.encode(bytes, source, block.iter().copied());
+        }
+    }
+
+    /// The neighbors of a single block.
+    pub fn decode_block<V: VertexId>(self, source: V, bytes: &'a [u8]) -> Block<'a, V>
+    {
+        match self.codec {
+            Codec::ByteRle => Block::ByteRle(decoder::decode(source, bytes)),
+            Codec::Byte => Block::Byte(byte_code::decode(source, bytes)),
+            Codec::Nibble => Block::Nibble(nibble_code::decode(source, bytes)),
+            Codec::StreamVByte => Block::StreamVByte(stream_vbyte::decode(source, bytes)),
+            Codec::Gamma | Codec::Delta | Codec::Zeta(_) | Codec::Rice(_) => {
+                Block::Bits(bit_code::decode(source, bytes, self.codec.bit_code()))
+            }
+            Codec::Rans => Block::Rans(rans::decode(source, bytes, self.model)),
+        }
+    }
+
+    /// The neighbors of a vertex.
+    pub fn decode<V: VertexId>(self, source: V, bytes: &'a [u8]) -> Decode<'a, V>
+    {
+        Decode {
+            coder: self,
+            source,
+            blocks: Blocks::new(bytes),
+            next_block: 0,
+            block: None,
+        }
+    }
+
+    /// Appends the neighbors of a vertex to `out`.
+    pub fn decode_into<V: VertexId>(self, source: V, bytes: &[u8], out: &mut Vec<V>)
+    {
+        let blocks = Blocks::new(bytes);
+        for i in 0..blocks.len() {
+            let block = blocks.get(i);
+            match self.codec {
+                Codec::ByteRle => decoder::decode_into(source, block, out),
+                Codec::Byte => byte_code::decode_into(source, block, out),
+                Codec::Nibble => nibble_code::decode_into(source, block, out),
+                Codec::StreamVByte => stream_vbyte::decode_into(source, block, out),
+                Codec::Gamma | Codec::Delta | Codec::Zeta(_) | Codec::Rice(_) => {
+                    bit_code::decode_into(source, block, out, self.codec.bit_code())
+                }
+                Codec::Rans => rans::decode_into(source, block, out, self.model),
+            }
+        }
+    }
+
+    /// Like `decode_into` but checks every byte, see `checked`.
+    pub fn try_decode_into<V: VertexId>(self, source: V, mut bytes: Cursor<'_>, sink: &mut Sink<'_, V>) -> Checked<()>
+    {
+        if bytes.rest().first() != Some(&BLOCKED) {
+            return self.try_decode_block(source, bytes, sink);
+        }
+
+        let start = bytes.offset();
+        let end = start + bytes.rest().len();
+        let header = bytes.take(BLOCKED_HEADER_LEN as u64)?.rest();
+        let (degree, block_len) = (read_u64(&header[1..]) as u64, read_u64(&header[9..]) as u64);
+        if degree == 0 || block_len == 0 {
+            return Err((start, "the block header is invalid"));
+        }
+        let num_blocks = degree.div_ceil(block_len);
+        let table = bytes.take((num_blocks - 1).saturating_mul(8))?.rest();
+
+        for i in 0..num_blocks as usize {
+            let block_end = match table.get(8 * i..8 * i + 8) {
+                Some(offset) => start.saturating_add(read_u64(offset)),
+                None => end,
+            };
+            if block_end < bytes.offset() || block_end > end {
+                return Err((start + BLOCKED_HEADER_LEN + 8 * i, "the block offsets are out of bounds"));
+            }
+            let offset = bytes.offset();
+            let block = bytes.take((block_end - offset) as u64)?;
+            let len = sink.len();
+            self.try_decode_block(source, block, sink)?;
+            if (sink.len() - len) as u64 != block_len.min(degree - i as u64 * block_len) {
+                return Err((offset, "a block has the wrong number of neighbors"));
+            }
+        }
+        Ok(())
+    }
+
+    fn try_decode_block<V: VertexId>(self, source: V, bytes: Cursor<'_>, sink: &mut Sink<'_, V>) -> Checked<()>
+    {
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        match self.codec {
+            Codec::ByteRle => decoder::try_decode_into(source, bytes, sink),
+            Codec::Byte => byte_code::try_decode_into(source, bytes, sink),
+            Codec::Nibble => nibble_code::try_decode_into(source, bytes, sink),
+            Codec::StreamVByte => stream_vbyte::try_decode_into(source, bytes, sink),
+            Codec::Gamma | Codec::Delta | Codec::Zeta(_) | Codec::Rice(_) => {
+                bit_code::try_decode_into(source, bytes, sink, self.codec.bit_code())
+            }
+            Codec::Rans => rans::try_decode_into(source, bytes, sink, self.model),
+        }
+    }
+}
+
+/// The neighbors of a block.
+pub(crate) enum Block<'a, V: VertexId>
+{
+    ByteRle(decoder::Iter<'a, V>),
+    Byte(byte_code::Iter<'a, V>),
+    Nibble(nibble_code::Iter<'a, V>),
+    StreamVByte(stream_vbyte::Iter<'a, V>),
+    Bits(bit_code::Iter<'a, V>),
+    Rans(rans::Iter<'a, V>),
+}
+
+impl<'a, V: VertexId> Iterator for Block<'a, V>
+{
+    type Item = V;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item>
+    {
+        match self {
+            Block::ByteRle(iter) => iter.next(),
+            Block::Byte(iter) => iter.next(),
+            Block::Nibble(iter) => iter.next(),
+            Block::StreamVByte(iter) => iter.next(),
+            Block::Bits(iter) => iter.next(),
+            Block::Rans(iter) => iter.next(),
+        }
+    }
+}
+
+/// The neighbors of a vertex, block by block.
+pub(crate) struct Decode<'a, V: VertexId>
+{
+    coder: Coder<'a>,
+    source: V,
+    blocks: Blocks<'a>,
+    next_block: usize,
+    block: Option<Block<'a, V>>,
+}
+
+impl<'a, V: VertexId> Iterator for Decode<'a, V>
+{
+    type Item = V;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item>
+    {
+        loop {
+            if let Some(edge) = self.block.as_mut().and_then(Block::next) {
+                return Some(edge);
+            }
+            if self.next_block == self.blocks.len() {
+                return None;
+            }
+            self.block = Some(self.coder.decode_block(self.source, self.blocks.get(self.next_block)));
+            self.next_block += 1;
+        }
+    }
+}
+
+/// The first byte of a block encoded vertex, a negative zero first edge that
+/// `encoder::first_edge` never writes.
+pub(crate) const BLOCKED: u8 = 0x40;
+
+// BLOCKED, the degree and the block length.
+const BLOCKED_HEADER_LEN: usize = 17;
+
+#[inline]
+fn read_u64(bytes: &[u8]) -> usize
+{
+    u64::from_le_bytes(bytes[..8].try_into().unwrap()) as usize
+}
+
+/// The independently decodable blocks of a vertex's bytes, see
+/// `Codec::encode_blocked`. A vertex that isn't block encoded is a single
+/// block, or none if it has no neighbors.
+#[derive(Clone, Copy)]
+pub(crate) struct Blocks<'a>
+{
+    bytes: &'a [u8],
+    // The degree and the block length of a block encoded vertex.
+    blocked: Option<(usize, usize)>,
+    len: usize,
+}
+
+impl<'a> Blocks<'a>
+{
+    pub fn new(bytes: &'a [u8]) -> Self
+    {
+        match bytes.first() {
+            Some(&BLOCKED) => {
+                let degree = read_u64(&bytes[1..]);
+                let block_len = read_u64(&bytes[9..]);
+                Self {
+                    bytes,
+                    blocked: Some((degree, block_len)),
+                    len: degree.div_ceil(block_len),
+                }
+            }
+            Some(_) => Self {
+                bytes,
+                blocked: None,
+                len: 1,
+            },
+            None => Self {
+                bytes,
+                blocked: None,
+                len: 0,
+            },
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize
+    {
+        self.len
+    }
+
+    #[inline]
+    pub fn block_len(&self) -> Option<usize>
+    {
+        self.blocked.map(|(_, block_len)| block_len)
+    }
+
+    /// The bytes of the `i`th block.
+    pub fn get(&self, i: usize) -> &'a [u8]
+    {
+        if self.blocked.is_none() {
+            return self.bytes;
+        }
+
+        let offset = |j: usize| {
+            if j == 0 {
+                BLOCKED_HEADER_LEN + 8 * (self.len - 1)
+            }
+            else if j == self.len {
+                self.bytes.len()
+            }
+            else {
+                read_u64(&self.bytes[BLOCKED_HEADER_LEN + 8 * (j - 1)..])
+            }
+        };
+
+        &self.bytes[offset(i)..offset(i + 1)]
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    /// A source and its sorted neighbors smaller than `order`, the first
+    /// anywhere and the differences of up to `bits` bits.
+    fn random_list(rng: &mut StdRng, order: u64, bits: u32) -> (u64, Vec<u64>)
+    {
+        let source = rng.gen_range(0..order);
+        let mut neighbors = vec![];
+        let mut edge = rng.gen_range(0..order);
+        for _ in 0..rng.gen_range(0..200) {
+            neighbors.push(edge);
+            let len = rng.gen_range(0..bits);
+            let diff = rng.gen_range(1..=1u64 << len);
+            match edge.checked_add(diff).filter(|&edge| edge < order) {
+                Some(next) => edge = next,
+                None => break,
+            }
+        }
+        (source, neighbors)
+    }
+
+    /// Lists that start or end at the smallest and largest ids.
+    fn extremes(max: u64) -> Vec<(u64, Vec<u64>)>
+    {
+        vec![
+            (0, vec![]),
+            (0, vec![0]),
+            (max, vec![max]),
+            (0, vec![max]),
+            (max, vec![0]),
+            (max, vec![0, 1, max - 1, max]),
+            (max / 2, (max - 100..=max).collect()),
+        ]
+    }
+
+    fn check<V: VertexId>(coder: Coder<'_>, source: V, neighbors: &[V], bytes: &[u8])
+    {
+        assert_eq!(neighbors, coder.decode(source, bytes).collect::<Vec<_>>());
+
+        let mut out = vec![];
+        coder.decode_into(source, bytes, &mut out);
+        assert_eq!(neighbors, out);
+
+        let mut out = vec![];
+        coder
+            .try_decode_into(source, Cursor::new(bytes, 0), &mut Sink::new(&mut out, u64::MAX))
+            .unwrap();
+        assert_eq!(neighbors, out);
+    }
+
+    /// Encodes the neighbors in one block and in blocks and decodes them
+    /// every way.
+    fn round_trip<V: VertexId>(coder: Coder<'_>, source: u64, neighbors: &[u64])
+    {
+        let source = V::from_u64(source);
+        let neighbors: Vec<V> = neighbors.iter().map(|&v| V::from_u64(v)).collect();
+
+        let mut bytes = vec![];
+        coder.encode(&mut bytes, source, neighbors.iter().copied());
+        check(coder, source, &neighbors, &bytes);
+
+        if neighbors.len() > 1 {
+            let mut bytes = vec![];
+            coder.encode_blocked(&mut bytes, source, &neighbors, neighbors.len().div_ceil(3));
+            check(coder, source, &neighbors, &bytes);
+        }
+    }
+
+    /// Random lists with differences of up to `max_bits` bits, and the
+    /// extremes if `max_bits` allows differences of any size, for both
+    /// vertex id types.
+    fn round_trips(codec: Codec, max_bits: u32)
+    {
+        let coder = Coder { codec, model: &[] };
+        let mut rng = StdRng::seed_from_u64(codec.id() as u64);
+        for _ in 0..100 {
+            let bits = rng.gen_range(1..=max_bits);
+            let (source, neighbors) = random_list(&mut rng, 1 << 32, bits.min(32));
+            round_trip::<u32>(coder, source, &neighbors);
+            let (source, neighbors) = random_list(&mut rng, 1 << 48, bits);
+            round_trip::<u64>(coder, source, &neighbors);
+        }
+        if max_bits < 48 {
+            return;
+        }
+        for (source, neighbors) in extremes(u32::MAX as u64) {
+            round_trip::<u32>(coder, source, &neighbors);
+        }
+        for (source, neighbors) in extremes(1 << 48) {
+            round_trip::<u64>(coder, source, &neighbors);
+        }
+    }
+
+    #[test]
+    fn byte_round_trips()
+    {
+        round_trips(Codec::Byte, 48);
+    }
+
+    #[test]
+    fn nibble_round_trips()
+    {
+        round_trips(Codec::Nibble, 48);
+    }
+
+    #[test]
+    fn gamma_and_delta_round_trips()
+    {
+        round_trips(Codec::Gamma, 48);
+        round_trips(Codec::Delta, 48);
+    }
+
+    #[test]
+    fn zeta_round_trips()
+    {
+        for k in [1, 2, 3, 5, 8, 16] {
+            round_trips(Codec::Zeta(k), 48);
+        }
+    }
+
+    #[test]
+    fn rice_round_trips()
+    {
+        // The quotient is unary, so the differences are kept to a few
+        // hundred times 2^k.
+        for k in [0, 1, 5, 20, 40, 63] {
+            round_trips(Codec::Rice(k), (k as u32 + 8).min(48));
+        }
+    }
+
+    #[test]
+    fn rans_round_trips()
+    {
+        let mut rng = StdRng::seed_from_u64(21);
+        let mut lists = vec![];
+        for _ in 0..200 {
+            let bits = rng.gen_range(1..=32);
+            lists.push(random_list(&mut rng, 1 << 32, bits));
+        }
+
+        // The model is built from half of the lists, the others and the
+        // extremes have to be encoded with it too.
+        for model_lists in [&lists[..0], &lists[..2], &lists[..100]] {
+            let mut histogram = rans::Histogram::default();
+            for (_, list) in model_lists {
+                histogram.add(list.iter().copied());
+            }
+            let model = histogram.model();
+            let coder = Coder { codec: Codec::Rans, model: &model };
+            for (source, list) in &lists {
+                round_trip::<u32>(coder, *source, list);
+                round_trip::<u64>(coder, *source << 16, &list.iter().map(|&v| v << 16).collect::<Vec<_>>());
+            }
+            for (source, list) in extremes(u32::MAX as u64) {
+                round_trip::<u32>(coder, source, &list);
+            }
+            for (source, list) in extremes(1 << 48) {
+                round_trip::<u64>(coder, source, &list);
+            }
+        }
+    }
+}
@@ -18,19 +18,70 @@
 //! edges are compressed using the byte run length encoding scheme from
 //! [Ligra+](https://people.csail.mit.edu/jshun/ligra+.pdf).
 
-use rayon::prelude::*;
+use std::{
+    borrow::Cow,
+    io::{self, Read, Write},
+    marker::PhantomData,
+};
 
-use crate::{decoder, encoder, iter::IteratorDedupExt, par, vec, Edge};
+use rayon::{iter::Either, prelude::*};
 
-/// The Compressed Sparse Row struct.
-pub struct CSR
+use crate::{
+    builder::CsrBuilder,
+    checked::{self, Checked, Cursor},
+    codec::{Blocks, Codec, Coder},
+    error::{DecodeError, EncodeError, Error},
+    format,
+    graph::Graph,
+    iter::IteratorDedupExt,
+    offsets::Offsets,
+    par,
+    rans::Histogram,
+    reference,
+    skip::SkipIndex,
+    vec,
+    vertex::VertexId,
+    weighted,
+    Edge,
+};
+
+/// The Compressed Sparse Row struct, generic over the vertex id type, `u32`
+/// by default or `u64` for graphs with more than 4B vertices.
+///
+/// # Examples
+///
+/// ```
+/// use glzip::{CSR, Edge};
+///
+/// let targets: Vec<u64> = (0..100).map(|i| i * 3).chain([1 << 20]).collect();
+///
+/// let csr = CSR::from_edges(targets.iter().map(|&v| Edge(7u64, v)));
+///
+/// assert_eq!((1 << 20) + 1, csr.order());
+/// assert_eq!(targets, csr.neighbors(7).collect::<Vec<_>>());
+/// assert_eq!(101, csr.degree(7));
+/// ```
+pub struct CSR<V: VertexId = u32>
 {
-    vertices: Vec<usize>,
+    vertices: Offsets,
+    // The index of each vertex's first edge in decode order, the degrees
+    // are the differences.
+    edge_offsets: Offsets,
     num_edges: usize,
     edges: Vec<u8>,
+    codec: Codec,
+    // An id per edge in decode order.
+    edge_ids: Option<Vec<u64>>,
+    skips: SkipIndex<'static, V>,
+    // Whether vertices may be block encoded, see `CsrBuilder::block_len`.
+    blocked: bool,
+    // The longest chain of references if vertices are encoded against
+    // references, see `CsrBuilder::references`.
+    max_chain: Option<usize>,
+    marker: PhantomData<V>,
 }
 
-impl CSR
+impl<V: VertexId> CSR<V>
 {
     /// The neighbors of a vertex.
     ///
@@ -50,15 +101,163 @@ impl CSR
     /// assert_eq!(vec![0], csr.neighbors(1).collect::<Vec<_>>());
     /// assert_eq!(vec![1], csr.neighbors(2).collect::<Vec<_>>());
     /// ```
-    pub fn neighbors(&self, source: u32) -> impl Iterator<Item = u32> + '_
+    pub fn neighbors(&self, source: V) -> impl Iterator<Item = V> + '_
     {
-        let i = source as usize;
-        self.vertices.get(i).into_iter().flat_map(move |&start| {
+        if self.max_chain.is_some() {
+            let mut buf = Vec::new();
+            self.neighbors_into(source, &mut buf);
+            return Either::Left(buf.into_iter());
+        }
+
+        Either::Right(
             self.vertices
-                .get(i + 1)
+                .range(source.to_usize())
                 .into_iter()
-                .flat_map(move |&end| decoder::decode(source, &self.edges[start..end]))
+                .flat_map(move |(start, end)| self.coder().decode(source, &self.edges[start..end])),
+        )
+    }
+
+    /// The codec with the model stored in front of the first vertex.
+    fn coder(&self) -> Coder<'_>
+    {
+        Coder {
+            codec: self.codec,
+            model: &self.edges[..self.vertices.get(0).unwrap_or(0)],
+        }
+    }
+
+    /// The bytes of a vertex, empty if there is no such vertex.
+    fn vertex_bytes(&self, u: usize) -> &[u8]
+    {
+        self.vertices
+            .range(u)
+            .map_or(&[][..], |(start, end)| &self.edges[start..end])
+    }
+
+    /// Appends the neighbors of a vertex to `buf`. Faster than collecting
+    /// [`CSR::neighbors`] as whole groups are decoded straight into `buf`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glzip::CSR;
+    ///
+    /// let csr = CSR::from(vec![
+    ///     [0u32,1],
+    ///     [0,2],
+    ///     [1,0],
+    ///     [2,1],
+    /// ]);
+    ///
+    /// let mut buf = Vec::new();
+    /// csr.neighbors_into(0, &mut buf);
+    /// csr.neighbors_into(2, &mut buf);
+    ///
+    /// assert_eq!(vec![1,2,1], buf);
+    /// ```
+    pub fn neighbors_into(&self, source: V, buf: &mut Vec<V>)
+    {
+        if let Some((start, end)) = self.vertices.range(source.to_usize()) {
+            buf.reserve(self.degree(source));
+            if let Some(max_chain) = self.max_chain {
+                reference::decode_into(self.coder(), max_chain, source, &|u| self.vertex_bytes(u), buf);
+            }
+            else {
+                self.coder().decode_into(source, &self.edges[start..end], buf);
+            }
+        }
+    }
+
+    /// [`CSR::neighbors_into`] with every byte checked, for a graph whose
+    /// bytes may be corrupt. Fails with the vertex and the offset of the
+    /// first byte that doesn't decode, appending nothing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glzip::CSR;
+    ///
+    /// let csr = CSR::from(vec![
+    ///     [0u32,1],
+    ///     [0,2],
+    ///     [1,0],
+    ///     [2,1],
+    /// ]);
+    ///
+    /// let mut buf = Vec::new();
+    /// csr.try_neighbors_into(0, &mut buf).unwrap();
+    ///
+    /// assert_eq!(vec![1,2], buf);
+    /// ```
+    pub fn try_neighbors_into(&self, source: V, buf: &mut Vec<V>) -> Result<(), DecodeError>
+    {
+        let bytes = |u| Ok(self.vertex_cursor(u));
+        checked::decode_into(self.coder(), self.max_chain, self.order(), source, &bytes, buf)
+    }
+
+    /// The bytes of a vertex with their offset.
+    fn vertex_cursor(&self, u: usize) -> Cursor<'_>
+    {
+        Cursor::new(self.vertex_bytes(u), self.vertices.get(u).unwrap_or(0))
+    }
+
+    /// Checks that the bytes of every vertex decode to neighbors that are
+    /// increasing and vertices of the graph, as many as its degree. Graphs
+    /// that are built or read with [`CSR::read_from`] always are.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glzip::{CSR, Edge};
+    ///
+    /// let csr = CSR::from_edges((0..1000u32).map(|v| Edge(v % 10, v)));
+    ///
+    /// assert_eq!(Ok(()), csr.validate());
+    /// ```
+    pub fn validate(&self) -> Result<(), DecodeError>
+    {
+        checked::degrees(self.order(), |u, buf| {
+            self.try_neighbors_into(V::from_usize(u), buf)?;
+            if buf.len() != self.degree(V::from_usize(u)) {
+                let offset = self.vertices.get(u).unwrap_or(0);
+                return Err(DecodeError::new(u, (offset, "the degree doesn't match")));
+            }
+            Ok(())
         })
+        .map(|_| ())
+    }
+
+    /// The neighbors of a vertex decoded in parallel, one task per block for
+    /// vertices that are block encoded, see [`CsrBuilder::block_len`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glzip::{CSR, Edge};
+    /// use rayon::prelude::*;
+    ///
+    /// let csr = CSR::builder()
+    ///     .block_len(64)
+    ///     .build((0..1000u32).map(|v| Edge(0, 3 * v)));
+    ///
+    /// let neighbors: Vec<u32> = csr.par_neighbors(0).collect();
+    ///
+    /// assert_eq!(csr.neighbors(0).collect::<Vec<_>>(), neighbors);
+    /// ```
+    pub fn par_neighbors(&self, source: V) -> impl ParallelIterator<Item = V> + '_
+    {
+        if self.max_chain.is_some() {
+            let mut buf = Vec::new();
+            self.neighbors_into(source, &mut buf);
+            return Either::Left(buf.into_par_iter());
+        }
+
+        let blocks = Blocks::new(self.vertex_bytes(source.to_usize()));
+        Either::Right(
+            (0..blocks.len())
+                .into_par_iter()
+                .flat_map_iter(move |i| self.coder().decode_block(source, blocks.get(i))),
+        )
     }
 
     /// The degrees of a vertex.
@@ -79,17 +278,113 @@ impl CSR
     /// assert_eq!(1, csr.degree(1));
     /// assert_eq!(1, csr.degree(2));
     /// ```
-    pub fn degree(&self, source: u32) -> usize
+    pub fn degree(&self, source: V) -> usize
     {
-        let i = source as usize;
-        self.vertices
-            .get(i)
-            .and_then(move |&start| {
-                self.vertices
-                    .get(i + 1)
-                    .map(|&end| decoder::count(source, &self.edges[start..end]))
-            })
-            .unwrap_or(0usize)
+        self.edge_range(source).len()
+    }
+
+    /// The `i`th neighbor of a vertex in ascending order. Vertices with many
+    /// neighbors keep a skip index, so only the neighbors close to `i` are
+    /// decoded. A graph built with [`CsrBuilder::references`] has no skip
+    /// index, every call decodes the vertex and its chain of references into
+    /// a new `Vec`, so [`CSR::neighbors_into`] with a reused buffer is faster
+    /// for many lookups into the same vertex.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glzip::{CSR, Edge};
+    ///
+    /// let csr = CSR::from_edges((0..1000u32).map(|v| Edge(0, 2 * v)));
+    ///
+    /// assert_eq!(Some(0), csr.neighbor_at(0, 0));
+    /// assert_eq!(Some(1000), csr.neighbor_at(0, 500));
+    /// assert_eq!(Some(1998), csr.neighbor_at(0, 999));
+    /// assert_eq!(None, csr.neighbor_at(0, 1000));
+    /// assert_eq!(None, csr.neighbor_at(1, 0));
+    /// ```
+    pub fn neighbor_at(&self, source: V, i: usize) -> Option<V>
+    {
+        if self.max_chain.is_some() {
+            let mut buf = Vec::new();
+            self.neighbors_into(source, &mut buf);
+            return buf.get(i).copied();
+        }
+
+        let (start, end) = self.vertices.range(source.to_usize())?;
+        self.skips.neighbor_at(self.coder(), source, &self.edges[start..end], i)
+    }
+
+    /// Whether the graph has the edge from `source` to `target`. Decoding
+    /// stops at the first neighbor not smaller than `target`, and starts from
+    /// the skip index for vertices with many neighbors. A graph built with
+    /// [`CsrBuilder::references`] decodes the vertex and its chain of
+    /// references into a new `Vec` on every call, see [`CSR::neighbor_at`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glzip::CSR;
+    ///
+    /// let csr = CSR::from(vec![
+    ///     [0u32,1],
+    ///     [0,2],
+    ///     [1,0],
+    ///     [2,1],
+    /// ]);
+    ///
+    /// assert!(csr.has_edge(0, 2));
+    /// assert!(!csr.has_edge(2, 0));
+    /// assert!(!csr.has_edge(3, 0));
+    /// ```
+    pub fn has_edge(&self, source: V, target: V) -> bool
+    {
+        if self.max_chain.is_some() {
+            let mut buf = Vec::new();
+            self.neighbors_into(source, &mut buf);
+            return buf.binary_search(&target).is_ok();
+        }
+
+        match self.vertices.range(source.to_usize()) {
+            Some((start, end)) => {
+                self.skips
+                    .contains(self.coder(), source, &self.edges[start..end], target)
+            }
+            None => false,
+        }
+    }
+
+    /// [`CSR::has_edge`] for a batch of edges, in parallel.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glzip::CSR;
+    ///
+    /// let csr = CSR::from(vec![
+    ///     [0u32,1],
+    ///     [0,2],
+    ///     [1,0],
+    ///     [2,1],
+    /// ]);
+    ///
+    /// assert_eq!(vec![true, false, true], csr.has_edges(&[(0, 1), (1, 2), (2, 1)]));
+    /// ```
+    pub fn has_edges(&self, edges: &[(V, V)]) -> Vec<bool>
+    {
+        edges
+            .par_iter()
+            .map(|&(u, v)| self.has_edge(u, v))
+            .collect()
+    }
+
+    /// The indices of the edges of a vertex in decode order, e.g. into the
+    /// weights of a `WeightedCSR`.
+    pub(crate) fn edge_range(&self, source: V) -> std::ops::Range<usize>
+    {
+        self.edge_offsets
+            .range(source.to_usize())
+            .map_or(0..0, |(start, end)| start..end)
     }
 
     /// The edges of a graph.
@@ -110,113 +405,281 @@ impl CSR
     ///
     /// assert_eq!(es, csr.edges().collect::<Vec<_>>());
     /// ```
-    pub fn edges(&self) -> impl Iterator<Item = Edge> + '_
+    pub fn edges(&self) -> impl Iterator<Item = Edge<V>> + '_
     {
-        (0u32..self.order() as u32).flat_map(|u| {
-            let u = u as u32;
+        (0..self.order()).flat_map(|u| {
+            let u = V::from_usize(u);
             self.neighbors(u).map(move |v| Edge(u, v))
         })
     }
 
-    fn from_buffer(buf: &mut [Edge]) -> Self
+    pub(crate) fn from_buffer(buf: &mut [Edge<V>]) -> Result<Self, Error>
+    {
+        Self::from_buffer_with(buf, &CsrBuilder::new())
+    }
+
+    pub(crate) fn from_buffer_with(buf: &mut [Edge<V>], options: &CsrBuilder<V>) -> Result<Self, Error>
     {
         // Sort the buffer in lexigraphical order.
         buf.par_sort_unstable();
 
-        let num_nodes = match (
+        Self::from_sorted_with(buf, options)
+    }
+
+    /// Like `from_buffer_with` for a buffer that is already sorted.
+    pub(crate) fn from_sorted_with(buf: &[Edge<V>], options: &CsrBuilder<V>) -> Result<Self, Error>
+    {
+        options.check()?;
+
+        let max = match (
             par::max(buf.par_iter().map(|e| e.0)),
             par::max(buf.par_iter().map(|e| e.1)),
         ) {
-            (Some(u), Some(v)) => std::cmp::max(u, v) as usize,
-            (None, Some(v)) => v as usize,
-            (Some(u), None) => u as usize,
+            (Some(u), Some(v)) => std::cmp::max(u, v),
+            (None, Some(v)) => v,
+            (Some(u), None) => u,
             (None, None) => {
-                return Self {
-                    vertices: vec![],
+                return Ok(Self {
+                    vertices: Offsets::default(),
+                    edge_offsets: Offsets::default(),
                     num_edges: 0,
                     edges: vec![],
-                }
+                    codec: options.codec,
+                    edge_ids: None,
+                    skips: SkipIndex::default(),
+                    blocked: false,
+                    max_chain: None,
+                    marker: PhantomData,
+                })
             }
         };
+        let num_nodes = Self::max_vertex(max)?;
+
+        // The model is stored in front of the vertices, see `Codec::Rans`.
+        let model = if options.codec == Codec::Rans {
+            par::group_by(buf, |e1, e2| e1.0 == e2.0)
+                .fold(Histogram::default, |mut histogram, group| {
+                    histogram.add(group.iter().dedup().map(|e| e.1));
+                    histogram
+                })
+                .reduce(Histogram::default, Histogram::merge)
+                .model()
+        }
+        else {
+            vec![]
+        };
+        let coder = Coder { codec: options.codec, model: &model };
+
+        if let Some((window, max_chain)) = options.references {
+            let lists: Vec<(V, Vec<V>)> = par::group_by(buf, |e1, e2| e1.0 == e2.0)
+                .map(|group| (group[0].0, group.iter().dedup().map(|e| e.1).collect()))
+                .collect();
+            let (num_edges, nodes_and_nnzs, edges, longest) =
+                reference::encode(coder, &lists, window, max_chain, options.verified)?;
+            let mut csr = Self::from_encoded(num_nodes, num_edges, nodes_and_nnzs, &model, edges, options)?;
+            csr.max_chain = Some(longest);
+            return Ok(csr);
+        }
 
-        let (num_edges, nodes_and_nnzs, mut edges) =
+        let (num_edges, nodes_and_nnzs, edges) =
             // Group the buffer into groups of edges that
             // share a source vertex.
             par::group_by(buf, |e1, e2| e1.0 == e2.0)
-                .fold(|| (0, vec![], vec![]),
-                      |(mut num_edges, mut nodes_and_nnzs, mut edges), group| {
-                    let mut iter = group.iter().dedup();
-                    if let Some(&Edge(u, v)) = iter.next() {
-                        num_edges += 1usize;
-                        let start = edges.len();
-                        encoder::encode(
-                            &mut edges,
-                            u,
-                            std::iter::once(v).chain(iter.map(|Edge(_, w)| {
-                                num_edges += 1;
-                                *w
-                            })),
-                        );
-                        let end = edges.len();
-                        nodes_and_nnzs.push((u, end - start));
-                        (num_edges, nodes_and_nnzs, edges)
-                    }
-                    else {
-                        (num_edges, nodes_and_nnzs, edges)
-                    }
+                .try_fold(|| (0, vec![], vec![], vec![], vec![]),
+                          |(mut num_edges, mut nodes_and_nnzs, mut edges, mut targets, mut decoded), group| {
+                    let u = group[0].0;
+                    targets.clear();
+                    targets.extend(group.iter().dedup().map(|e| e.1));
+                    let start = edges.len();
+                    options.encode_vertex(coder, &mut edges, u, &targets, &mut decoded)?;
+                    let end = edges.len();
+                    num_edges += targets.len();
+                    nodes_and_nnzs.push((u, end - start, targets.len()));
+                    Ok::<_, EncodeError>((num_edges, nodes_and_nnzs, edges, targets, decoded))
                 })
-                .reduce(|| (0, vec![], vec![]),
-                        |left, right| {
-                    (left.0 + right.0, vec::concat(left.1, right.1), vec::concat(left.2, right.2))
-                });
+                .map(|result| result.map(|(num_edges, nodes_and_nnzs, edges, _, _)| (num_edges, nodes_and_nnzs, edges)))
+                .try_reduce(|| (0, vec![], vec![]),
+                            |left, right| {
+                    Ok((left.0 + right.0, vec::concat(left.1, right.1), vec::concat(left.2, right.2)))
+                })?;
 
+        Self::from_encoded(num_nodes, num_edges, nodes_and_nnzs, &model, edges, options)
+    }
+
+    /// The largest vertex as a `usize`, failing if there can't be offsets up
+    /// to the one past it.
+    pub(crate) fn max_vertex(max: V) -> Result<usize, Error>
+    {
+        usize::try_from(max.to_u64())
+            .ok()
+            .filter(|&n| n < usize::MAX - 1)
+            .ok_or(Error::IdOverflow { edge: None, id: max.to_u64() as i128 })
+    }
+
+    /// Adds vertices without neighbors after the last vertex up to `order`,
+    /// e.g. the declared size of a MatrixMarket file.
+    pub(crate) fn pad_order(&mut self, order: usize) -> Result<(), Error>
+    {
+        if order <= self.order() {
+            return Ok(());
+        }
+        let pad = |offsets: &Offsets| -> Result<Offsets, Error> {
+            let mut values = Vec::new();
+            values.try_reserve_exact(order + 1)?;
+            values.extend(offsets.iter());
+            let end = values.last().copied().unwrap_or(0);
+            values.resize(order + 1, end);
+            Ok(Offsets::new(&values))
+        };
+        self.vertices = pad(&self.vertices)?;
+        self.edge_offsets = pad(&self.edge_offsets)?;
+        Ok(())
+    }
+
+    /// Builds the offsets of the encoded vertices, which have their number
+    /// of bytes and degree in `nodes_and_nnzs`, and puts the `model` of the
+    /// codec in front of them.
+    pub(crate) fn from_encoded(
+        num_nodes: usize,
+        num_edges: usize,
+        nodes_and_nnzs: Vec<(V, usize, usize)>,
+        model: &[u8],
+        mut edges: Vec<u8>,
+        options: &CsrBuilder<V>,
+    ) -> Result<Self, Error>
+    {
+        if !model.is_empty() {
+            edges.splice(0..0, model.iter().copied());
+        }
         edges.shrink_to_fit();
 
-        let mut nnzs = Vec::with_capacity(nodes_and_nnzs.len());
+        let mut nnzs = Vec::new();
+        nnzs.try_reserve_exact(num_nodes + 2)?;
+        let mut degrees = Vec::new();
+        degrees.try_reserve_exact(num_nodes + 2)?;
 
-        for (u, nnz) in nodes_and_nnzs {
-            nnzs.resize(u as usize, 0);
+        for (u, nnz, degree) in nodes_and_nnzs {
+            nnzs.resize(u.to_usize(), 0);
             nnzs.push(nnz);
+            degrees.resize(u.to_usize(), 0);
+            degrees.push(degree);
         }
 
         nnzs.resize(num_nodes + 1, 0);
+        degrees.resize(num_nodes + 1, 0);
 
-        let mut vertices = par::exclusive_sum(0, nnzs);
-
-        vertices.shrink_to_fit();
+        let vertices = Offsets::new(&par::exclusive_sum(model.len(), nnzs));
+        let edge_offsets = Offsets::new(&par::exclusive_sum(0, degrees));
+        let max_chain = options.references.map(|(_, max_chain)| max_chain);
+        let skips = if max_chain.is_some() {
+            SkipIndex::default()
+        }
+        else {
+            SkipIndex::new(
+                options.codec,
+                num_nodes,
+                |u| edge_offsets.range(u).map_or(0, |(start, end)| end - start),
+                |u| vertices.range(u).map_or(&[][..], |(start, end)| &edges[start..end]),
+            )
+        };
 
-        Self {
+        Ok(Self {
             vertices,
+            edge_offsets,
             num_edges,
             edges,
-        }
+            codec: options.codec,
+            edge_ids: None,
+            skips,
+            blocked: options.block_len.is_some(),
+            max_chain,
+            marker: PhantomData,
+        })
     }
+
+    /// Panics if the graph doesn't fit into memory, see
+    /// [`CSR::try_from_edges`].
     pub fn from_edges<I>(iter: I) -> Self
     where
-        I: IntoIterator<Item = Edge>
+        I: IntoIterator<Item = Edge<V>>
     {
-        let graph: Result<Self, !> = Self::try_from_edges(iter.into_iter().map(|e| Ok(e)));
+        let graph: Result<Self, Error> = Self::try_from_edges(iter.into_iter().map(Ok));
 
-        graph.into_ok()
+        graph.unwrap_or_else(|err| panic!("{}", err))
     }
 
+    /// Like [`CSR::from_edges`] but reserves room for `capacity` edges up
+    /// front. Panics if the graph doesn't fit into memory, see
+    /// [`CSR::try_from_edges_with_capacity`].
     pub fn from_edges_with_capacity<I>(capacity: usize, iter: I) -> Self
     where
-        I: IntoIterator<Item = Edge>
+        I: IntoIterator<Item = Edge<V>>
+    {
+        let graph: Result<Self, Error> = Self::try_from_edges_with_capacity(capacity, iter.into_iter().map(Ok));
+
+        graph.unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Builds a graph with non-default encoding options.
+    pub fn builder() -> CsrBuilder<V>
     {
-        let graph: Result<Self, !> = Self::try_from_edges_with_capacity(capacity, iter.into_iter().map(|e| Ok(e)));
+        CsrBuilder::new()
+    }
 
-        graph.into_ok()
+    /// The encoding of the neighbors.
+    pub fn codec(&self) -> Codec
+    {
+        self.codec
     }
 
+    /// The memory used by the graph in bytes, including the model of the
+    /// codec, see [`CSR::model_nbytes`]. The offsets take one to two bytes
+    /// per vertex for sparse graphs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glzip::{CSR, Edge};
+    ///
+    /// let csr = CSR::from_edges((0..10_000u32).map(|u| Edge(u, (u + 1) % 10_000)));
+    ///
+    /// assert!(csr.nbytes() < 4 * 10_000);
+    /// ```
     pub fn nbytes(&self) -> usize
     {
         let mut bytes = std::mem::size_of_val(self);
-        bytes += std::mem::size_of_val(&self.vertices[..]);
+        bytes += self.vertices.nbytes();
+        bytes += self.edge_offsets.nbytes();
         bytes += std::mem::size_of_val(&self.edges[..]);
+        bytes += self.skips.nbytes();
+        if let Some(edge_ids) = &self.edge_ids {
+            bytes += std::mem::size_of_val(&edge_ids[..]);
+        }
         bytes
     }
 
+    /// The bytes of the model of the codec, which are part of
+    /// [`CSR::nbytes`]. Only [`Codec::Rans`] has one, of at most a few
+    /// kilobytes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glzip::{codec::Codec, CSR, Edge};
+    ///
+    /// let edges: Vec<Edge<u32>> = (0..10_000u32).map(|u| Edge(u % 100, u)).collect();
+    ///
+    /// let csr = CSR::builder().codec(Codec::Rans).build(edges.iter().copied());
+    ///
+    /// assert!(csr.model_nbytes() > 0);
+    /// assert_eq!(0, CSR::from_edges(edges).model_nbytes());
+    /// assert_eq!((0..100).map(|v| 100 * v + 7).collect::<Vec<_>>(), csr.neighbors(7).collect::<Vec<_>>());
+    /// ```
+    pub fn model_nbytes(&self) -> usize
+    {
+        self.coder().model.len()
+    }
+
     /// The number of vertices in the graph.
     ///
     /// # Examples
@@ -245,42 +708,406 @@ impl CSR
         self.num_edges
     }
 
-    pub fn try_from_edges<E, I>(iter: I) -> Result<Self, E>
+    /// Builds a graph that remembers, for every edge, its index in `iter`.
+    /// Of parallel edges the smallest index is kept.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glzip::{CSR, Edge};
+    ///
+    /// let csr = CSR::from_edges_with_ids(vec![
+    ///     Edge(2u32, 1),
+    ///     Edge(0, 2),
+    ///     Edge(1, 0),
+    ///     Edge(0, 1),
+    ///     Edge(0, 2),
+    /// ]);
+    ///
+    /// assert_eq!(Some(&[3, 1][..]), csr.edge_ids(0));
+    /// assert_eq!(
+    ///     vec![(1, 3), (2, 1)],
+    ///     csr.neighbors_with_eid(0).unwrap().collect::<Vec<_>>()
+    /// );
+    /// assert_eq!(Some(&[0][..]), csr.edge_ids(2));
+    /// ```
+    pub fn from_edges_with_ids<I>(iter: I) -> Self
     where
-        I: IntoIterator<Item = Result<Edge, E>>,
+        I: IntoIterator<Item = Edge<V>>,
+    {
+        let graph: Result<Self, Error> = Self::try_from_edges_with_ids(iter.into_iter().map(Ok));
+
+        graph.unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like [`CSR::from_edges_with_ids`] but for edges that may fail. The
+    /// first error of an edge is returned as [`Error::Source`],
+    /// [`Error::Capacity`] if the graph doesn't fit into memory and
+    /// [`Error::IdOverflow`] if its largest vertex can't be indexed.
+    pub fn try_from_edges_with_ids<E, I>(iter: I) -> Result<Self, Error<E>>
+    where
+        I: IntoIterator<Item = Result<Edge<V>, E>>,
     {
-        let edges = iter.into_iter();
         let mut buf = Vec::new();
-        for edge in edges {
-            match edge {
-                Ok(e) => buf.push(e),
-                Err(err) => return Err(err),
-            };
+        for (i, edge) in iter.into_iter().enumerate() {
+            buf.try_reserve(1)?;
+            buf.push((edge.map_err(Error::Source)?, i as u64));
+        }
+
+        let (mut csr, ids) = weighted::compress_with(buf, std::cmp::min).map_err(Error::widen)?;
+        csr.edge_ids = Some(ids);
+
+        Ok(csr)
+    }
+
+    /// The ids of the edges of a vertex in the order of its neighbors, `None`
+    /// if the graph was built without edge ids.
+    pub fn edge_ids(&self, source: V) -> Option<&[u64]>
+    {
+        self.edge_ids
+            .as_ref()
+            .map(|edge_ids| &edge_ids[self.edge_range(source)])
+    }
+
+    /// The neighbors of a vertex with the ids of the edges to them, `None`
+    /// if the graph was built without edge ids.
+    pub fn neighbors_with_eid(&self, source: V) -> Option<impl Iterator<Item = (V, u64)> + '_>
+    {
+        self.edge_ids(source)
+            .map(|ids| self.neighbors(source).zip(ids.iter().copied()))
+    }
+
+    /// Writes the graph to `w` in glzip's versioned binary format so that it
+    /// can be loaded again with [`CSR::read_from`] without re-encoding.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glzip::CSR;
+    ///
+    /// let csr = CSR::from(vec![
+    ///     [0u32,1],
+    ///     [0,2],
+    ///     [1,0],
+    ///     [2,1],
+    /// ]);
+    ///
+    /// let mut buf = Vec::new();
+    /// csr.write_to(&mut buf).unwrap();
+    ///
+    /// let copy: CSR = CSR::read_from(&buf[..]).unwrap();
+    ///
+    /// assert_eq!(csr.edges().collect::<Vec<_>>(), copy.edges().collect::<Vec<_>>());
+    /// ```
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()>
+    {
+        let offset_width = if self.edges.len() <= u32::MAX as usize { 4 } else { 8 };
+
+        let mut flags = match self.edge_ids {
+            Some(_) => format::FLAG_EDGE_IDS,
+            None => 0,
+        };
+        if V::BYTES == 8 {
+            flags |= format::FLAG_U64_IDS;
         }
-        Ok(Self::from_buffer(&mut buf[..]))
+        if self.blocked {
+            flags |= format::FLAG_BLOCKS;
+        }
+        if self.max_chain.is_some() {
+            flags |= format::FLAG_REFERENCES;
+        }
+
+        let header = format::Header {
+            codec: self.codec,
+            offset_width,
+            flags,
+            order: self.order() as u64,
+            size: self.num_edges as u64,
+            num_bytes: self.edges.len() as u64,
+            max_chain: self.max_chain.unwrap_or(0).try_into().map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidInput, "the reference chains are too long for glzip's format")
+            })?,
+        };
+
+        header.write(&mut w)?;
+        if self.vertices.is_empty() {
+            format::write_offsets(&mut w, [0], offset_width as usize)?;
+        }
+        else {
+            format::write_offsets(&mut w, self.vertices.iter(), offset_width as usize)?;
+        }
+        if self.edge_offsets.is_empty() {
+            format::write_offsets(&mut w, [0], header.edge_offset_width())?;
+        }
+        else {
+            format::write_offsets(&mut w, self.edge_offsets.iter(), header.edge_offset_width())?;
+        }
+        w.write_all(&self.edges)?;
+        if let Some(edge_ids) = &self.edge_ids {
+            format::write_ids(&mut w, edge_ids)?;
+        }
+        w.write_all(self.skips.as_bytes())?;
+        Ok(())
     }
 
-    pub fn try_from_edges_with_capacity<E, I>(capacity: usize, iter: I) -> Result<Self, E>
+    /// Reads a graph written by [`CSR::write_to`]. Every vertex is decoded
+    /// with checks, so a corrupt file fails with [`Error::Corrupt`].
+    pub fn read_from<R: Read>(mut r: R) -> Result<Self, Error>
+    {
+        let header = format::Header::read(&mut r)?;
+        header.check_vertex_id::<V>()?;
+        let codec = header.codec;
+        let max_chain = header.max_chain();
+
+        let vertices =
+            format::read_offsets(&mut r, header.order.saturating_add(1), header.offset_width as usize)?;
+
+        if let Some(u) = vertices.windows(2).position(|w| w[0] > w[1]) {
+            let reason = "the offsets of the vertex are out of bounds";
+            return Err(DecodeError::new(u, (vertices[u], reason)).into());
+        }
+        let end = vertices[vertices.len() - 1];
+        if end != header.num_bytes as usize {
+            let reason = "the offsets don't end with the edges";
+            return Err(DecodeError::new(header.order as usize, (end, reason)).into());
+        }
+
+        let stored_edge_offsets =
+            format::read_offsets(&mut r, header.order.saturating_add(1), header.edge_offset_width())?;
+
+        let edges = format::read_bytes(&mut r, header.num_bytes)?;
+
+        // Decoding every vertex with checks both validates the bytes and
+        // counts the neighbors.
+        let order = header.order as usize;
+        let coder = Coder { codec, model: &edges[..vertices[0]] };
+        let bytes = |u: usize| -> Checked<Cursor<'_>> {
+            Ok(Cursor::new(&edges[vertices[u]..vertices[u + 1]], vertices[u]))
+        };
+        let degrees = checked::degrees(order, |u, buf| {
+            checked::decode_into(coder, max_chain, order, V::from_usize(u), &bytes, buf)
+        })?;
+        let edge_offsets = par::exclusive_sum(0, degrees);
+
+        if edge_offsets.last().copied() != Some(header.size as usize) {
+            return Err(DecodeError::new(order, (end, "the size doesn't match")).into());
+        }
+        // The first edge offset that differs is past the vertex whose
+        // degree doesn't match.
+        let mismatch = stored_edge_offsets.iter().zip(&edge_offsets).position(|(a, b)| a != b);
+        if let Some(u) = mismatch.map(|i| i.saturating_sub(1)) {
+            return Err(DecodeError::new(u, (vertices[u], "the degree doesn't match")).into());
+        }
+
+        let edge_ids = if header.flags & format::FLAG_EDGE_IDS != 0 {
+            let bytes = format::read_bytes(&mut r, header.size.saturating_mul(8))?;
+            Some(
+                bytes
+                    .chunks_exact(8)
+                    .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+                    .collect(),
+            )
+        }
+        else {
+            None
+        };
+
+        let vertices = Offsets::new(&vertices);
+        let edge_offsets = Offsets::new(&edge_offsets);
+        let skips = if max_chain.is_some() {
+            SkipIndex::default()
+        }
+        else {
+            SkipIndex::new(
+                codec,
+                order,
+                |u| edge_offsets.range(u).map_or(0, |(start, end)| end - start),
+                |u| vertices.range(u).map_or(&[][..], |(start, end)| &edges[start..end]),
+            )
+        };
+        if SkipIndex::<V>::read(&mut r)?.as_bytes() != skips.as_bytes() {
+            return Err(DecodeError::new(order, (end, "the skip index doesn't match")).into());
+        }
+
+        Ok(Self {
+            vertices,
+            edge_offsets,
+            num_edges: header.size as usize,
+            edges,
+            codec,
+            edge_ids,
+            skips,
+            blocked: header.flags & format::FLAG_BLOCKS != 0,
+            max_chain,
+            marker: PhantomData,
+        })
+    }
+
+    /// Builds a graph from edges that may fail, e.g. while they are read
+    /// from a file. The first error of an edge is returned as
+    /// [`Error::Source`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glzip::{CSR, Edge, Error};
+    ///
+    /// let edges = vec![Ok(Edge(0u32, 1)), Err("no such page"), Ok(Edge(1, 0))];
+    ///
+    /// let err = CSR::try_from_edges(edges).err().unwrap();
+    ///
+    /// assert!(matches!(err, Error::Source("no such page")));
+    /// ```
+    pub fn try_from_edges<E, I>(iter: I) -> Result<Self, Error<E>>
     where
-        I: IntoIterator<Item = Result<Edge, E>>,
+        I: IntoIterator<Item = Result<Edge<V>, E>>,
+    {
+        Self::try_from_edges_with_capacity(0, iter)
+    }
+
+    /// Like [`CSR::try_from_edges`] but reserves room for `capacity` edges
+    /// up front. The first error of an edge is returned as
+    /// [`Error::Source`], [`Error::Capacity`] if the edges or the graph
+    /// don't fit into memory and [`Error::IdOverflow`] if its largest vertex
+    /// can't be indexed.
+    pub fn try_from_edges_with_capacity<E, I>(capacity: usize, iter: I) -> Result<Self, Error<E>>
+    where
+        I: IntoIterator<Item = Result<Edge<V>, E>>,
     {
         let edges = iter.into_iter();
-        let mut buf = Vec::with_capacity(capacity);
+        let mut buf = Vec::new();
+        buf.try_reserve(capacity)?;
         for edge in edges {
             match edge {
-                Ok(e) => buf.push(e),
-                Err(err) => return Err(err),
+                Ok(e) => {
+                    buf.try_reserve(1)?;
+                    buf.push(e)
+                }
+                Err(err) => return Err(Error::Source(err)),
             };
         }
-        Ok(Self::from_buffer(&mut buf[..]))
+        Self::from_buffer(&mut buf[..]).map_err(Error::widen)
     }
 }
 
-impl<T: Into<Edge>> From<Vec<T>> for CSR
+/// Panics if the graph doesn't fit into memory, see
+/// [`CSR::try_from_edges`].
+impl<V: VertexId, T: Into<Edge<V>>> From<Vec<T>> for CSR<V>
 {
     fn from(vect: Vec<T>) -> Self
     {
-        let mut edges: Vec<Edge> = vect.into_iter().map(|x| x.into()).collect();
-        Self::from_buffer(&mut edges[..])
+        let mut edges: Vec<Edge<V>> = vect.into_iter().map(|x| x.into()).collect();
+        Self::from_buffer(&mut edges[..]).unwrap_or_else(|err| panic!("{}", err))
+    }
+}
+
+impl<V: VertexId> Graph for CSR<V>
+{
+    type Vertex = V;
+
+    fn neighbors(&self, source: V) -> impl Iterator<Item = V> + '_
+    {
+        CSR::neighbors(self, source)
+    }
+
+    fn degree(&self, source: V) -> usize
+    {
+        CSR::degree(self, source)
+    }
+
+    fn neighbors_into(&self, source: V, buf: &mut Vec<V>)
+    {
+        CSR::neighbors_into(self, source, buf)
+    }
+
+    fn neighbor_at(&self, source: V, i: usize) -> Option<V>
+    {
+        CSR::neighbor_at(self, source, i)
+    }
+
+    fn random_access(&self) -> bool
+    {
+        // Only byte-RLE vertices have skips, block encoded ones find their
+        // block directly.
+        self.max_chain.is_none() && (self.codec == Codec::ByteRle || self.blocked)
+    }
+
+    fn has_edge(&self, source: V, target: V) -> bool
+    {
+        CSR::has_edge(self, source, target)
+    }
+
+    fn order(&self) -> usize
+    {
+        CSR::order(self)
+    }
+
+    fn size(&self) -> usize
+    {
+        CSR::size(self)
+    }
+
+    fn edge_ids(&self, source: V) -> Option<Cow<'_, [u64]>>
+    {
+        CSR::edge_ids(self, source).map(Cow::Borrowed)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+    use super::*;
+
+    /// Shuffled edges with parallel edges.
+    fn edges(rng: &mut StdRng) -> Vec<Edge>
+    {
+        let mut edges: Vec<Edge> = (0..2000).map(|_| Edge(rng.gen_range(0..50), rng.gen_range(0..300))).collect();
+        edges.extend_from_within(..500);
+        edges.shuffle(rng);
+        edges
+    }
+
+    fn check_edge_ids(csr: &CSR, edges: &[Edge])
+    {
+        let mut seen = 0;
+        for u in 0..csr.order() as u32 {
+            for (v, id) in csr.neighbors_with_eid(u).unwrap() {
+                let first = edges.iter().position(|&e| e == Edge(u, v)).unwrap();
+                assert_eq!(first as u64, id, "({}, {})", u, v);
+                seen += 1;
+            }
+        }
+        let mut distinct = edges.to_vec();
+        distinct.sort_unstable();
+        distinct.dedup();
+        assert_eq!(distinct.len(), seen);
+    }
+
+    #[test]
+    fn edge_ids_follow_the_neighbors()
+    {
+        let mut rng = StdRng::seed_from_u64(8);
+        let edges = edges(&mut rng);
+        let csr = CSR::from_edges_with_ids(edges.clone());
+        check_edge_ids(&csr, &edges);
+
+        let mut buf = Vec::new();
+        csr.write_to(&mut buf).unwrap();
+        check_edge_ids(&CSR::read_from(&buf[..]).unwrap(), &edges);
+    }
+
+    #[test]
+    fn edges_convert_from_narrower_ids()
+    {
+        assert_eq!(Edge::from([1u8, 2]), Edge(1, 2));
+        assert_eq!(Edge::from((1u16, 2u16)), Edge(1, 2));
+        assert_eq!(Edge::<u64>::from([1u64 << 40, 2]), Edge(1 << 40, 2));
+
+        let csr = CSR::from(vec![[0u8, 1], [1, 0]]);
+        assert_eq!(csr.neighbors(0).collect::<Vec<u32>>(), [1]);
+        let csr = CSR::from(vec![(0u64, 1u64)]);
+        assert_eq!(csr.neighbors(0).collect::<Vec<u64>>(), [1]);
     }
 }
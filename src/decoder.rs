@@ -28,20 +28,28 @@
 // therefore achieve a speed up.
 //
 
-use std::{hint::unreachable_unchecked, iter, mem::MaybeUninit, ops::Range, slice::Iter};
+use std::{hint::unreachable_unchecked, mem::MaybeUninit, ops::Range, slice};
 
-fn first_edge(source: u32, bytes: &mut Iter<'_, u8>) -> Option<u32>
+use crate::{
+    checked::{self, Checked, Cursor, Sink},
+    encoder::width_bits,
+    vertex::VertexId,
+};
+
+/// Reads the first neighbor, which all codecs store the same way, see
+/// `encoder::first_edge`.
+pub fn first_edge(source: u64, bytes: &mut slice::Iter<'_, u8>) -> Option<u64>
 {
     match bytes.next() {
         None => None,
         Some(&first_byte) => {
-            let mut diff = (first_byte & 0x3f) as u32;
+            let mut diff = (first_byte & 0x3f) as u64;
             if (first_byte & 0x80) > 0 {
                 let mut shift_amount = 6;
                 loop {
                     match bytes.next() {
                         Some(&b) => {
-                            diff |= ((b & 0x7f) as u32) << shift_amount;
+                            diff |= ((b & 0x7f) as u64) << shift_amount;
                             if (b & 0x80) > 0 {
                                 shift_amount += 7;
                             }
@@ -63,15 +71,24 @@ fn first_edge(source: u32, bytes: &mut Iter<'_, u8>) -> Option<u32>
     }
 }
 
-pub struct Group
+/// The byte width of the diffs and the run length of a group.
+#[inline]
+pub fn parse_header<V: VertexId>(header: u8) -> (usize, usize)
+{
+    let num_bytes = ((header & (V::BYTES as u8 - 1)) + 1) as usize;
+    let run_length = ((header >> width_bits::<V>()) + 1) as usize;
+    (num_bytes, run_length)
+}
+
+pub struct Group<V: VertexId>
 {
     range: Range<usize>,
-    data: [MaybeUninit<u32>; 64],
+    data: [MaybeUninit<V>; 64],
 }
 
-impl Iterator for Group
+impl<V: VertexId> Iterator for Group<V>
 {
-    type Item = u32;
+    type Item = V;
 
     fn next(&mut self) -> Option<Self::Item>
     {
@@ -80,23 +97,38 @@ impl Iterator for Group
     }
 }
 
-pub struct Groups<'a>
+pub struct Groups<'a, V: VertexId>
 {
-    prev_edge: u32,
-    bytes: Iter<'a, u8>,
+    prev_edge: V,
+    bytes: slice::Iter<'a, u8>,
+}
+
+/// Reads big endian diffs of `N` bytes.
+#[inline]
+fn unpack<V: VertexId, const N: usize>(left: &[u8], buf: &mut [MaybeUninit<V>; 64])
+{
+    for (chunk, dst) in (unsafe { left.as_chunks_unchecked::<N>() })
+        .iter()
+        .zip(buf.iter_mut())
+    {
+        let mut diff = 0u64;
+        for &byte in chunk {
+            diff = (diff << 8) | byte as u64;
+        }
+        dst.write(V::from_u64(diff));
+    }
 }
 
-impl<'a> Iterator for Groups<'a>
+impl<'a, V: VertexId> Iterator for Groups<'a, V>
 {
-    type Item = Group;
+    type Item = Group<V>;
 
     fn next(&mut self) -> Option<Self::Item>
     {
         match self.bytes.next() {
             None => None,
             Some(&header) => {
-                let num_bytes = ((header & 0x3) + 1) as usize;
-                let run_length = ((header >> 2) + 1) as usize;
+                let (num_bytes, run_length) = parse_header::<V>(header);
                 let (left, right) = unsafe {
                     self.bytes
                         .as_slice()
@@ -105,59 +137,29 @@ impl<'a> Iterator for Groups<'a>
 
                 self.bytes = right.iter();
 
-                let mut buf: [MaybeUninit<u32>; 64] =
+                let mut buf: [MaybeUninit<V>; 64] =
                     unsafe { MaybeUninit::uninit().assume_init() };
 
                 match num_bytes {
-                    1 => {
-                        for (&byte, dst) in left.iter().zip(buf.iter_mut()) {
-                            dst.write(byte as u32);
-                        }
-                    }
-                    2 => {
-                        for (chunk, dst) in (unsafe { left.as_chunks_unchecked::<2>() })
-                            .iter()
-                            .zip(buf.iter_mut())
-                        {
-                            let mut diff = (chunk[0] as u32) << 8;
-                            diff |= chunk[1] as u32;
-                            dst.write(diff);
-                        }
-                    }
-                    3 => {
-                        for (chunk, dst) in (unsafe { left.as_chunks_unchecked::<3>() })
-                            .iter()
-                            .zip(buf.iter_mut())
-                        {
-                            let mut diff = (chunk[0] as u32) << 16;
-                            diff |= (chunk[1] as u32) << 8;
-                            diff |= chunk[2] as u32;
-                            dst.write(diff);
-                        }
-                    }
-                    4 => {
-                        for (chunk, dst) in (unsafe { left.as_chunks_unchecked::<4>() })
-                            .iter()
-                            .zip(buf.iter_mut())
-                        {
-                            let mut diff = (chunk[0] as u32) << 24;
-                            diff |= (chunk[1] as u32) << 16;
-                            diff |= (chunk[2] as u32) << 8;
-                            diff |= chunk[3] as u32;
-                            dst.write(diff);
-                        }
-                    }
+                    1 => unpack::<V, 1>(left, &mut buf),
+                    2 => unpack::<V, 2>(left, &mut buf),
+                    3 => unpack::<V, 3>(left, &mut buf),
+                    4 => unpack::<V, 4>(left, &mut buf),
+                    5 => unpack::<V, 5>(left, &mut buf),
+                    6 => unpack::<V, 6>(left, &mut buf),
+                    7 => unpack::<V, 7>(left, &mut buf),
+                    8 => unpack::<V, 8>(left, &mut buf),
                     _ => unsafe { unreachable_unchecked() },
                 }
 
-                let mut p_edge = self.prev_edge;
+                let mut p_edge = self.prev_edge.to_u64();
                 for item in buf.iter_mut().take(run_length) {
-                    let diff = unsafe { item.assume_init() };
+                    let diff = unsafe { item.assume_init() }.to_u64();
                     let edge = p_edge + diff;
                     p_edge = edge;
-                    item.write(edge);
+                    item.write(V::from_u64(edge));
                 }
-                self.prev_edge = p_edge;
+                self.prev_edge = V::from_u64(p_edge);
 
                 Some(Group {
                     range: 0..run_length,
@@ -168,46 +170,189 @@ impl<'a> Iterator for Groups<'a>
     }
 }
 
-pub fn decode(source: u32, bytes: &[u8]) -> impl Iterator<Item = u32> + '_
+/// The neighbors of a block.
+pub struct Iter<'a, V: VertexId>
 {
-    let mut bytes = bytes.iter();
-    first_edge(source, &mut bytes)
-        .into_iter()
-        .flat_map(move |edge| {
-            iter::once(edge).chain(
-                (Groups {
-                    prev_edge: edge,
-                    bytes: bytes.clone(),
-                })
-                .flatten(),
-            )
-        })
+    first: Option<V>,
+    groups: Groups<'a, V>,
+    group: Option<Group<V>>,
 }
 
-pub fn count(source: u32, bytes: &[u8]) -> usize
+impl<'a, V: VertexId> Iterator for Iter<'a, V>
+{
+    type Item = V;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item>
+    {
+        if let Some(first) = self.first.take() {
+            return Some(first);
+        }
+        loop {
+            if let Some(edge) = self.group.as_mut().and_then(Group::next) {
+                return Some(edge);
+            }
+            self.group = Some(self.groups.next()?);
+        }
+    }
+}
+
+pub fn decode<V: VertexId>(source: V, bytes: &[u8]) -> Iter<'_, V>
 {
     let mut bytes = bytes.iter();
+    let first = first_edge(source.to_u64(), &mut bytes).map(V::from_u64);
+    Iter {
+        first,
+        groups: Groups {
+            prev_edge: first.unwrap_or_default(),
+            bytes,
+        },
+        group: None,
+    }
+}
 
-    match first_edge(source, &mut bytes) {
-        None => return 0,
-        Some(_) => {}
+/// The neighbors from the group that starts at `bytes`, `prev_edge` is the
+/// neighbor before it.
+pub fn decode_from<V: VertexId>(prev_edge: V, bytes: &[u8]) -> Iter<'_, V>
+{
+    Iter {
+        first: None,
+        groups: Groups {
+            prev_edge,
+            bytes: bytes.iter(),
+        },
+        group: None,
     }
+}
+
+/// The groups that hold the edges at the multiples of `every`, as the index
+/// of their first edge, their byte offset and the neighbor before them.
+/// The first edge is not in a group and never included. `every` must be
+/// larger than a group.
+pub fn skips<V: VertexId>(source: V, bytes: &[u8], every: usize) -> Vec<(usize, usize, V)>
+{
+    let mut iter = bytes.iter();
+    let first = match first_edge(source.to_u64(), &mut iter) {
+        Some(first) => V::from_u64(first),
+        None => return vec![],
+    };
+
+    let mut groups = Groups {
+        prev_edge: first,
+        bytes: iter,
+    };
 
-    let mut acc = 1;
+    let mut skips = Vec::new();
+    let mut index = 1;
+    let mut next = every;
 
     loop {
-        match bytes.next() {
+        let offset = bytes.len() - groups.bytes.as_slice().len();
+        let prev_edge = groups.prev_edge;
+        match groups.next() {
             None => break,
-            Some(&header) => {
-                let num_bytes = ((header & 0x3) + 1) as usize;
-                let run_length = ((header >> 2) + 1) as usize;
-                let (_, right) =
-                    unsafe { bytes.as_slice().split_at_unchecked(num_bytes * run_length) };
-                bytes = right.iter();
-                acc += run_length;
+            Some(group) => {
+                let run_length = group.range.len();
+                if index + run_length > next {
+                    skips.push((index, offset, prev_edge));
+                    next += every;
+                }
+                index += run_length;
             }
         }
     }
 
-    acc
+    skips
+}
+
+/// Reads big endian diffs of `N` bytes and appends their prefix sums
+/// starting from `prev_edge`.
+#[inline]
+fn unpack_into<V: VertexId, const N: usize>(group: &[u8], prev_edge: &mut u64, out: &mut Vec<V>)
+{
+    out.extend(
+        (unsafe { group.as_chunks_unchecked::<N>() })
+            .iter()
+            .map(|chunk| {
+                let mut diff = 0u64;
+                for &byte in chunk {
+                    diff = (diff << 8) | byte as u64;
+                }
+                *prev_edge += diff;
+                V::from_u64(*prev_edge)
+            }),
+    );
+}
+
+/// Appends the `run_length` neighbors of the group of `num_bytes` diffs at
+/// the start of `bytes`, which runs to the end of the block.
+pub type Unpack<V> = fn(bytes: &[u8], num_bytes: usize, run_length: usize, prev_edge: &mut u64, out: &mut Vec<V>);
+
+pub fn unpack_scalar<V: VertexId>(
+    bytes: &[u8],
+    num_bytes: usize,
+    run_length: usize,
+    prev_edge: &mut u64,
+    out: &mut Vec<V>,
+)
+{
+    let group = &bytes[..num_bytes * run_length];
+    match num_bytes {
+        1 => unpack_into::<V, 1>(group, prev_edge, out),
+        2 => unpack_into::<V, 2>(group, prev_edge, out),
+        3 => unpack_into::<V, 3>(group, prev_edge, out),
+        4 => unpack_into::<V, 4>(group, prev_edge, out),
+        5 => unpack_into::<V, 5>(group, prev_edge, out),
+        6 => unpack_into::<V, 6>(group, prev_edge, out),
+        7 => unpack_into::<V, 7>(group, prev_edge, out),
+        8 => unpack_into::<V, 8>(group, prev_edge, out),
+        _ => unsafe { unreachable_unchecked() },
+    }
+}
+
+fn decode_block_into<V: VertexId>(source: V, bytes: &[u8], out: &mut Vec<V>, unpack: Unpack<V>)
+{
+    let mut iter = bytes.iter();
+    let mut prev_edge = match first_edge(source.to_u64(), &mut iter) {
+        Some(edge) => edge,
+        None => return,
+    };
+    out.push(V::from_u64(prev_edge));
+
+    let mut bytes = iter.as_slice();
+    while let Some((&header, rest)) = bytes.split_first() {
+        let (num_bytes, run_length) = parse_header::<V>(header);
+        unpack(rest, num_bytes, run_length, &mut prev_edge, out);
+        bytes = unsafe { rest.get_unchecked(num_bytes * run_length..) };
+    }
+}
+
+/// Like `decode` but appends the neighbors to `out` a group at a time,
+/// without the per-group buffer and the nested iterators. With the `simd`
+/// feature, `u32` groups are unpacked with the best instructions the CPU
+/// supports, see `VertexId::unpack_groups`.
+pub fn decode_into<V: VertexId>(source: V, bytes: &[u8], out: &mut Vec<V>)
+{
+    decode_block_into(source, bytes, out, V::unpack_groups())
+}
+
+/// Like `decode_into` but checks every group against the end of the
+/// bytes, see `checked`.
+pub fn try_decode_into<V: VertexId>(source: V, mut bytes: Cursor<'_>, sink: &mut Sink<'_, V>) -> Checked<()>
+{
+    let offset = bytes.offset();
+    let mut prev_edge = bytes.first_edge(source.to_u64())?;
+    sink.push(prev_edge, offset)?;
+
+    while !bytes.is_empty() {
+        let (num_bytes, run_length) = parse_header::<V>(bytes.byte()?);
+        let group = bytes.take((num_bytes * run_length) as u64)?;
+        for (i, chunk) in group.rest().chunks_exact(num_bytes).enumerate() {
+            let offset = group.offset() + i * num_bytes;
+            let diff = chunk.iter().fold(0u64, |diff, &byte| diff << 8 | byte as u64);
+            prev_edge = checked::add(prev_edge, diff, offset)?;
+            sink.push(prev_edge, offset)?;
+        }
+    }
+    Ok(())
 }
@@ -1,7 +1,12 @@
+use crate::vertex::VertexId;
+
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
-pub struct Edge(pub u32, pub u32);
+pub struct Edge<V = u32>(pub V, pub V);
 
-impl<T: Into<u32>> From<[T; 2]> for Edge
+// Edges with `u32` ids can be built from any type that converts into `u32`,
+// e.g. `[u8; 2]`, those with `u64` ids only from `u64`, so that the id type
+// of `CSR::from(vec![[0u32, 1]])` is still inferred.
+impl<T: Into<u32>> From<[T; 2]> for Edge<u32>
 {
     fn from(arr: [T; 2]) -> Self
     {
@@ -10,7 +15,16 @@ impl<T: Into<u32>> From<[T; 2]> for Edge
     }
 }
 
-impl<T: Into<u32>> From<(T, T)> for Edge
+impl From<[u64; 2]> for Edge<u64>
+{
+    fn from(arr: [u64; 2]) -> Self
+    {
+        let [u, v] = arr;
+        Self(u, v)
+    }
+}
+
+impl<T: Into<u32>> From<(T, T)> for Edge<u32>
 {
     fn from(tup: (T, T)) -> Self
     {
@@ -19,17 +33,26 @@ impl<T: Into<u32>> From<(T, T)> for Edge
     }
 }
 
-impl From<Edge> for (u32, u32)
+impl From<(u64, u64)> for Edge<u64>
+{
+    fn from(tup: (u64, u64)) -> Self
+    {
+        let (u, v) = tup;
+        Self(u, v)
+    }
+}
+
+impl<V: VertexId> From<Edge<V>> for (V, V)
 {
-    fn from(edge: Edge) -> Self
+    fn from(edge: Edge<V>) -> Self
     {
         (edge.0, edge.1)
     }
 }
 
-impl From<Edge> for [u32; 2]
+impl<V: VertexId> From<Edge<V>> for [V; 2]
 {
-    fn from(edge: Edge) -> Self
+    fn from(edge: Edge<V>) -> Self
     {
         [edge.0, edge.1]
     }
@@ -0,0 +1,309 @@
+// glzip is a graph compression library for graph learning systems
+// Copyright (C) 2022 Jacob Konrad
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Plain text edge lists, one `source target` pair per line separated by
+//! whitespace or a comma, as in SNAP and OGB raw dumps. Lines starting with
+//! `#` or `%` and blank lines are skipped, columns after the second are
+//! ignored.
+
+use std::{fmt, io::BufRead, marker::PhantomData};
+
+use crate::{error::Error, vertex::VertexId, Edge, CSR};
+
+/// What went wrong on a line of a text graph file.
+#[derive(Debug)]
+pub enum ParseErrorKind
+{
+    /// The line has fewer than two columns.
+    MissingColumn,
+    /// A column is not a valid vertex id.
+    InvalidVertex(String),
+    /// A banner or size line that is malformed or describes an unsupported format.
+    InvalidHeader(String),
+    /// A column is not a valid numeric value.
+    InvalidValue(String),
+    /// A vertex id outside of the declared dimensions.
+    VertexOutOfRange(u64),
+    /// The file ends with another number of entries than its size line
+    /// declares.
+    EntryCount
+    {
+        declared: u64,
+        found: u64,
+    },
+}
+
+/// An error while reading a text graph file, with the 1-based line it
+/// occurred on. I/O errors are not parse errors, they are [`Error::Io`].
+#[derive(Debug)]
+pub struct ParseError
+{
+    pub line: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl fmt::Display for ParseError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match &self.kind {
+            ParseErrorKind::MissingColumn => write!(f, "line {}: expected two vertex ids", self.line),
+            ParseErrorKind::InvalidVertex(s) => {
+                write!(f, "line {}: invalid vertex id {:?}", self.line, s)
+            }
+            ParseErrorKind::InvalidHeader(s) => write!(f, "line {}: invalid header {:?}", self.line, s),
+            ParseErrorKind::InvalidValue(s) => write!(f, "line {}: invalid value {:?}", self.line, s),
+            ParseErrorKind::VertexOutOfRange(id) => {
+                write!(f, "line {}: vertex id {} out of range", self.line, id)
+            }
+            ParseErrorKind::EntryCount { declared, found } => {
+                write!(f, "line {}: {} entries rather than the {} declared", self.line, found, declared)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// An iterator over the edges of a text edge list.
+///
+/// # Examples
+///
+/// ```
+/// use glzip::{edge_list::EdgeList, Edge};
+///
+/// let text = "# FromNodeId ToNodeId\n0\t1\n0 2\n\n% comment\n2,1\n";
+///
+/// let edges: Vec<Edge> = EdgeList::new(text.as_bytes())
+///     .collect::<Result<_, _>>()
+///     .unwrap();
+///
+/// assert_eq!(vec![Edge(0, 1), Edge(0, 2), Edge(2, 1)], edges);
+/// ```
+pub struct EdgeList<R, V = u32>
+{
+    reader: R,
+    buf: String,
+    line: usize,
+    skip_header: bool,
+    marker: PhantomData<V>,
+}
+
+impl<R: BufRead, V: VertexId> EdgeList<R, V>
+{
+    pub fn new(reader: R) -> Self
+    {
+        Self {
+            reader,
+            buf: String::new(),
+            line: 0,
+            skip_header: false,
+            marker: PhantomData,
+        }
+    }
+
+    /// Treat the first line that is not a comment as a header and skip it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glzip::{edge_list::EdgeList, Edge};
+    ///
+    /// let text = "src,dst\n0,1\n1,0\n";
+    ///
+    /// let edges: Vec<Edge> = EdgeList::new(text.as_bytes())
+    ///     .with_header(true)
+    ///     .collect::<Result<_, _>>()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(vec![Edge(0, 1), Edge(1, 0)], edges);
+    /// ```
+    pub fn with_header(mut self, header: bool) -> Self
+    {
+        self.skip_header = header;
+        self
+    }
+
+    fn error(&self, kind: ParseErrorKind) -> ParseError
+    {
+        ParseError {
+            line: self.line,
+            kind,
+        }
+    }
+
+    fn parse_vertex(&self, column: Option<&str>) -> Result<V, ParseError>
+    {
+        let column = column.ok_or_else(|| self.error(ParseErrorKind::MissingColumn))?;
+        column
+            .parse()
+            .map_err(|_| self.error(ParseErrorKind::InvalidVertex(column.to_string())))
+    }
+}
+
+impl<R: BufRead, V: VertexId> Iterator for EdgeList<R, V>
+{
+    type Item = Result<Edge<V>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item>
+    {
+        loop {
+            self.buf.clear();
+            self.line += 1;
+            match self.reader.read_line(&mut self.buf) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(err) => return Some(Err(Error::Io(err))),
+            }
+
+            let line = self.buf.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with('%') {
+                continue;
+            }
+
+            if self.skip_header {
+                self.skip_header = false;
+                continue;
+            }
+
+            let mut columns = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|s| !s.is_empty());
+
+            let edge = self
+                .parse_vertex(columns.next())
+                .and_then(|u| self.parse_vertex(columns.next()).map(|v| Edge(u, v)));
+
+            return Some(edge.map_err(Error::Parse));
+        }
+    }
+}
+
+impl<V: VertexId> CSR<V>
+{
+    /// Builds a graph from a text edge list, see [`EdgeList`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glzip::{edge_list::ParseError, CSR, Error};
+    ///
+    /// let csr: CSR = CSR::from_edge_list("0 1\n0 2\n1 0\n2 1\n".as_bytes(), false).unwrap();
+    ///
+    /// assert_eq!(vec![1,2], csr.neighbors(0).collect::<Vec<_>>());
+    ///
+    /// let err = CSR::<u32>::from_edge_list("0 1\n0 x\n".as_bytes(), false).err().unwrap();
+    ///
+    /// assert!(matches!(err, Error::Parse(ParseError { line: 2, .. })));
+    /// ```
+    pub fn from_edge_list<R: BufRead>(reader: R, header: bool) -> Result<Self, Error>
+    {
+        Self::try_from_edges(EdgeList::new(reader).with_header(header)).map_err(Error::flatten)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::io::{self, BufReader, Read};
+
+    use super::*;
+
+    /// A reader that fails after its first line.
+    struct Failing(bool);
+
+    impl Read for Failing
+    {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>
+        {
+            if std::mem::replace(&mut self.0, true) {
+                return Err(io::Error::other("disk on fire"));
+            }
+            let line = b"%%MatrixMarket matrix coordinate pattern general\n";
+            buf[..line.len()].copy_from_slice(line);
+            Ok(line.len())
+        }
+    }
+
+    #[test]
+    fn io_errors_are_not_parse_errors()
+    {
+        let err = CSR::<u32>::from_edge_list(BufReader::new(Failing(false)), false).err();
+        assert!(matches!(err, Some(Error::Io(_))));
+        let err = CSR::<u32>::from_matrix_market(BufReader::new(Failing(false))).err();
+        assert!(matches!(err, Some(Error::Io(_))));
+    }
+
+    // The line and kind of the first error in `text`.
+    fn error(text: &str) -> (usize, ParseErrorKind)
+    {
+        let edges: Result<Vec<Edge>, Error> = EdgeList::new(text.as_bytes()).collect();
+        match edges {
+            Err(Error::Parse(ParseError { line, kind })) => (line, kind),
+            Err(err) => panic!("{}", err),
+            Ok(_) => panic!("{:?} parsed", text),
+        }
+    }
+
+    #[test]
+    fn bad_tokens_fail_on_their_line()
+    {
+        match error("0 1\n1 2\n2 x3\n") {
+            (3, ParseErrorKind::InvalidVertex(s)) => assert_eq!("x3", s),
+            (line, kind) => panic!("line {}: {:?}", line, kind),
+        }
+        assert!(matches!(error("-1 2\n"), (1, ParseErrorKind::InvalidVertex(_))));
+    }
+
+    #[test]
+    fn missing_targets_fail_on_their_line()
+    {
+        assert!(matches!(error("0 1\n4\n"), (2, ParseErrorKind::MissingColumn)));
+        assert!(matches!(error("0 1\n4,\n"), (2, ParseErrorKind::MissingColumn)));
+    }
+
+    #[test]
+    fn skipped_lines_are_counted()
+    {
+        let text = "# comment\n\n% comment\n   \n0 1\n\n# 1 x\n1 x\n";
+        assert!(matches!(error(text), (8, ParseErrorKind::InvalidVertex(_))));
+
+        let edges: Vec<Edge> = EdgeList::new("src dst\n# comment\n0 1\n".as_bytes())
+            .with_header(true)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(vec![Edge(0, 1)], edges);
+        let edges: Result<Vec<Edge>, Error> = EdgeList::new("# comment\nsrc dst\n0 1\n0\n".as_bytes())
+            .with_header(true)
+            .collect();
+        assert!(matches!(edges, Err(Error::Parse(ParseError { line: 4, kind: ParseErrorKind::MissingColumn }))));
+    }
+
+    #[test]
+    fn ids_that_overflow_fail_on_their_line()
+    {
+        match error("0 1\n0 4294967296\n") {
+            (2, ParseErrorKind::InvalidVertex(s)) => assert_eq!("4294967296", s),
+            (line, kind) => panic!("line {}: {:?}", line, kind),
+        }
+
+        // The same id fits u64.
+        let edges: Vec<Edge<u64>> = EdgeList::new("0 1\n0 4294967296\n".as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(Edge(0, 1 << 32), edges[1]);
+    }
+}
@@ -28,9 +28,14 @@
 // therefore achieve a speed up.
 //
 
-use std::{iter, iter::Peekable, mem::MaybeUninit};
+use std::{iter, iter::Peekable};
 
-fn first_edge(bytes: &mut Vec<u8>, source: u32, target: u32)
+use crate::vertex::VertexId;
+
+/// Writes the first neighbor as its difference from the source, 6 bits and a
+/// sign in the first byte and 7 bits in each following byte. All codecs
+/// start a block this way.
+pub fn first_edge(bytes: &mut Vec<u8>, source: u64, target: u64)
 {
     let mut diff = target.abs_diff(source);
     let mut first_byte = (diff as u8) & 0x3f;
@@ -60,92 +65,76 @@ fn first_edge(bytes: &mut Vec<u8>, source: u32, target: u32)
     }));
 }
 
-const ONE_BYTE: u32 = 256; // 0xff + 1
-const TWO_BYTES: u32 = 65536; // 0xffff + 1
-const THREE_BYTES: u32 = 16777216; // 0xffffff + 1
+/// The number of low bits of a group header that hold the byte width of the
+/// diffs in the group, 2 for `u32` ids (1 to 4 bytes) and 3 for `u64` ids (1
+/// to 8 bytes). The remaining bits hold the run length.
+#[inline]
+pub const fn width_bits<V: VertexId>() -> u32
+{
+    V::BYTES.trailing_zeros()
+}
+
+/// The maximum number of diffs in a group, 64 for `u32` ids and 32 for `u64` ids.
+#[inline]
+pub const fn max_run<V: VertexId>() -> usize
+{
+    1 << (8 - width_bits::<V>())
+}
+
+/// The number of bytes needed to store `diff`.
+#[inline]
+fn byte_width(diff: u64) -> usize
+{
+    std::cmp::max(1, (71 - diff.leading_zeros() as usize) / 8)
+}
 
 #[inline]
-fn parse_group_and_extend_vec<I, H, P, F, const N: usize>(
-    bytes: &mut Vec<u8>,
-    diffs: &mut Peekable<I>,
-    header: H,
-    pred: P,
-    f: F,
-) where
-    I: Iterator<Item = u32>,
-    H: Fn(u8) -> u8,
-    P: Fn(&u32) -> bool + Copy,
-    F: Fn(u32) -> [u8; N],
+fn parse_group_and_extend_vec<V, I, const N: usize>(bytes: &mut Vec<u8>, diffs: &mut Peekable<I>)
+where
+    V: VertexId,
+    I: Iterator<Item = u64>,
 {
-    let mut j = 0usize;
-    let mut buf: [MaybeUninit<u32>; 64] = unsafe { MaybeUninit::uninit().assume_init() };
+    let header = bytes.len();
+    bytes.push(0);
 
-    for (i, dst) in buf.iter_mut().enumerate() {
-        match diffs.next_if(pred) {
+    let mut run_length = 0usize;
+    while run_length < max_run::<V>() {
+        match diffs.next_if(|&d| byte_width(d) == N) {
             Some(d) => {
-                dst.write(d);
-            }
-            None => {
-                j = i - 1;
-                break;
+                bytes.extend_from_slice(&d.to_be_bytes()[8 - N..]);
+                run_length += 1;
             }
+            None => break,
         }
     }
 
-    bytes.reserve((j + 1) * N);
-    bytes.push(header(j as u8));
-    for md in buf.iter().take(j + 1) {
-        let d = unsafe { md.assume_init() };
-        let bs = f(d);
-        bytes.extend(bs.into_iter());
-    }
+    bytes[header] = (((run_length - 1) as u8) << width_bits::<V>()) | (N - 1) as u8;
 }
 
-fn next_group<I>(bytes: &mut Vec<u8>, diffs: &mut Peekable<I>)
+fn next_group<V, I>(bytes: &mut Vec<u8>, diffs: &mut Peekable<I>)
 where
-    I: Iterator<Item = u32>,
+    V: VertexId,
+    I: Iterator<Item = u64>,
 {
-    match diffs.peek() {
+    match diffs.peek().map(|&d| byte_width(d)) {
         None => {}
-        Some(&diff) if diff < ONE_BYTE => {
-            parse_group_and_extend_vec(bytes, diffs, |j| j << 2, |&d| d < ONE_BYTE, |d| [d as u8]);
-        }
-        Some(&diff) if diff < TWO_BYTES => {
-            parse_group_and_extend_vec(
-                bytes,
-                diffs,
-                |j| 1u8 | (j << 2),
-                |d| (ONE_BYTE..TWO_BYTES).contains(d),
-                |d| [(d >> 8) as u8, d as u8],
-            );
-        }
-        Some(&diff) if diff < THREE_BYTES => {
-            parse_group_and_extend_vec(
-                bytes,
-                diffs,
-                |j| 2u8 | (j << 2),
-                |d| (TWO_BYTES..THREE_BYTES).contains(d),
-                |d| [(d >> 16) as u8, (d >> 8) as u8, d as u8],
-            );
-        }
-        Some(_) => {
-            parse_group_and_extend_vec(
-                bytes,
-                diffs,
-                |j| 3u8 | (j << 2),
-                |&d| d >= THREE_BYTES,
-                |d| [(d >> 24) as u8, (d >> 16) as u8, (d >> 8) as u8, d as u8],
-            );
-        }
+        Some(1) => parse_group_and_extend_vec::<V, I, 1>(bytes, diffs),
+        Some(2) => parse_group_and_extend_vec::<V, I, 2>(bytes, diffs),
+        Some(3) => parse_group_and_extend_vec::<V, I, 3>(bytes, diffs),
+        Some(4) => parse_group_and_extend_vec::<V, I, 4>(bytes, diffs),
+        Some(5) => parse_group_and_extend_vec::<V, I, 5>(bytes, diffs),
+        Some(6) => parse_group_and_extend_vec::<V, I, 6>(bytes, diffs),
+        Some(7) => parse_group_and_extend_vec::<V, I, 7>(bytes, diffs),
+        Some(_) => parse_group_and_extend_vec::<V, I, 8>(bytes, diffs),
     }
 }
 
-struct Diffs<I: Iterator<Item = u32>>
+struct Diffs<I: Iterator<Item = u64>>
 {
     iter: Peekable<I>,
 }
 
-impl<I: Iterator<Item = u32>> Diffs<I>
+impl<I: Iterator<Item = u64>> Diffs<I>
 {
     fn new(iter: I) -> Self
     {
@@ -155,9 +144,9 @@ impl<I: Iterator<Item = u32>> Diffs<I>
     }
 }
 
-impl<I: Iterator<Item = u32>> Iterator for Diffs<I>
+impl<I: Iterator<Item = u64>> Iterator for Diffs<I>
 {
-    type Item = u32;
+    type Item = u64;
 
     fn next(&mut self) -> Option<Self::Item>
     {
@@ -167,22 +156,73 @@ impl<I: Iterator<Item = u32>> Iterator for Diffs<I>
     }
 }
 
-pub fn encode<I>(bytes: &mut Vec<u8>, source: u32, into_edges: I)
+pub fn encode<V, I>(bytes: &mut Vec<u8>, source: V, into_edges: I)
 where
-    I: IntoIterator<Item = u32>,
+    V: VertexId,
+    I: IntoIterator<Item = V>,
 {
-    let mut edges = into_edges.into_iter().peekable();
+    let mut edges = into_edges.into_iter().map(V::to_u64).peekable();
 
     let edge = match edges.peek() {
         Some(&e) => e,
         None => return,
     };
 
-    first_edge(bytes, source, edge);
+    first_edge(bytes, source.to_u64(), edge);
 
     let mut diffs = Diffs::new(edges).peekable();
 
     while diffs.peek().is_some() {
-        next_group(bytes, &mut diffs);
+        next_group::<V, _>(bytes, &mut diffs);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::decoder;
+
+    fn round_trip<V: VertexId>(edges: &[V])
+    {
+        let source = V::from_u64(0);
+        let mut bytes = Vec::new();
+        encode(&mut bytes, source, edges.iter().copied());
+
+        assert_eq!(edges, decoder::decode(source, &bytes).collect::<Vec<_>>());
+        let mut out = Vec::new();
+        decoder::decode_into(source, &bytes, &mut out);
+        assert_eq!(edges, out);
+    }
+
+    // Groups that fill all of their run slots used to wrap the run length
+    // to zero and lose their diffs.
+    #[test]
+    fn full_groups()
+    {
+        for len in [max_run::<u32>(), max_run::<u32>() + 1, 2 * max_run::<u32>() + 1, 200] {
+            // A first edge and then `len` diffs of 1, 2, 3 and 4 bytes.
+            for diff in [1u32, 300, 70_000, 1 << 24] {
+                let edges: Vec<u32> = (0..=len as u32).map(|i| i * diff).collect();
+                round_trip(&edges);
+            }
+        }
+        for len in [max_run::<u64>(), max_run::<u64>() + 1, 100] {
+            for diff in [1u64, 1 << 20, 1 << 40] {
+                let edges: Vec<u64> = (0..=len as u64).map(|i| i * diff).collect();
+                round_trip(&edges);
+            }
+        }
+    }
+
+    #[test]
+    fn header_holds_the_full_run()
+    {
+        let edges: Vec<u32> = (0..=64).collect();
+        let mut bytes = Vec::new();
+        encode(&mut bytes, 0u32, edges.iter().copied());
+        // The first edge, then a single header for all 64 one byte diffs.
+        assert_eq!(1 + 1 + 64, bytes.len());
+        assert_eq!((1, 64), decoder::parse_header::<u32>(bytes[1]));
     }
 }
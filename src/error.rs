@@ -0,0 +1,291 @@
+// glzip is a graph compression library for graph learning systems
+// Copyright (C) 2022 Jacob Konrad
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The errors of glzip.
+
+use std::{collections::TryReserveError, convert::Infallible, error, fmt, io};
+
+use crate::edge_list::ParseError;
+
+/// An error while loading or building a graph, generic over the error `E`
+/// of the edges passed by the caller, e.g. to [`CSR::try_from_edges`].
+///
+/// [`CSR::try_from_edges`]: crate::CSR::try_from_edges
+#[derive(Debug)]
+pub enum Error<E = Infallible>
+{
+    Io(io::Error),
+    /// A line of a text graph file is malformed.
+    Parse(ParseError),
+    /// The header of a binary file can't be read, e.g. it's not a glzip or
+    /// `.npy` file, it's corrupt or its format version, codec or flags are
+    /// unknown.
+    Header(String),
+    /// The header is valid but the graph was written with another vertex id
+    /// type than it's read as, e.g. `u64` ids read as `u32`.
+    CodecMismatch(&'static str),
+    /// The options of a [`CsrBuilder`](crate::CsrBuilder) can't be combined,
+    /// e.g. block encoding with references.
+    InvalidOptions(&'static str),
+    /// A vertex id that doesn't fit into the vertex id type, or that is too
+    /// large for a graph in memory, with the index of its edge if known.
+    IdOverflow
+    {
+        edge: Option<u64>,
+        id: i128,
+    },
+    /// An edge, or a vertex if `target` is `None`, that comes before the
+    /// one pushed before it, see [`StreamBuilder`](crate::builder::StreamBuilder).
+    Unsorted
+    {
+        source: u64,
+        target: Option<u64>,
+    },
+    /// The graph doesn't fit into memory.
+    Capacity(TryReserveError),
+    /// The bytes of a vertex don't decode, see [`CSR::validate`].
+    ///
+    /// [`CSR::validate`]: crate::CSR::validate
+    Corrupt(DecodeError),
+    /// A vertex doesn't decode to the neighbors it was encoded from, see
+    /// [`CsrBuilder::build_verified`](crate::CsrBuilder::build_verified).
+    Encode(EncodeError),
+    /// The error of an edge passed by the caller.
+    Source(E),
+}
+
+impl<E> Error<E>
+{
+    fn map_source<F, G>(self, f: G) -> Error<F>
+    where
+        G: FnOnce(E) -> Error<F>,
+    {
+        match self {
+            Error::Io(err) => Error::Io(err),
+            Error::Parse(err) => Error::Parse(err),
+            Error::Header(msg) => Error::Header(msg),
+            Error::CodecMismatch(msg) => Error::CodecMismatch(msg),
+            Error::InvalidOptions(msg) => Error::InvalidOptions(msg),
+            Error::IdOverflow { edge, id } => Error::IdOverflow { edge, id },
+            Error::Unsorted { source, target } => Error::Unsorted { source, target },
+            Error::Capacity(err) => Error::Capacity(err),
+            Error::Corrupt(err) => Error::Corrupt(err),
+            Error::Encode(err) => Error::Encode(err),
+            Error::Source(err) => f(err),
+        }
+    }
+}
+
+impl<E: Into<Error>> Error<E>
+{
+    /// Replaces the error of an edge with the error it stands for, e.g. the
+    /// `ParseError` of a line of a text file with `Error::Parse`.
+    pub(crate) fn flatten(self) -> Error
+    {
+        self.map_source(Into::into)
+    }
+}
+
+impl Error
+{
+    /// The same error for edges that can fail with `E`.
+    pub(crate) fn widen<E>(self) -> Error<E>
+    {
+        self.map_source(|never| match never {})
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for Error<E>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Parse(err) => write!(f, "{}", err),
+            Error::Header(msg) => write!(f, "invalid header: {}", msg),
+            Error::CodecMismatch(msg) => write!(f, "{}", msg),
+            Error::InvalidOptions(msg) => write!(f, "invalid options: {}", msg),
+            Error::IdOverflow { edge: Some(edge), id } => {
+                write!(f, "edge {}: vertex id {} does not fit into the vertex id type", edge, id)
+            }
+            Error::IdOverflow { edge: None, id } => write!(f, "vertex id {} is too large", id),
+            Error::Unsorted { source, target: Some(target) } => {
+                write!(f, "edge ({}, {}) is out of order", source, target)
+            }
+            Error::Unsorted { source, target: None } => write!(f, "vertex {} is out of order", source),
+            Error::Capacity(err) => write!(f, "the graph doesn't fit into memory: {}", err),
+            Error::Corrupt(err) => write!(f, "{}", err),
+            Error::Encode(err) => write!(f, "{}", err),
+            Error::Source(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl<E: error::Error + 'static> error::Error for Error<E>
+{
+    fn source(&self) -> Option<&(dyn error::Error + 'static)>
+    {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Parse(err) => Some(err),
+            Error::Capacity(err) => Some(err),
+            Error::Corrupt(err) => Some(err),
+            Error::Encode(err) => Some(err),
+            Error::Source(err) => Some(err),
+            Error::Header(_)
+            | Error::CodecMismatch(_)
+            | Error::InvalidOptions(_)
+            | Error::IdOverflow { .. }
+            | Error::Unsorted { .. } => None,
+        }
+    }
+}
+
+impl<E> From<io::Error> for Error<E>
+{
+    fn from(err: io::Error) -> Self
+    {
+        Error::Io(err)
+    }
+}
+
+impl<E> From<ParseError> for Error<E>
+{
+    fn from(err: ParseError) -> Self
+    {
+        Error::Parse(err)
+    }
+}
+
+impl<E> From<TryReserveError> for Error<E>
+{
+    fn from(err: TryReserveError) -> Self
+    {
+        Error::Capacity(err)
+    }
+}
+
+impl<E> From<DecodeError> for Error<E>
+{
+    fn from(err: DecodeError) -> Self
+    {
+        Error::Corrupt(err)
+    }
+}
+
+impl<E> From<EncodeError> for Error<E>
+{
+    fn from(err: EncodeError) -> Self
+    {
+        Error::Encode(err)
+    }
+}
+
+impl<E: error::Error + Send + Sync + 'static> From<Error<E>> for io::Error
+{
+    fn from(err: Error<E>) -> Self
+    {
+        match err {
+            Error::Io(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+/// The bytes of a vertex that don't decode, see [`CSR::validate`].
+///
+/// [`CSR::validate`]: crate::CSR::validate
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodeError
+{
+    /// The vertex, or the order of the graph for the graph as a whole.
+    pub vertex: usize,
+    /// The offset of the offending byte in the edges buffer.
+    pub offset: usize,
+    /// What is wrong with it.
+    pub reason: &'static str,
+}
+
+impl DecodeError
+{
+    pub(crate) fn new(vertex: usize, (offset, reason): (usize, &'static str)) -> Self
+    {
+        Self { vertex, offset, reason }
+    }
+}
+
+impl fmt::Display for DecodeError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(
+            f,
+            "glzip buffer is corrupt at byte {}, vertex {}: {}",
+            self.offset, self.vertex, self.reason
+        )
+    }
+}
+
+impl error::Error for DecodeError {}
+
+impl From<DecodeError> for io::Error
+{
+    fn from(err: DecodeError) -> Self
+    {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+/// A vertex that doesn't decode to the neighbors it was encoded from, see
+/// [`CsrBuilder::build_verified`](crate::CsrBuilder::build_verified).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EncodeError
+{
+    pub vertex: u64,
+    /// The index of the first neighbor that differs.
+    pub index: usize,
+    /// The neighbor, `None` past the last one.
+    pub expected: Option<u64>,
+    /// What it decodes to, `None` past the last one or if the bytes don't
+    /// decode.
+    pub decoded: Option<u64>,
+    /// Why the bytes don't decode.
+    pub decode_error: Option<DecodeError>,
+}
+
+impl fmt::Display for EncodeError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(
+            f,
+            "glzip encoded vertex {} wrong: neighbor {} is {:?} rather than {:?}",
+            self.vertex, self.index, self.decoded, self.expected
+        )?;
+        if let Some(err) = &self.decode_error {
+            write!(f, " ({})", err)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for EncodeError
+{
+    fn source(&self) -> Option<&(dyn error::Error + 'static)>
+    {
+        self.decode_error.as_ref().map(|err| err as _)
+    }
+}
@@ -0,0 +1,357 @@
+// glzip is a graph compression library for graph learning systems
+// Copyright (C) 2022 Jacob Konrad
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// The on-disk container for a CSR. Everything is little endian.
+//
+//   offset  size  field
+//   0       8     magic, b"GLZIPCSR"
+//   8       4     format version
+//   12      1     codec id, see CODEC_*
+//   13      1     offset width in bytes (4 or 8)
+//   14      2     flags, see FLAG_*
+//   16      8     order (number of vertices)
+//   24      8     size (number of edges)
+//   32      8     length of the edges buffer in bytes
+//   40      4     the longest chain of references if FLAG_REFERENCES, else
+//                 zero
+//   44      4     reserved, zero
+//   48            order + 1 offsets, each `offset width` bytes
+//                 order + 1 edge offsets, the index of the first edge of
+//                 each vertex in decode order, each 4 bytes, or 8 if size
+//                 doesn't fit into a u32
+//                 the edges buffer, starting with the model of CODEC_RANS
+//                 if FLAG_EDGE_IDS, size edge ids as u64 in decode order
+//                 the skip index of the vertices with many neighbors, see
+//                 `skip`
+//
+// A header that can't be read, whether its magic, version, codec, flags or
+// any other field is unknown or corrupt, fails with Error::Header. A valid
+// header whose vertex id type isn't the one it's read as fails with
+// Error::CodecMismatch.
+
+use std::io::{self, Read, Write};
+
+use crate::{codec::Codec, error::Error, vertex::VertexId};
+
+pub const MAGIC: [u8; 8] = *b"GLZIPCSR";
+
+pub const VERSION: u32 = 1;
+
+pub const HEADER_LEN: usize = 48;
+
+/// The byte run length encoding from Ligra+, see `encoder` and `decoder`.
+pub const CODEC_BYTE_RLE: u8 = 0;
+
+/// The byte code from Ligra+, see `byte_code`.
+pub const CODEC_BYTE: u8 = 1;
+
+/// The nibble code from Ligra+, see `nibble_code`.
+pub const CODEC_NIBBLE: u8 = 2;
+
+/// Stream VByte, see `stream_vbyte`.
+pub const CODEC_STREAM_VBYTE: u8 = 3;
+
+/// Elias γ, see `bit_code`.
+pub const CODEC_GAMMA: u8 = 4;
+
+/// Elias δ, see `bit_code`.
+pub const CODEC_DELTA: u8 = 5;
+
+/// Entropy coded with rANS, see `rans`.
+pub const CODEC_RANS: u8 = 6;
+
+/// ζ_k, with k in the low bits, see `bit_code`.
+pub const CODEC_ZETA: u8 = 0x40;
+
+/// Golomb-Rice, with k in the low bits, see `bit_code`.
+pub const CODEC_RICE: u8 = 0x80;
+
+/// The bits of a codec id that hold the parameter of `CODEC_ZETA` and
+/// `CODEC_RICE`.
+pub const CODEC_PARAM_MASK: u8 = 0x3f;
+
+/// The graph stores an id per edge.
+pub const FLAG_EDGE_IDS: u16 = 1;
+
+/// The vertex ids are `u64` rather than `u32`.
+pub const FLAG_U64_IDS: u16 = 2;
+
+/// Vertices may be block encoded, see `Codec::encode_blocked`.
+pub const FLAG_BLOCKS: u16 = 4;
+
+/// Vertices are encoded against references, see `reference`.
+pub const FLAG_REFERENCES: u16 = 8;
+
+pub struct Header
+{
+    pub codec: Codec,
+    pub offset_width: u8,
+    pub flags: u16,
+    pub order: u64,
+    pub size: u64,
+    pub num_bytes: u64,
+    pub max_chain: u32,
+}
+
+fn invalid(msg: &str) -> Error
+{
+    Error::Header(msg.to_string())
+}
+
+impl Header
+{
+    pub fn to_bytes(&self) -> [u8; HEADER_LEN]
+    {
+        let mut bytes = [0u8; HEADER_LEN];
+        bytes[0..8].copy_from_slice(&MAGIC);
+        bytes[8..12].copy_from_slice(&VERSION.to_le_bytes());
+        bytes[12] = self.codec.id();
+        bytes[13] = self.offset_width;
+        bytes[14..16].copy_from_slice(&self.flags.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.order.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.size.to_le_bytes());
+        bytes[32..40].copy_from_slice(&self.num_bytes.to_le_bytes());
+        bytes[40..44].copy_from_slice(&self.max_chain.to_le_bytes());
+        bytes
+    }
+
+    /// Parses a header of `HEADER_LEN` bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error>
+    {
+        if bytes[0..8] != MAGIC {
+            return Err(invalid("not a glzip CSR: bad magic"));
+        }
+
+        if u32::from_le_bytes(bytes[8..12].try_into().unwrap()) != VERSION {
+            return Err(invalid("unsupported glzip format version"));
+        }
+
+        let codec = Codec::from_id(bytes[12]).ok_or_else(|| invalid("unknown glzip codec"))?;
+
+        if bytes[44..48] != [0; 4] {
+            return Err(invalid("reserved glzip header bytes are set"));
+        }
+
+        let header = Self {
+            codec,
+            offset_width: bytes[13],
+            flags: u16::from_le_bytes(bytes[14..16].try_into().unwrap()),
+            order: u64::from_le_bytes(bytes[16..24].try_into().unwrap()),
+            size: u64::from_le_bytes(bytes[24..32].try_into().unwrap()),
+            num_bytes: u64::from_le_bytes(bytes[32..40].try_into().unwrap()),
+            max_chain: u32::from_le_bytes(bytes[40..44].try_into().unwrap()),
+        };
+
+        if header.flags & !(FLAG_EDGE_IDS | FLAG_U64_IDS | FLAG_BLOCKS | FLAG_REFERENCES) != 0 {
+            return Err(invalid("unknown glzip flags"));
+        }
+
+        if header.offset_width != 4 && header.offset_width != 8 {
+            return Err(invalid("unsupported glzip offset width"));
+        }
+
+        if header.flags & FLAG_REFERENCES == 0 && header.max_chain != 0 {
+            return Err(invalid("a reference chain without references"));
+        }
+
+        Ok(header)
+    }
+
+    /// The longest chain of references if vertices are encoded against
+    /// references, see `reference`.
+    pub fn max_chain(&self) -> Option<usize>
+    {
+        (self.flags & FLAG_REFERENCES != 0).then_some(self.max_chain as usize)
+    }
+
+    /// The width of an edge offset in bytes.
+    pub fn edge_offset_width(&self) -> usize
+    {
+        if self.size <= u32::MAX as u64 { 4 } else { 8 }
+    }
+
+    /// Fails if the graph was written with a different vertex id type than `V`.
+    pub fn check_vertex_id<V: VertexId>(&self) -> Result<(), Error>
+    {
+        let bytes = if self.flags & FLAG_U64_IDS != 0 { 8 } else { 4 };
+        if bytes != V::BYTES {
+            return Err(Error::CodecMismatch("glzip vertex id type mismatch"));
+        }
+        Ok(())
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()>
+    {
+        w.write_all(&self.to_bytes())
+    }
+
+    pub fn read<R: Read>(r: &mut R) -> Result<Self, Error>
+    {
+        let mut bytes = [0u8; HEADER_LEN];
+        r.read_exact(&mut bytes)?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+/// Reads a single little endian offset of `width` bytes.
+#[inline]
+pub fn read_offset(bytes: &[u8], width: usize) -> usize
+{
+    let mut buf = [0u8; 8];
+    buf[..width].copy_from_slice(&bytes[..width]);
+    u64::from_le_bytes(buf) as usize
+}
+
+pub fn write_offsets<W: Write, I>(w: &mut W, offsets: I, width: usize) -> io::Result<()>
+where
+    I: IntoIterator<Item = usize>,
+{
+    let offsets = offsets.into_iter();
+    let mut buf = Vec::with_capacity(offsets.size_hint().0 * width);
+    for offset in offsets {
+        buf.extend_from_slice(&(offset as u64).to_le_bytes()[..width]);
+    }
+    w.write_all(&buf)
+}
+
+/// The number of edge ids `write_ids` converts at once.
+const WRITE_CHUNK: usize = 1 << 16;
+
+/// Writes the edge ids as little-endian `u64`s, a chunk at a time rather
+/// than converting them all first.
+pub fn write_ids<W: Write>(w: &mut W, ids: &[u64]) -> io::Result<()>
+{
+    let mut buf = Vec::with_capacity(WRITE_CHUNK.min(ids.len()) * 8);
+    for chunk in ids.chunks(WRITE_CHUNK) {
+        buf.clear();
+        for id in chunk {
+            buf.extend_from_slice(&id.to_le_bytes());
+        }
+        w.write_all(&buf)?;
+    }
+    Ok(())
+}
+
+pub fn read_offsets<R: Read>(r: &mut R, len: u64, width: usize) -> Result<Vec<usize>, Error>
+{
+    let buf = read_bytes(r, len.saturating_mul(width as u64))?;
+    Ok(buf.chunks_exact(width).map(|b| read_offset(b, width)).collect())
+}
+
+/// The most memory `read_bytes` reserves before reading, it grows the buffer
+/// by at most its length after that.
+const READ_CHUNK: u64 = 1 << 20;
+
+/// Reads `len` bytes, failing rather than aborting if they don't fit into
+/// memory. The buffer grows as bytes are read, so a corrupt header that
+/// claims more bytes than `r` has fails with `UnexpectedEof` having
+/// reserved at most about twice the bytes there are.
+pub fn read_bytes<R: Read>(r: &mut R, len: u64) -> Result<Vec<u8>, Error>
+{
+    let mut buf = Vec::new();
+    while (buf.len() as u64) < len {
+        let step = (len - buf.len() as u64).min(READ_CHUNK.max(buf.len() as u64));
+        buf.try_reserve_exact(step as usize)?;
+        if r.take(step).read_to_end(&mut buf)? < step as usize {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+    }
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{CsrView, Edge, CSR};
+
+    fn bytes() -> Vec<u8>
+    {
+        let csr = CSR::from_edges((0..100u32).map(|v| Edge(v % 3, v)));
+        let mut buf = Vec::new();
+        csr.write_to(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn ids_are_written_across_chunks()
+    {
+        let ids: Vec<u64> = (0..WRITE_CHUNK as u64 + 3).map(|id| id << 33 | id).collect();
+        let mut buf = Vec::new();
+        write_ids(&mut buf, &ids).unwrap();
+        let written: Vec<u64> = buf.chunks_exact(8).map(|b| u64::from_le_bytes(b.try_into().unwrap())).collect();
+        assert_eq!(ids, written);
+    }
+
+    #[test]
+    fn unreadable_headers_are_header_errors()
+    {
+        let corrupt: [fn(&mut Vec<u8>); 6] = [
+            |buf| buf[0] = b'X',
+            |buf| buf[8] = 0,
+            |buf| buf[8] = 2,
+            |buf| buf[12] = 7,
+            |buf| buf[14] = 0x10,
+            |buf| buf[46] = 1,
+        ];
+        for f in corrupt {
+            let mut buf = bytes();
+            f(&mut buf);
+            assert!(matches!(CSR::<u32>::read_from(&buf[..]), Err(Error::Header(_))));
+            assert!(matches!(CsrView::<u32>::new(&buf[..]), Err(Error::Header(_))));
+        }
+    }
+
+    #[test]
+    fn claimed_lengths_are_not_reserved()
+    {
+        let eof = |result: Result<Vec<u8>, Error>| {
+            matches!(result, Err(Error::Io(err)) if err.kind() == io::ErrorKind::UnexpectedEof)
+        };
+        assert!(eof(read_bytes(&mut &[1u8; 100][..], u64::MAX / 2)));
+        assert_eq!(vec![1u8; 100], read_bytes(&mut &[1u8; 200][..], 100).unwrap());
+
+        // A vertex whose offsets end at the claimed length, with a few bytes
+        // of edges.
+        let header = Header {
+            codec: Codec::ByteRle,
+            offset_width: 8,
+            flags: 0,
+            order: 1,
+            size: 1,
+            num_bytes: u64::MAX / 2,
+            max_chain: 0,
+        };
+        let mut buf = header.to_bytes().to_vec();
+        write_offsets(&mut buf, [0, u64::MAX as usize / 2], 8).unwrap();
+        write_offsets(&mut buf, [0, 1], 4).unwrap();
+        buf.extend_from_slice(&[0; 16]);
+        assert!(eof(CSR::<u32>::read_from(&buf[..]).map(|_| vec![])));
+        assert!(CsrView::<u32>::new(&buf[..]).is_err());
+
+        // More vertices than there are offsets.
+        let mut buf = bytes();
+        buf[16..24].copy_from_slice(&(u64::MAX / 2).to_le_bytes());
+        assert!(eof(CSR::<u32>::read_from(&buf[..]).map(|_| vec![])));
+    }
+
+    #[test]
+    fn other_vertex_ids_are_codec_mismatches()
+    {
+        let buf = bytes();
+        assert!(matches!(CSR::<u64>::read_from(&buf[..]), Err(Error::CodecMismatch(_))));
+    }
+}
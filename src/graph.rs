@@ -0,0 +1,190 @@
+// glzip is a graph compression library for graph learning systems
+// Copyright (C) 2022 Jacob Konrad
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The adjacency interface shared by the graph representations.
+
+use std::borrow::Cow;
+
+use crate::{vertex::VertexId, Edge};
+
+/// A directed graph whose adjacency can be queried per vertex.
+///
+/// [`CSR`](crate::CSR) and [`CsrView`](crate::CsrView) implement it, and the
+/// algorithms in this crate, like
+/// [`GraphSageSampler`](crate::graph_sage_sampler::GraphSageSampler) and
+/// [`reorder`](crate::reorder), are generic over it, so any other adjacency
+/// backend can be plugged in by implementing `neighbors`, `order` and `size`.
+///
+/// # Examples
+///
+/// ```
+/// use glzip::{Edge, Graph};
+///
+/// struct AdjList(Vec<Vec<u32>>);
+///
+/// impl Graph for AdjList
+/// {
+///     type Vertex = u32;
+///
+///     fn neighbors(&self, source: u32) -> impl Iterator<Item = u32> + '_
+///     {
+///         self.0.get(source as usize).into_iter().flatten().copied()
+///     }
+///
+///     fn order(&self) -> usize
+///     {
+///         self.0.len()
+///     }
+///
+///     fn size(&self) -> usize
+///     {
+///         self.0.iter().map(Vec::len).sum()
+///     }
+/// }
+///
+/// let graph = AdjList(vec![vec![1, 2], vec![0], vec![1]]);
+///
+/// assert_eq!(2, graph.degree(0));
+/// assert_eq!(Some(Edge(2, 1)), graph.edges().last());
+/// ```
+pub trait Graph
+{
+    /// The vertex id type.
+    type Vertex: VertexId;
+
+    /// The neighbors of a vertex.
+    fn neighbors(&self, source: Self::Vertex) -> impl Iterator<Item = Self::Vertex> + '_;
+
+    /// The number of vertices in the graph.
+    fn order(&self) -> usize;
+
+    /// The number of edges in the graph.
+    fn size(&self) -> usize;
+
+    /// The degree of a vertex.
+    ///
+    /// The default counts the neighbors, representations that can do better
+    /// should override it.
+    fn degree(&self, source: Self::Vertex) -> usize
+    {
+        self.neighbors(source).count()
+    }
+
+    /// Appends the neighbors of a vertex to `buf`.
+    fn neighbors_into(&self, source: Self::Vertex, buf: &mut Vec<Self::Vertex>)
+    {
+        buf.extend(self.neighbors(source));
+    }
+
+    /// The `i`th neighbor of a vertex in the order of `neighbors`.
+    ///
+    /// The default walks the neighbors, representations that can do better
+    /// should override it.
+    fn neighbor_at(&self, source: Self::Vertex, i: usize) -> Option<Self::Vertex>
+    {
+        self.neighbors(source).nth(i)
+    }
+
+    /// Whether [`neighbor_at`](Self::neighbor_at) finds a neighbor by
+    /// decoding a bounded number of neighbors rather than those before it,
+    /// e.g. from a skip index. `false` by default, so that samplers decode
+    /// the neighbors once and index them.
+    fn random_access(&self) -> bool
+    {
+        false
+    }
+
+    /// Whether the graph has the edge from `source` to `target`.
+    ///
+    /// The default walks the neighbors, representations that can do better
+    /// should override it.
+    fn has_edge(&self, source: Self::Vertex, target: Self::Vertex) -> bool
+    {
+        self.neighbors(source).any(|v| v == target)
+    }
+
+    /// The edges of the graph.
+    fn edges(&self) -> impl Iterator<Item = Edge<Self::Vertex>> + '_
+    {
+        (0..self.order()).flat_map(move |u| {
+            let u = Self::Vertex::from_usize(u);
+            self.neighbors(u).map(move |v| Edge(u, v))
+        })
+    }
+
+    /// The ids of the edges of a vertex in the order of its neighbors, `None`
+    /// if the graph doesn't store edge ids, which is the default. They are
+    /// borrowed where the graph stores them as `u64`s.
+    fn edge_ids(&self, _source: Self::Vertex) -> Option<Cow<'_, [u64]>>
+    {
+        None
+    }
+}
+
+impl<G: Graph + ?Sized> Graph for &G
+{
+    type Vertex = G::Vertex;
+
+    fn neighbors(&self, source: G::Vertex) -> impl Iterator<Item = G::Vertex> + '_
+    {
+        (**self).neighbors(source)
+    }
+
+    fn order(&self) -> usize
+    {
+        (**self).order()
+    }
+
+    fn size(&self) -> usize
+    {
+        (**self).size()
+    }
+
+    fn degree(&self, source: G::Vertex) -> usize
+    {
+        (**self).degree(source)
+    }
+
+    fn neighbors_into(&self, source: G::Vertex, buf: &mut Vec<G::Vertex>)
+    {
+        (**self).neighbors_into(source, buf)
+    }
+
+    fn neighbor_at(&self, source: G::Vertex, i: usize) -> Option<G::Vertex>
+    {
+        (**self).neighbor_at(source, i)
+    }
+
+    fn random_access(&self) -> bool
+    {
+        (**self).random_access()
+    }
+
+    fn has_edge(&self, source: G::Vertex, target: G::Vertex) -> bool
+    {
+        (**self).has_edge(source, target)
+    }
+
+    fn edges(&self) -> impl Iterator<Item = Edge<G::Vertex>> + '_
+    {
+        (**self).edges()
+    }
+
+    fn edge_ids(&self, source: G::Vertex) -> Option<Cow<'_, [u64]>>
+    {
+        (**self).edge_ids(source)
+    }
+}
@@ -1,7 +1,7 @@
 
 use std::{slice, collections::{HashMap, hash_map::Entry}};
 
-use crate::{CSR, iter::*, vec};
+use crate::{graph::Graph, skip::SKIP_LEN, vec, vertex::VertexId};
 
 use rayon::prelude::*;
 
@@ -9,12 +9,14 @@ pub struct Adj
 {
     pub src: Vec<u32>,
     pub dst: Vec<u32>,
+    /// The ids of the sampled edges, empty if the graph doesn't store edge ids.
+    pub e_id: Vec<u64>,
     pub size: (usize, usize),
 }
     
-fn reindex(inputs: &[u32], outputs: &[u32], output_counts: &[usize]) -> (Vec<u32>, Vec<u32>, Vec<u32>)
+fn reindex<V: VertexId>(inputs: &[V], outputs: &[V], output_counts: &[usize]) -> (Vec<V>, Vec<u32>, Vec<u32>)
 {
-    let mut out_map: HashMap<u32, u32> = HashMap::new();
+    let mut out_map: HashMap<V, u32> = HashMap::new();
 
     let mut frontier = Vec::new();
 
@@ -27,13 +29,10 @@ fn reindex(inputs: &[u32], outputs: &[u32], output_counts: &[usize]) -> (Vec<u32
     }
 
     for &output in outputs.iter() {
-        match out_map.entry(output) {
-            Entry::Vacant(hole) => {
-                hole.insert(n_id); 
-                n_id += 1;
-                frontier.push(output);
-            }
-            _ => {},
+        if let Entry::Vacant(hole) = out_map.entry(output) {
+            hole.insert(n_id);
+            n_id += 1;
+            frontier.push(output);
         }
     }
 
@@ -52,42 +51,106 @@ fn reindex(inputs: &[u32], outputs: &[u32], output_counts: &[usize]) -> (Vec<u32
     (frontier, row_idx, col_idx)
 }
 
-pub struct GraphSageSampler<'a>
+/// Samples the multi-hop neighborhood of a batch of vertices like PyG's
+/// `NeighborSampler`, `sizes[i]` neighbors per vertex in hop `i`.
+///
+/// # Examples
+///
+/// ```
+/// use glzip::{graph_sage_sampler::GraphSageSampler, CSR, Edge};
+///
+/// let csr = CSR::from_edges_with_ids(vec![Edge(0u32, 1), Edge(0, 2), Edge(1, 0), Edge(2, 1)]);
+///
+/// let sizes = [2];
+/// let sampler = GraphSageSampler::new(&csr, &sizes);
+/// let (nodes, batch_size, adjs) = sampler.sample(&[0]);
+///
+/// assert_eq!(1, batch_size);
+/// assert_eq!(vec![0, 1, 2], nodes);
+/// assert_eq!(vec![0, 1], adjs[0].e_id);
+/// ```
+pub struct GraphSageSampler<'a, G>
 {
-    csr: &'a CSR,
+    graph: &'a G,
     sizes: slice::Iter<'a, usize>,
 }
 
-impl<'a> GraphSageSampler<'a> 
+impl<'a, G: Graph + Sync> GraphSageSampler<'a, G> 
 {
-    pub fn new(csr: &'a CSR, sizes: &'a [usize]) -> Self
+    pub fn new(graph: &'a G, sizes: &'a [usize]) -> Self
     {
-        Self { csr, sizes: sizes.iter() }
+        Self { graph, sizes: sizes.iter() }
     }
 
-    fn sample_kernel(&self, inputs: &[u32], k: usize) -> (Vec<u32>, Vec<usize>)
+    /// Samples `k` neighbors of every input with the ids of their edges.
+    /// Positions past the neighbors or the edge ids of a vertex, from a
+    /// graph whose degree disagrees with them, are skipped.
+    fn sample_kernel(&self, inputs: &[G::Vertex], k: usize) -> (Vec<G::Vertex>, Vec<u64>, Vec<usize>)
     {
         inputs
             .par_iter()
-            .map_init(|| rand::thread_rng(), |rng, &v| {
-                let ns = self.csr.neighbors(v).reservoir_sample(rng, k);
+            .map_init(|| (rand::thread_rng(), Vec::new()), |(rng, buf), &v| {
+                let d = self.graph.degree(v);
+                let ids = self.graph.edge_ids(v);
+                let (ns, es): (Vec<_>, Vec<_>) = if d <= k {
+                    let mut ns = Vec::with_capacity(d);
+                    self.graph.neighbors_into(v, &mut ns);
+                    let es = match ids {
+                        Some(ids) => {
+                            ns.truncate(ids.len());
+                            ids[..ns.len()].to_vec()
+                        }
+                        None => vec![],
+                    };
+                    (ns, es)
+                }
+                else {
+                    // Draw the positions first so that only the sampled
+                    // neighbors are decoded if the graph can find them
+                    // directly, and decode all of them once otherwise or
+                    // if most would be decoded anyway.
+                    let mut picks = rand::seq::index::sample(rng, d, k).into_vec();
+                    picks.sort_unstable();
+                    let direct = self.graph.random_access() && k.saturating_mul(SKIP_LEN) < d;
+                    if !direct {
+                        buf.clear();
+                        self.graph.neighbors_into(v, buf);
+                    }
+                    let mut ns = Vec::with_capacity(k);
+                    let mut es = Vec::with_capacity(if ids.is_some() { k } else { 0 });
+                    for &i in &picks {
+                        let n = if direct { self.graph.neighbor_at(v, i) } else { buf.get(i).copied() };
+                        let e = ids.as_ref().map(|ids| ids.get(i).copied());
+                        match (n, e) {
+                            (Some(n), None) => ns.push(n),
+                            (Some(n), Some(Some(e))) => {
+                                ns.push(n);
+                                es.push(e);
+                            }
+                            _ => {}
+                        }
+                    }
+                    (ns, es)
+                };
                 let d = ns.len();
-                (ns, vec![d])
+                (ns, es, vec![d])
+            })
+            .reduce(|| (vec![], vec![], vec![]), |a, b| {
+                (vec::concat(a.0, b.0), vec::concat(a.1, b.1), vec::concat(a.2, b.2))
             })
-            .reduce(|| (vec![], vec![]), |a, b| (vec::concat(a.0, b.0), vec::concat(a.1, b.1)))
     }
 
-    pub fn sample(&self, input_nodes: &[u32]) -> (Vec<u32>, usize, Vec<Adj>)
+    pub fn sample(&self, input_nodes: &[G::Vertex]) -> (Vec<G::Vertex>, usize, Vec<Adj>)
     {
-        let mut nodes: Vec<_> = input_nodes.iter().copied().collect();
+        let mut nodes = input_nodes.to_vec();
         let mut adjs = Vec::new();
         let batch_size = nodes.len();
 
         for &k in self.sizes.clone() {
-            let (out, cnt) = self.sample_kernel(&nodes[..], k);
+            let (out, e_id, cnt) = self.sample_kernel(&nodes[..], k);
             let (frontier, dst, src) = reindex(&nodes[..], &out[..], &cnt[..]);
             let size = (frontier.len(), nodes.len());
-            adjs.push(Adj { src, dst, size });
+            adjs.push(Adj { src, dst, e_id, size });
             nodes = frontier;
         }
 
@@ -97,3 +160,87 @@ impl<'a> GraphSageSampler<'a>
     }
 }
 
+#[cfg(test)]
+mod tests
+{
+    use std::borrow::Cow;
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::{Edge, CSR};
+
+    /// A graph that claims more neighbors than it has, and lacks the id of
+    /// the last edge of every vertex.
+    struct Inconsistent(CSR);
+
+    impl Graph for Inconsistent
+    {
+        type Vertex = u32;
+
+        fn neighbors(&self, source: u32) -> impl Iterator<Item = u32> + '_
+        {
+            self.0.neighbors(source)
+        }
+
+        fn order(&self) -> usize
+        {
+            self.0.order()
+        }
+
+        fn size(&self) -> usize
+        {
+            self.0.size()
+        }
+
+        fn degree(&self, source: u32) -> usize
+        {
+            self.0.degree(source) + 10
+        }
+
+        fn edge_ids(&self, source: u32) -> Option<Cow<'_, [u64]>>
+        {
+            let ids = self.0.edge_ids(source)?;
+            Some(Cow::Borrowed(&ids[..ids.len().saturating_sub(1)]))
+        }
+    }
+
+    fn check(nodes: &[u32], adjs: &[Adj], edges: &[Edge])
+    {
+        for adj in adjs {
+            assert_eq!(adj.src.len(), adj.e_id.len());
+            for ((&src, &dst), &id) in adj.src.iter().zip(&adj.dst).zip(&adj.e_id) {
+                assert_eq!(Edge(nodes[dst as usize], nodes[src as usize]), edges[id as usize]);
+            }
+        }
+    }
+
+    #[test]
+    fn edge_ids_are_the_sampled_edges()
+    {
+        let mut rng = StdRng::seed_from_u64(8);
+        let edges: Vec<Edge> = (0..3000).map(|_| Edge(rng.gen_range(0..100), rng.gen_range(0..100))).collect();
+        let csr = CSR::from_edges_with_ids(edges.clone());
+
+        // Vertices have about 30 neighbors, so the first hop samples them
+        // and the second takes them all. The nodes of a hop are a prefix of
+        // the nodes of the next.
+        let sizes = [5, 100];
+        let sampler = GraphSageSampler::new(&csr, &sizes);
+        let (nodes, _, adjs) = sampler.sample(&[0, 7, 42]);
+        check(&nodes, &adjs, &edges);
+    }
+
+    #[test]
+    fn inconsistent_graphs_do_not_panic()
+    {
+        let mut rng = StdRng::seed_from_u64(12);
+        let edges: Vec<Edge> = (0..3000).map(|_| Edge(rng.gen_range(0..100), rng.gen_range(0..100))).collect();
+        let graph = Inconsistent(CSR::from_edges_with_ids(edges.clone()));
+
+        let sizes = [20, 100];
+        let sampler = GraphSageSampler::new(&graph, &sizes);
+        let (nodes, _, adjs) = sampler.sample(&[0, 7, 42]);
+        check(&nodes, &adjs, &edges);
+    }
+}
@@ -14,11 +14,6 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use rand::{
-    distributions::{Distribution, Uniform},
-    Rng,
-};
-
 pub struct Dedup<I: Iterator>
 {
     prev: Option<<I as Iterator>::Item>,
@@ -76,48 +71,3 @@ where
         }
     }
 }
-
-/// https://en.m.wikipedia.org/wiki/Reservoir_sampling#An_optimal_algorithm
-pub trait IteratorReservoirSamplingExt: Iterator + Sized
-{
-    fn reservoir_sample<R: Rng + ?Sized>(self, rng: &mut R, k: usize) -> Vec<Self::Item>;
-}
-
-impl<I> IteratorReservoirSamplingExt for I
-where
-    I: Iterator + Sized,
-{
-    fn reservoir_sample<R: Rng + ?Sized>(mut self, rng: &mut R, k: usize) -> Vec<Self::Item>
-    {
-        let mut buf = Vec::with_capacity(k);
-
-        for _ in 0..k {
-            match self.next() {
-                Some(x) => buf.push(x),
-                None => return buf,
-            }
-        }
-
-        let indexing_range = Uniform::new(0, k);
-
-        let open_unit_interval = Uniform::new(f64::MIN_POSITIVE, 1.0);
-
-        let k = k as f64;
-
-        let mut w = (open_unit_interval.sample(rng).ln() / k).exp();
-
-        loop {
-            let i = (open_unit_interval.sample(rng).ln() / (1.0 - w).ln()).floor() as usize + 1;
-            match self.nth(i) {
-                Some(x) => {
-                    buf[indexing_range.sample(rng)] = x;
-                    w *= (open_unit_interval.sample(rng).ln() / k).exp()
-                }
-                None => break,
-            }
-        }
-
-        buf
-    }
-}
-
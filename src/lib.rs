@@ -14,22 +14,43 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-#![feature(slice_as_chunks)]
-#![feature(slice_split_at_unchecked)]
-#![feature(slice_group_by)]
-#![feature(never_type)]
-#![feature(unwrap_infallible)]
-#![feature(total_cmp)]
-
+pub mod binary;
+mod bit_code;
+pub mod builder;
+mod byte_code;
+mod checked;
+pub mod codec;
 pub mod csr;
 mod decoder;
 mod edge;
+pub mod edge_list;
 mod encoder;
+pub mod error;
+mod format;
+pub mod graph;
 pub mod graph_sage_sampler;
+pub mod mtx;
+mod nibble_code;
+mod offsets;
+mod reference;
 pub mod reorder;
 mod iter;
 mod par;
+mod rans;
+#[cfg(feature = "simd")]
+mod simd;
+mod skip;
+mod stream_vbyte;
 mod vec;
+pub mod vertex;
+pub mod view;
+pub mod weighted;
 
+pub use builder::CsrBuilder;
 pub use csr::CSR;
 pub use edge::Edge;
+pub use error::{DecodeError, EncodeError, Error};
+pub use graph::Graph;
+pub use vertex::VertexId;
+pub use view::CsrView;
+pub use weighted::WeightedCSR;
@@ -0,0 +1,463 @@
+// glzip is a graph compression library for graph learning systems
+// Copyright (C) 2022 Jacob Konrad
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! [MatrixMarket](https://math.nist.gov/MatrixMarket/formats.html) coordinate
+//! files. Row `i` and column `j` (1-based) are read as the edge `Edge(i - 1, j - 1)`.
+
+use std::io::{self, BufRead, Write};
+
+use crate::{
+    edge_list::{ParseError, ParseErrorKind},
+    error::Error,
+    vertex::VertexId,
+    Edge,
+    CSR,
+};
+
+/// The most edges reserved for before any is read, see
+/// `MatrixMarket::capacity`.
+const MAX_RESERVE: usize = 1 << 20;
+
+/// The type of the values of a MatrixMarket file.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Field
+{
+    Pattern,
+    Integer,
+    Real,
+}
+
+/// Which entries of a MatrixMarket file are stored explicitly.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Symmetry
+{
+    General,
+    /// Only the lower triangle is stored, `(i, j)` implies `(j, i)`.
+    Symmetric,
+    /// Like `Symmetric` but the implied entry has the negated value.
+    SkewSymmetric,
+}
+
+/// A reader of MatrixMarket coordinate files, an iterator over the stored
+/// entries and their values. Symmetric files are expanded to both
+/// directions, pattern files have the value `1.0`.
+///
+/// # Examples
+///
+/// ```
+/// use glzip::{mtx::{MatrixMarket, Symmetry}, Edge};
+///
+/// let text = "%%MatrixMarket matrix coordinate real symmetric\n\
+///             % a comment\n\
+///             3 3 2\n\
+///             2 1 0.5\n\
+///             3 3 2.0\n";
+///
+/// let mtx: MatrixMarket<_> = MatrixMarket::new(text.as_bytes()).unwrap();
+///
+/// assert_eq!(Symmetry::Symmetric, mtx.symmetry());
+///
+/// let entries: Vec<(Edge, f64)> = mtx.collect::<Result<_, _>>().unwrap();
+///
+/// assert_eq!(vec![(Edge(1, 0), 0.5), (Edge(0, 1), 0.5), (Edge(2, 2), 2.0)], entries);
+/// ```
+pub struct MatrixMarket<R, V = u32>
+{
+    reader: R,
+    buf: String,
+    line: usize,
+    field: Field,
+    symmetry: Symmetry,
+    rows: u64,
+    cols: u64,
+    nnz: u64,
+    // The number of entries read so far, before symmetric expansion.
+    entries: u64,
+    mirrored: Option<(Edge<V>, f64)>,
+}
+
+impl<R: BufRead, V: VertexId> MatrixMarket<R, V>
+{
+    /// Reads the banner and the size line.
+    pub fn new(reader: R) -> Result<Self, Error>
+    {
+        let mut mtx = Self {
+            reader,
+            buf: String::new(),
+            line: 0,
+            field: Field::Pattern,
+            symmetry: Symmetry::General,
+            rows: 0,
+            cols: 0,
+            nnz: 0,
+            entries: 0,
+            mirrored: None,
+        };
+
+        if !mtx.read_line()? {
+            return Err(mtx.error(ParseErrorKind::InvalidHeader(String::new())).into());
+        }
+
+        let banner = mtx.buf.trim().to_ascii_lowercase();
+        let words: Vec<&str> = banner.split_whitespace().collect();
+        match words[..] {
+            ["%%matrixmarket", "matrix", "coordinate", field, symmetry] => {
+                mtx.field = match field {
+                    "pattern" => Field::Pattern,
+                    "integer" => Field::Integer,
+                    "real" => Field::Real,
+                    _ => return Err(mtx.error(ParseErrorKind::InvalidHeader(banner.clone())).into()),
+                };
+                mtx.symmetry = match symmetry {
+                    "general" => Symmetry::General,
+                    "symmetric" => Symmetry::Symmetric,
+                    "skew-symmetric" => Symmetry::SkewSymmetric,
+                    _ => return Err(mtx.error(ParseErrorKind::InvalidHeader(banner.clone())).into()),
+                };
+            }
+            _ => return Err(mtx.error(ParseErrorKind::InvalidHeader(banner.clone())).into()),
+        }
+
+        if !mtx.next_data_line()? {
+            return Err(mtx.error(ParseErrorKind::InvalidHeader(String::new())).into());
+        }
+
+        let size: Vec<u64> = mtx
+            .buf
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|_| mtx.error(ParseErrorKind::InvalidHeader(mtx.buf.trim().to_string())))?;
+        match size[..] {
+            [rows, cols, nnz] => {
+                mtx.rows = rows;
+                mtx.cols = cols;
+                mtx.nnz = nnz;
+            }
+            _ => return Err(mtx.error(ParseErrorKind::InvalidHeader(mtx.buf.trim().to_string())).into()),
+        }
+
+        Ok(mtx)
+    }
+
+    pub fn field(&self) -> Field
+    {
+        self.field
+    }
+
+    pub fn symmetry(&self) -> Symmetry
+    {
+        self.symmetry
+    }
+
+    /// The number of rows and columns declared by the size line.
+    pub fn dimensions(&self) -> (u64, u64)
+    {
+        (self.rows, self.cols)
+    }
+
+    /// The number of entries stored in the file, before symmetric expansion.
+    pub fn nnz(&self) -> u64
+    {
+        self.nnz
+    }
+
+    /// The number of edges to reserve up front, those declared after
+    /// symmetric expansion but at most `MAX_RESERVE`, as the size line may
+    /// declare more entries than the file has. The buffer grows from there
+    /// as entries are read.
+    pub(crate) fn capacity(&self) -> usize
+    {
+        let capacity = match self.symmetry {
+            Symmetry::General => self.nnz,
+            Symmetry::Symmetric | Symmetry::SkewSymmetric => self.nnz.saturating_mul(2),
+        };
+        usize::try_from(capacity).map_or(MAX_RESERVE, |capacity| capacity.min(MAX_RESERVE))
+    }
+
+    /// The order of the graph, the larger of the declared rows and columns,
+    /// so that vertices without entries after the last entry are kept.
+    pub(crate) fn order(&self) -> Result<usize, Error>
+    {
+        let order = self.rows.max(self.cols);
+        if let Some(max) = order.checked_sub(1) {
+            let max = V::try_from_u64(max).ok_or(Error::IdOverflow { edge: None, id: max as i128 })?;
+            CSR::max_vertex(max)?;
+        }
+        Ok(order as usize)
+    }
+
+    /// Reads the remaining entries into `buf` as `entry(edge, value)`.
+    /// Fails with [`ParseErrorKind::VertexOutOfRange`] if an index is
+    /// outside of the declared dimensions, or [`ParseErrorKind::EntryCount`]
+    /// if the file doesn't have as many entries as declared.
+    pub(crate) fn read_into<T, F>(mut self, buf: &mut Vec<T>, mut entry: F) -> Result<(), Error>
+    where
+        F: FnMut(Edge<V>, f64) -> T,
+    {
+        for result in self.by_ref() {
+            let (e, x) = result?;
+            buf.try_reserve(1)?;
+            buf.push(entry(e, x));
+        }
+        if self.entries != self.nnz {
+            let kind = ParseErrorKind::EntryCount { declared: self.nnz, found: self.entries };
+            return Err(self.error(kind).into());
+        }
+        Ok(())
+    }
+
+    fn error(&self, kind: ParseErrorKind) -> ParseError
+    {
+        ParseError {
+            line: self.line,
+            kind,
+        }
+    }
+
+    fn read_line(&mut self) -> io::Result<bool>
+    {
+        self.buf.clear();
+        self.line += 1;
+        Ok(self.reader.read_line(&mut self.buf)? > 0)
+    }
+
+    fn next_data_line(&mut self) -> io::Result<bool>
+    {
+        while self.read_line()? {
+            let line = self.buf.trim();
+            if !line.is_empty() && !line.starts_with('%') {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn parse_index(&self, column: Option<&str>, bound: u64) -> Result<V, ParseError>
+    {
+        let column = column.ok_or_else(|| self.error(ParseErrorKind::MissingColumn))?;
+        let i: u64 = column
+            .parse()
+            .map_err(|_| self.error(ParseErrorKind::InvalidVertex(column.to_string())))?;
+        if i == 0 || i > bound {
+            return Err(self.error(ParseErrorKind::VertexOutOfRange(i)));
+        }
+        V::try_from_u64(i - 1).ok_or_else(|| self.error(ParseErrorKind::VertexOutOfRange(i)))
+    }
+
+    fn parse_entry(&self) -> Result<(Edge<V>, f64), ParseError>
+    {
+        let mut columns = self.buf.split_whitespace();
+        let u = self.parse_index(columns.next(), self.rows)?;
+        let v = self.parse_index(columns.next(), self.cols)?;
+        let value = match self.field {
+            Field::Pattern => 1.0,
+            Field::Integer | Field::Real => {
+                let column = columns
+                    .next()
+                    .ok_or_else(|| self.error(ParseErrorKind::MissingColumn))?;
+                column
+                    .parse()
+                    .map_err(|_| self.error(ParseErrorKind::InvalidValue(column.to_string())))?
+            }
+        };
+        Ok((Edge(u, v), value))
+    }
+}
+
+impl<R: BufRead, V: VertexId> Iterator for MatrixMarket<R, V>
+{
+    type Item = Result<(Edge<V>, f64), Error>;
+
+    fn next(&mut self) -> Option<Self::Item>
+    {
+        if let Some(entry) = self.mirrored.take() {
+            return Some(Ok(entry));
+        }
+
+        match self.next_data_line() {
+            Ok(true) => {}
+            Ok(false) => return None,
+            Err(err) => return Some(Err(Error::Io(err))),
+        }
+
+        let entry = self.parse_entry().map(|(Edge(u, v), value)| {
+            self.entries += 1;
+            if u != v {
+                match self.symmetry {
+                    Symmetry::General => {}
+                    Symmetry::Symmetric => self.mirrored = Some((Edge(v, u), value)),
+                    Symmetry::SkewSymmetric => self.mirrored = Some((Edge(v, u), -value)),
+                }
+            }
+            (Edge(u, v), value)
+        });
+
+        Some(entry.map_err(Error::Parse))
+    }
+}
+
+impl<V: VertexId> CSR<V>
+{
+    /// Builds a graph from a MatrixMarket coordinate file, the values are
+    /// ignored. The order of the graph is the larger of the declared rows
+    /// and columns. Fails with [`Error::Parse`] if an index is outside of
+    /// them, see [`ParseErrorKind::VertexOutOfRange`], or the number of
+    /// entries differs from the declared one, see
+    /// [`ParseErrorKind::EntryCount`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glzip::{edge_list::{ParseError, ParseErrorKind}, CSR, Error};
+    ///
+    /// let text = "%%MatrixMarket matrix coordinate pattern general\n\
+    ///             3 3 4\n\
+    ///             1 2\n\
+    ///             1 3\n\
+    ///             2 1\n\
+    ///             3 2\n";
+    ///
+    /// let csr: CSR = CSR::from_matrix_market(text.as_bytes()).unwrap();
+    ///
+    /// assert_eq!(3, csr.order());
+    /// assert_eq!(vec![1,2], csr.neighbors(0).collect::<Vec<_>>());
+    /// assert_eq!(vec![1], csr.neighbors(2).collect::<Vec<_>>());
+    ///
+    /// // Vertex 4 has no entries, and the file ends early.
+    /// let text = "%%MatrixMarket matrix coordinate pattern general\n\
+    ///             4 4 2\n\
+    ///             1 2\n";
+    ///
+    /// assert!(matches!(
+    ///     CSR::<u32>::from_matrix_market(text.as_bytes()),
+    ///     Err(Error::Parse(ParseError { kind: ParseErrorKind::EntryCount { declared: 2, found: 1 }, .. }))
+    /// ));
+    /// ```
+    pub fn from_matrix_market<R: BufRead>(reader: R) -> Result<Self, Error>
+    {
+        let mtx: MatrixMarket<R, V> = MatrixMarket::new(reader)?;
+        let order = mtx.order()?;
+        let mut buf = Vec::new();
+        buf.try_reserve(mtx.capacity())?;
+        mtx.read_into(&mut buf, |e, _| e)?;
+        let mut csr = Self::from_buffer(&mut buf[..])?;
+        csr.pad_order(order)?;
+        Ok(csr)
+    }
+
+    /// Writes the graph as a square `coordinate pattern general` MatrixMarket file.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glzip::CSR;
+    ///
+    /// let csr = CSR::from(vec![
+    ///     [0u32,1],
+    ///     [0,2],
+    ///     [1,0],
+    ///     [2,1],
+    /// ]);
+    ///
+    /// let mut buf = Vec::new();
+    /// csr.write_matrix_market(&mut buf).unwrap();
+    ///
+    /// let copy: CSR = CSR::from_matrix_market(&buf[..]).unwrap();
+    ///
+    /// assert_eq!(csr.order(), copy.order());
+    /// assert_eq!(csr.edges().collect::<Vec<_>>(), copy.edges().collect::<Vec<_>>());
+    /// ```
+    pub fn write_matrix_market<W: Write>(&self, w: W) -> io::Result<()>
+    {
+        let mut w = io::BufWriter::new(w);
+        writeln!(w, "%%MatrixMarket matrix coordinate pattern general")?;
+        writeln!(w, "{} {} {}", self.order(), self.order(), self.size())?;
+        for Edge(u, v) in self.edges() {
+            writeln!(w, "{} {}", u.to_u64() + 1, v.to_u64() + 1)?;
+        }
+        w.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::weighted::WeightedCSR;
+
+    fn read(text: &str) -> Result<CSR, Error>
+    {
+        CSR::from_matrix_market(text.as_bytes())
+    }
+
+    #[test]
+    fn declared_dimensions_are_the_order()
+    {
+        let csr = read("%%MatrixMarket matrix coordinate pattern general\n6 4 1\n1 2\n").unwrap();
+        assert_eq!(6, csr.order());
+        assert_eq!(0, csr.degree(5));
+
+        let mut buf = Vec::new();
+        csr.write_matrix_market(&mut buf).unwrap();
+        let copy = read(std::str::from_utf8(&buf).unwrap()).unwrap();
+        assert_eq!(csr.order(), copy.order());
+        assert_eq!(csr.edges().collect::<Vec<_>>(), copy.edges().collect::<Vec<_>>());
+
+        let text = "%%MatrixMarket matrix coordinate real general\n5 5 1\n2 1 0.5\n";
+        let weighted: WeightedCSR = WeightedCSR::from_matrix_market(text.as_bytes(), |x| x as f32, |a, _| a).unwrap();
+        assert_eq!(5, weighted.order());
+
+        assert_eq!(3, read("%%MatrixMarket matrix coordinate pattern general\n3 3 0\n").unwrap().order());
+    }
+
+    #[test]
+    fn entries_must_match_the_header()
+    {
+        let kind = |text: &str| match read(text) {
+            Err(Error::Parse(err)) => Some((err.line, err.kind)),
+            _ => None,
+        };
+
+        assert!(matches!(
+            kind("%%MatrixMarket matrix coordinate pattern general\n3 3 2\n1 2\n"),
+            Some((_, ParseErrorKind::EntryCount { declared: 2, found: 1 }))
+        ));
+        assert!(matches!(
+            kind("%%MatrixMarket matrix coordinate pattern general\n3 3 1\n1 2\n2 3\n"),
+            Some((_, ParseErrorKind::EntryCount { declared: 1, found: 2 }))
+        ));
+        assert!(matches!(
+            kind("%%MatrixMarket matrix coordinate pattern general\n3 3 1\n4 1\n"),
+            Some((3, ParseErrorKind::VertexOutOfRange(4)))
+        ));
+        assert!(matches!(
+            kind("%%MatrixMarket matrix coordinate pattern general\n3 2 1\n1 3\n"),
+            Some((3, ParseErrorKind::VertexOutOfRange(3)))
+        ));
+        // Symmetric entries count once.
+        assert!(read("%%MatrixMarket matrix coordinate pattern symmetric\n3 3 1\n2 1\n").is_ok());
+        assert!(matches!(
+            read("%%MatrixMarket matrix coordinate pattern general\n4294967297 1 0\n"),
+            Err(Error::IdOverflow { .. })
+        ));
+        // The declared entries aren't reserved for.
+        assert!(matches!(
+            kind("%%MatrixMarket matrix coordinate pattern symmetric\n3 3 10000000000000000000\n1 2\n"),
+            Some((_, ParseErrorKind::EntryCount { declared: 10000000000000000000, found: 1 }))
+        ));
+    }
+}
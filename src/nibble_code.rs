@@ -0,0 +1,203 @@
+// glzip is a graph compression library for graph learning systems
+// Copyright (C) 2022 Jacob Konrad
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// The nibble code from Ligra+: the first neighbor as in `encoder::first_edge`
+// and then every difference in chunks of 3 bits, least significant first,
+// one chunk per nibble with the high bit of the nibble marking that more
+// chunks follow. The nibbles are packed high nibble first, an odd number of
+// nibbles is padded with a zero nibble, which can't start a difference as
+// the differences between distinct sorted neighbors are at least 1.
+//
+// Smaller than the byte code for the small differences of dense or
+// reordered graphs, slower to decode.
+
+use std::slice;
+
+use crate::{
+    checked::{self, Checked, Cursor, Sink, OVERFLOW, TRUNCATED},
+    decoder, encoder,
+    vertex::VertexId,
+};
+
+struct Nibbles<'a>
+{
+    bytes: &'a mut Vec<u8>,
+    high: Option<u8>,
+}
+
+impl<'a> Nibbles<'a>
+{
+    fn push(&mut self, nibble: u8)
+    {
+        match self.high.take() {
+            Some(high) => self.bytes.push(high << 4 | nibble),
+            None => self.high = Some(nibble),
+        }
+    }
+
+    fn finish(self)
+    {
+        if let Some(high) = self.high {
+            self.bytes.push(high << 4);
+        }
+    }
+}
+
+pub fn encode<V, I>(bytes: &mut Vec<u8>, source: V, edges: I)
+where
+    V: VertexId,
+    I: IntoIterator<Item = V>,
+{
+    let mut edges = edges.into_iter().map(V::to_u64);
+
+    let mut prev_edge = match edges.next() {
+        Some(edge) => edge,
+        None => return,
+    };
+
+    encoder::first_edge(bytes, source.to_u64(), prev_edge);
+
+    let mut nibbles = Nibbles { bytes, high: None };
+    for edge in edges {
+        let mut diff = edge - prev_edge;
+        prev_edge = edge;
+        while diff >= 0x8 {
+            nibbles.push(diff as u8 & 0x7 | 0x8);
+            diff >>= 3;
+        }
+        nibbles.push(diff as u8);
+    }
+    nibbles.finish();
+}
+
+struct Reader<'a>
+{
+    bytes: slice::Iter<'a, u8>,
+    low: Option<u8>,
+}
+
+impl<'a> Reader<'a>
+{
+    #[inline]
+    fn nibble(&mut self) -> Option<u8>
+    {
+        match self.low.take() {
+            Some(low) => Some(low),
+            None => {
+                let &byte = self.bytes.next()?;
+                self.low = Some(byte & 0xf);
+                Some(byte >> 4)
+            }
+        }
+    }
+
+    #[inline]
+    fn diff(&mut self) -> Option<u64>
+    {
+        let mut nibble = self.nibble().filter(|&nibble| nibble != 0)?;
+        let mut diff = (nibble & 0x7) as u64;
+        let mut shift = 3;
+        while nibble & 0x8 != 0 {
+            nibble = self.nibble()?;
+            diff |= ((nibble & 0x7) as u64) << shift;
+            shift += 3;
+        }
+        Some(diff)
+    }
+}
+
+pub struct Iter<'a, V>
+{
+    first: Option<V>,
+    prev_edge: u64,
+    reader: Reader<'a>,
+}
+
+impl<'a, V: VertexId> Iterator for Iter<'a, V>
+{
+    type Item = V;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item>
+    {
+        if let Some(first) = self.first.take() {
+            return Some(first);
+        }
+        self.prev_edge += self.reader.diff()?;
+        Some(V::from_u64(self.prev_edge))
+    }
+}
+
+pub fn decode<V: VertexId>(source: V, bytes: &[u8]) -> Iter<'_, V>
+{
+    let mut bytes = bytes.iter();
+    let first = decoder::first_edge(source.to_u64(), &mut bytes);
+    Iter {
+        first: first.map(V::from_u64),
+        prev_edge: first.unwrap_or(0),
+        reader: Reader { bytes, low: None },
+    }
+}
+
+pub fn decode_into<V: VertexId>(source: V, bytes: &[u8], out: &mut Vec<V>)
+{
+    out.extend(decode(source, bytes));
+}
+
+/// Like `decode_into` but checks every difference and the padding, see
+/// `checked`.
+pub fn try_decode_into<V: VertexId>(source: V, mut bytes: Cursor<'_>, sink: &mut Sink<'_, V>) -> Checked<()>
+{
+    let offset = bytes.offset();
+    let mut prev_edge = bytes.first_edge(source.to_u64())?;
+    sink.push(prev_edge, offset)?;
+
+    let rest = bytes.rest();
+    let nibble = |i: usize| rest[i / 2] >> (4 - 4 * (i % 2)) & 0xf;
+    let len = 2 * rest.len();
+    let mut i = 0;
+    while i < len {
+        let offset = bytes.offset() + i / 2;
+        if nibble(i) == 0 {
+            // Only the last nibble can be padding.
+            if i + 1 == len {
+                return Ok(());
+            }
+            return Err((offset, "a difference starts with a zero nibble"));
+        }
+
+        let mut diff = 0u64;
+        let mut shift = 0;
+        loop {
+            if i == len {
+                return Err((bytes.offset() + rest.len(), TRUNCATED));
+            }
+            let chunk = (nibble(i) & 0x7) as u64;
+            if shift >= 64 || chunk.leading_zeros() < shift {
+                return Err((offset, OVERFLOW));
+            }
+            diff |= chunk << shift;
+            shift += 3;
+            i += 1;
+            if nibble(i - 1) & 0x8 == 0 {
+                break;
+            }
+        }
+        prev_edge = checked::add(prev_edge, diff, offset)?;
+        sink.push(prev_edge, offset)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,144 @@
+// glzip is a graph compression library for graph learning systems
+// Copyright (C) 2022 Jacob Konrad
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// A compact non-decreasing sequence of offsets with O(1) access.
+//
+// The sequence is cut into blocks of BLOCK_LEN entries. Each block stores
+// its first value as an anchor and the rest as deltas from the anchor, all
+// with the smallest width of 1, 2, 4 or 8 bytes that fits the block's last
+// delta. With small degrees most blocks use 1 or 2 bytes per entry plus the
+// 16 bytes of the block itself, rather than 8 bytes per entry.
+
+const BLOCK_LEN: usize = 64;
+
+struct Block
+{
+    anchor: u64,
+    // The position of the block's deltas in `Offsets::deltas` shifted left by
+    // two, the low two bits are the log2 of the width.
+    start: u64,
+}
+
+impl Block
+{
+    #[inline]
+    fn width(&self) -> usize
+    {
+        1 << (self.start & 3)
+    }
+
+    #[inline]
+    fn start(&self) -> usize
+    {
+        (self.start >> 2) as usize
+    }
+}
+
+#[derive(Default)]
+pub struct Offsets
+{
+    len: usize,
+    blocks: Vec<Block>,
+    deltas: Vec<u8>,
+}
+
+impl Offsets
+{
+    /// Panics if `values` is not sorted.
+    pub fn new(values: &[usize]) -> Self
+    {
+        let mut blocks = Vec::with_capacity(values.len().div_ceil(BLOCK_LEN));
+        let mut deltas = Vec::new();
+
+        for chunk in values.chunks(BLOCK_LEN) {
+            let anchor = chunk[0];
+            let last = chunk[chunk.len() - 1];
+            assert!(anchor <= last, "offsets must be non-decreasing");
+            let log_width = match (last - anchor) as u64 {
+                0..=0xff => 0,
+                0x100..=0xffff => 1,
+                0x1_0000..=0xffff_ffff => 2,
+                _ => 3,
+            };
+            blocks.push(Block {
+                anchor: anchor as u64,
+                start: (deltas.len() as u64) << 2 | log_width,
+            });
+            // The first delta is always zero and not stored.
+            for &value in &chunk[1..] {
+                assert!(anchor <= value, "offsets must be non-decreasing");
+                deltas.extend_from_slice(&((value - anchor) as u64).to_le_bytes()[..1 << log_width]);
+            }
+        }
+
+        blocks.shrink_to_fit();
+        deltas.shrink_to_fit();
+
+        Self {
+            len: values.len(),
+            blocks,
+            deltas,
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize
+    {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.len == 0
+    }
+
+    #[inline]
+    pub fn get(&self, i: usize) -> Option<usize>
+    {
+        if i >= self.len {
+            return None;
+        }
+
+        let block = &self.blocks[i / BLOCK_LEN];
+        let j = i % BLOCK_LEN;
+        if j == 0 {
+            return Some(block.anchor as usize);
+        }
+
+        let width = block.width();
+        let at = block.start() + (j - 1) * width;
+        let mut buf = [0u8; 8];
+        buf[..width].copy_from_slice(&self.deltas[at..at + width]);
+        Some((block.anchor + u64::from_le_bytes(buf)) as usize)
+    }
+
+    /// The offsets `i` and `i + 1`, the bounds of the `i`th range.
+    #[inline]
+    pub fn range(&self, i: usize) -> Option<(usize, usize)>
+    {
+        Some((self.get(i)?, self.get(i + 1)?))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_
+    {
+        (0..self.len).map(|i| self.get(i).unwrap())
+    }
+
+    pub fn nbytes(&self) -> usize
+    {
+        std::mem::size_of_val(&self.blocks[..]) + std::mem::size_of_val(&self.deltas[..])
+    }
+}
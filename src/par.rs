@@ -49,7 +49,7 @@ where
             None
         }
     }) {
-        match xs[start..end]
+        if let Some(i) = xs[start..end]
             .windows(2)
             .enumerate()
             .find_map(|(i, win)| {
@@ -62,8 +62,7 @@ where
             })
             .map(|i| start + i)
         {
-            Some(i) => return Some(i),
-            None => {}
+            return Some(i);
         }
     }
     None
@@ -106,7 +105,7 @@ where
     where
         F: Folder<Self::Item>,
     {
-        folder.consume_iter(self.slice.group_by(self.pred))
+        folder.consume_iter(self.slice.chunk_by(self.pred))
     }
 }
 
@@ -168,10 +167,7 @@ where
                     Some(b) => Some(std::cmp::max(a, b)),
                     None => Some(a),
                 },
-                None => match y {
-                    Some(b) => Some(b),
-                    None => None,
-                },
+                None => y,
             },
         )
 }
@@ -0,0 +1,399 @@
+// glzip is a graph compression library for graph learning systems
+// Copyright (C) 2022 Jacob Konrad
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Based on:
+//
+// Asymmetric numeral systems: entropy coding combining speed of Huffman
+// coding with compression rate of arithmetic coding
+//
+// by Jarek Duda
+//
+// https://arxiv.org/abs/1311.2540
+//
+// and the byte-wise rANS of Fabian Giesen's ryg_rans.
+
+// Entropy coded differences. A difference d is split into its bucket, the
+// bit length of d minus 1, and the bits of d after its leading one. The
+// buckets are entropy coded with rANS, the bits are stored as they are.
+//
+// The model is a table of bucket frequencies for each degree class, the
+// vertices with 2^c to 2^(c + 1) - 1 differences being class c, built from
+// the whole graph. Its bytes are
+//
+//   the number of classes as u8,
+//   for every class, the NUM_BUCKETS + 1 cumulative frequencies as u16,
+//   from 0 to PROB_SCALE, so the tables are used in place.
+//
+// Every bucket has a frequency of at least 1, so any difference can be
+// encoded with any table, and a class without vertices uses the frequencies
+// of all classes together. There is at least one class.
+//
+// A block is the first neighbor as in `encoder::first_edge`, the number of
+// differences and the length of the rANS bytes as varints, the rANS bytes,
+// starting with the final state, and then the bits of the differences. It
+// decodes on its own given the model.
+
+use crate::{
+    bit_code::{BitReader, BitWriter},
+    byte_code::{read_varint, write_varint},
+    checked::{self, Checked, Cursor, Sink, TRAILING, TRUNCATED},
+    decoder, encoder,
+    vertex::VertexId,
+};
+
+pub const NUM_BUCKETS: usize = 64;
+
+const MAX_CLASSES: usize = 16;
+
+const PROB_BITS: u32 = 12;
+
+const PROB_SCALE: u32 = 1 << PROB_BITS;
+
+// The state is in [RANS_L, RANS_L << 8) and flushed in 3 bytes.
+const RANS_L: u32 = 1 << 16;
+
+const STATE_BYTES: usize = 3;
+
+#[inline]
+fn bucket(diff: u64) -> usize
+{
+    diff.ilog2() as usize
+}
+
+/// The class of a block with `n` >= 1 differences.
+#[inline]
+fn class(n: usize) -> usize
+{
+    (n.ilog2() as usize).min(MAX_CLASSES - 1)
+}
+
+/// The bucket counts of the differences of each degree class.
+#[derive(Default)]
+pub struct Histogram
+{
+    counts: Vec<[u64; NUM_BUCKETS]>,
+}
+
+impl Histogram
+{
+    /// Counts the differences of the sorted, deduplicated `edges`.
+    pub fn add<V: VertexId>(&mut self, edges: impl IntoIterator<Item = V>)
+    {
+        let edges: Vec<u64> = edges.into_iter().map(V::to_u64).collect();
+        if edges.len() < 2 {
+            return;
+        }
+        let class = class(edges.len() - 1);
+        if self.counts.len() <= class {
+            self.counts.resize(class + 1, [0; NUM_BUCKETS]);
+        }
+        for pair in edges.windows(2) {
+            self.counts[class][bucket(pair[1] - pair[0])] += 1;
+        }
+    }
+
+    pub fn merge(mut self, other: Self) -> Self
+    {
+        if self.counts.len() < other.counts.len() {
+            self.counts.resize(other.counts.len(), [0; NUM_BUCKETS]);
+        }
+        for (counts, other) in self.counts.iter_mut().zip(other.counts) {
+            for (count, other) in counts.iter_mut().zip(other) {
+                *count += other;
+            }
+        }
+        self
+    }
+
+    /// The bytes of the model.
+    pub fn model(&self) -> Vec<u8>
+    {
+        let mut all = [0; NUM_BUCKETS];
+        for counts in &self.counts {
+            for (count, &c) in all.iter_mut().zip(counts) {
+                *count += c;
+            }
+        }
+
+        // A graph without differences still gets a table, so that any
+        // difference can be encoded with the model.
+        let classes = self.counts.len().max(1);
+        let mut model = vec![classes as u8];
+        for class in 0..classes {
+            let counts = match self.counts.get(class) {
+                Some(counts) if counts.iter().any(|&c| c > 0) => counts,
+                _ => &all,
+            };
+            let mut cum = 0u16;
+            model.extend_from_slice(&cum.to_le_bytes());
+            for freq in normalize(counts) {
+                cum += freq;
+                model.extend_from_slice(&cum.to_le_bytes());
+            }
+        }
+        model
+    }
+}
+
+/// Frequencies summing to PROB_SCALE, at least 1 and otherwise proportional
+/// to `counts`.
+fn normalize(counts: &[u64; NUM_BUCKETS]) -> [u16; NUM_BUCKETS]
+{
+    let total: u64 = counts.iter().sum::<u64>().max(1);
+    let spare = (PROB_SCALE as usize - NUM_BUCKETS) as u64;
+
+    let mut freqs = [1u16; NUM_BUCKETS];
+    for (freq, &count) in freqs.iter_mut().zip(counts) {
+        *freq += (count * spare / total) as u16;
+    }
+
+    // Give what rounding left over to the most frequent bucket.
+    let sum: u32 = freqs.iter().map(|&f| f as u32).sum();
+    let most = (0..NUM_BUCKETS).max_by_key(|&i| counts[i]).unwrap();
+    freqs[most] += (PROB_SCALE - sum) as u16;
+    freqs
+}
+
+/// The cumulative frequencies of a class, borrowed from the model.
+struct Table<'a>
+{
+    cum: &'a [u8],
+}
+
+impl<'a> Table<'a>
+{
+    const LEN: usize = 2 * (NUM_BUCKETS + 1);
+
+    fn new(model: &'a [u8], class: usize) -> Self
+    {
+        let class = class.min(model[0] as usize - 1);
+        let start = 1 + Self::LEN * class;
+        Self { cum: &model[start..start + Self::LEN] }
+    }
+
+    /// Like `new` but checks that the table is in the model, starts at 0,
+    /// increases and ends at PROB_SCALE.
+    fn try_new(model: &'a [u8], class: usize) -> Option<Self>
+    {
+        let classes = *model.first()? as usize;
+        let start = 1 + Self::LEN * class.min(classes.checked_sub(1)?);
+        let table = Self { cum: model.get(start..start + Self::LEN)? };
+        let valid = table.cum(0) == 0
+            && table.cum(NUM_BUCKETS) == PROB_SCALE
+            && (0..NUM_BUCKETS).all(|i| table.cum(i) < table.cum(i + 1));
+        valid.then_some(table)
+    }
+
+    #[inline]
+    fn cum(&self, i: usize) -> u32
+    {
+        u16::from_le_bytes([self.cum[2 * i], self.cum[2 * i + 1]]) as u32
+    }
+
+    /// The start and the frequency of `bucket`.
+    #[inline]
+    fn get(&self, bucket: usize) -> (u32, u32)
+    {
+        let start = self.cum(bucket);
+        (start, self.cum(bucket + 1) - start)
+    }
+
+    /// The bucket of the slot `slot` < PROB_SCALE.
+    #[inline]
+    fn find(&self, slot: u32) -> usize
+    {
+        // The last bucket starting at or before `slot`.
+        let (mut lo, mut hi) = (0, NUM_BUCKETS);
+        while hi - lo > 1 {
+            let mid = (lo + hi) / 2;
+            if self.cum(mid) <= slot {
+                lo = mid;
+            }
+            else {
+                hi = mid;
+            }
+        }
+        lo
+    }
+}
+
+pub fn encode<V, I>(bytes: &mut Vec<u8>, source: V, edges: I, model: &[u8])
+where
+    V: VertexId,
+    I: IntoIterator<Item = V>,
+{
+    let mut edges = edges.into_iter().map(V::to_u64);
+
+    let mut prev_edge = match edges.next() {
+        Some(edge) => edge,
+        None => return,
+    };
+
+    encoder::first_edge(bytes, source.to_u64(), prev_edge);
+
+    let diffs: Vec<u64> = edges
+        .map(|edge| {
+            let diff = edge - prev_edge;
+            prev_edge = edge;
+            diff
+        })
+        .collect();
+
+    write_varint(bytes, diffs.len() as u64);
+    if diffs.is_empty() {
+        return;
+    }
+
+    // rANS decodes in the reverse order of encoding, so the differences are
+    // encoded from the last and the bytes reversed.
+    let table = Table::new(model, class(diffs.len()));
+    let mut rans = Vec::new();
+    let mut x = RANS_L;
+    for &diff in diffs.iter().rev() {
+        let (start, freq) = table.get(bucket(diff));
+        let x_max = ((RANS_L >> PROB_BITS) << 8) * freq;
+        while x >= x_max {
+            rans.push(x as u8);
+            x >>= 8;
+        }
+        x = ((x / freq) << PROB_BITS) + (x % freq) + start;
+    }
+    rans.extend_from_slice(&x.to_le_bytes()[..STATE_BYTES]);
+    rans.reverse();
+
+    write_varint(bytes, rans.len() as u64);
+    bytes.extend_from_slice(&rans);
+
+    let mut bits = BitWriter::new(bytes);
+    for &diff in &diffs {
+        bits.write(diff as u128, bucket(diff) as u32);
+    }
+    bits.finish();
+}
+
+pub struct Iter<'a, V>
+{
+    first: Option<V>,
+    prev_edge: u64,
+    left: usize,
+    table: Option<Table<'a>>,
+    x: u32,
+    rans: std::slice::Iter<'a, u8>,
+    bits: BitReader<'a>,
+}
+
+impl<'a, V: VertexId> Iterator for Iter<'a, V>
+{
+    type Item = V;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item>
+    {
+        if let Some(first) = self.first.take() {
+            return Some(first);
+        }
+        if self.left == 0 {
+            return None;
+        }
+        self.left -= 1;
+
+        let table = self.table.as_ref()?;
+        let slot = self.x & (PROB_SCALE - 1);
+        let bucket = table.find(slot);
+        let (start, freq) = table.get(bucket);
+        self.x = freq * (self.x >> PROB_BITS) + slot - start;
+        while self.x < RANS_L {
+            self.x = self.x << 8 | *self.rans.next()? as u32;
+        }
+
+        self.prev_edge += 1 << bucket | self.bits.read_wide(bucket as u32)? as u64;
+        Some(V::from_u64(self.prev_edge))
+    }
+}
+
+pub fn decode<'a, V: VertexId>(source: V, bytes: &'a [u8], model: &'a [u8]) -> Iter<'a, V>
+{
+    let mut bytes = bytes.iter();
+    let first = decoder::first_edge(source.to_u64(), &mut bytes);
+    let left = first.and_then(|_| read_varint(&mut bytes)).unwrap_or(0) as usize;
+
+    let (mut table, mut x, mut rans, mut bits) = (None, 0, [].iter(), BitReader::new(&[]));
+    if left > 0 {
+        let len = read_varint(&mut bytes).unwrap_or(0) as usize;
+        let (state, rest) = bytes.as_slice().split_at(len);
+        let (state, rest_of_rans) = state.split_at(STATE_BYTES);
+        table = Some(Table::new(model, class(left)));
+        x = state.iter().fold(0, |x, &byte| x << 8 | byte as u32);
+        rans = rest_of_rans.iter();
+        bits = BitReader::new(rest);
+    }
+
+    Iter {
+        first: first.map(V::from_u64),
+        prev_edge: first.unwrap_or(0),
+        left,
+        table,
+        x,
+        rans,
+        bits,
+    }
+}
+
+pub fn decode_into<V: VertexId>(source: V, bytes: &[u8], out: &mut Vec<V>, model: &[u8])
+{
+    out.extend(decode(source, bytes, model));
+}
+
+/// Like `decode_into` but checks the model, the lengths and that every
+/// byte is used, see `checked`.
+pub fn try_decode_into<V: VertexId>(source: V, mut bytes: Cursor<'_>, sink: &mut Sink<'_, V>, model: &[u8]) -> Checked<()>
+{
+    let offset = bytes.offset();
+    let mut prev_edge = bytes.first_edge(source.to_u64())?;
+    sink.push(prev_edge, offset)?;
+
+    let count = bytes.varint()?;
+    if count == 0 {
+        return bytes.finish();
+    }
+    let table = Table::try_new(model, class(count as usize)).ok_or((0, "the rANS model is invalid"))?;
+    let len = bytes.varint()?;
+    let mut rans = bytes.take(len)?;
+    let state = rans.take(STATE_BYTES as u64)?.rest();
+    let mut x = state.iter().fold(0, |x, &byte| x << 8 | byte as u32);
+
+    let rest = bytes.rest();
+    let mut bits = BitReader::new(rest);
+    for _ in 0..count {
+        let slot = x & (PROB_SCALE - 1);
+        let bucket = table.find(slot);
+        let (start, freq) = table.get(bucket);
+        x = freq * (x >> PROB_BITS) + slot - start;
+        while x < RANS_L {
+            x = x << 8 | rans.byte()? as u32;
+        }
+
+        let offset = bytes.offset() + (8 * rest.len() - bits.unread_bits()) / 8;
+        let diff = 1 << bucket | bits.read_wide(bucket as u32).ok_or((offset, TRUNCATED))? as u64;
+        prev_edge = checked::add(prev_edge, diff, offset)?;
+        sink.push(prev_edge, offset)?;
+    }
+    rans.finish()?;
+    if bits.unread_bits() >= 8 {
+        return Err((bytes.offset() + rest.len() - bits.unread_bits() / 8, TRAILING));
+    }
+    Ok(())
+}
@@ -0,0 +1,572 @@
+// glzip is a graph compression library for graph learning systems
+// Copyright (C) 2022 Jacob Konrad
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Based on:
+//
+// The WebGraph Framework I: Compression Techniques
+//
+// by Paolo Boldi and Sebastiano Vigna
+//
+// https://vigna.di.unimi.it/ftp/papers/WebGraphI.pdf
+
+// Reference and interval compression, see `CsrBuilder::references`. The
+// neighbors of a vertex are split into those copied from the neighbors of a
+// reference vertex among the previous `window` vertices, intervals of at
+// least MIN_INTERVAL consecutive ids and the residuals. Its bytes are
+//
+//   the distance r to the reference as a varint, 0 for none,
+//   if r > 0, the number of copy blocks and their lengths as varints,
+//   the number of intervals as a varint,
+//   the intervals, each its left end and its length minus MIN_INTERVAL,
+//   the residuals encoded with the graph's codec.
+//
+// The copy blocks cover the neighbors of the reference, alternately copied
+// and skipped starting with a copied block, and everything after the last
+// block is skipped. All blocks but the first are at least 1 long and stored
+// minus 1. The left end of the first interval is stored like the first
+// neighbor of a codec, see `encoder::first_edge`, the others as their
+// distance from the end of the previous interval minus 1.
+//
+// A reference can itself have a reference, the references of a vertex form
+// a chain of at most `max_chain` vertices that all have to be decoded, which
+// bounds the cost of a lookup. The longest chain is stored in the header, so
+// that decoding a corrupt chain stops there. Vertices without neighbors have
+// no bytes.
+
+use std::iter::Peekable;
+
+use rayon::prelude::*;
+
+use crate::{
+    builder,
+    byte_code::{read_varint, write_varint},
+    checked::{Checked, Cursor, Sink, NOT_INCREASING, OUT_OF_RANGE},
+    codec::Coder,
+    decoder, encoder,
+    error::{DecodeError, EncodeError},
+    vec,
+    vertex::VertexId,
+};
+
+/// The shortest run of consecutive neighbors stored as an interval.
+pub const MIN_INTERVAL: u64 = 4;
+
+// The lists are encoded in parallel chunks, references don't cross chunks.
+const CHUNK_LEN: usize = 1024;
+
+// The number of edges, the vertices with their number of bytes and degree,
+// the bytes and the longest chain of references.
+type Encoded<V> = (usize, Vec<(V, usize, usize)>, Vec<u8>, usize);
+
+/// Encodes `list`, the sorted neighbors of `source`, with the neighbors of
+/// the vertex `r` before it as the reference.
+fn encode_list<V: VertexId>(coder: Coder<'_>, bytes: &mut Vec<u8>, source: V, list: &[V], reference: Option<(usize, &[V])>)
+{
+    let mut extras = Vec::with_capacity(list.len());
+    match reference {
+        None => {
+            write_varint(bytes, 0);
+            extras.extend(list.iter().map(|v| v.to_u64()));
+        }
+        Some((r, reference)) => {
+            write_varint(bytes, r as u64);
+
+            let mut blocks = Vec::new();
+            let mut copying = true;
+            let mut run = 0;
+            let mut list = list.iter().map(|v| v.to_u64()).peekable();
+            for w in reference.iter().map(|v| v.to_u64()) {
+                while let Some(v) = list.next_if(|&v| v < w) {
+                    extras.push(v);
+                }
+                let copied = list.next_if_eq(&w).is_some();
+                if copied != copying {
+                    blocks.push(run);
+                    copying = copied;
+                    run = 0;
+                }
+                run += 1;
+            }
+            if copying {
+                blocks.push(run);
+            }
+            extras.extend(list);
+
+            write_varint(bytes, blocks.len() as u64);
+            for (i, &len) in blocks.iter().enumerate() {
+                write_varint(bytes, len - (i > 0) as u64);
+            }
+        }
+    }
+
+    // The maximal runs of consecutive ids.
+    let mut intervals = Vec::new();
+    let mut residuals = Vec::with_capacity(extras.len());
+    let mut start = 0;
+    for end in 1..=extras.len() {
+        if end < extras.len() && extras[end - 1] + 1 == extras[end] {
+            continue;
+        }
+        let run = &extras[start..end];
+        if run.len() as u64 >= MIN_INTERVAL {
+            intervals.push((run[0], run.len() as u64));
+        }
+        else {
+            residuals.extend(run.iter().map(|&v| V::from_u64(v)));
+        }
+        start = end;
+    }
+
+    write_varint(bytes, intervals.len() as u64);
+    let mut prev_end = 0;
+    for (i, &(left, len)) in intervals.iter().enumerate() {
+        if i == 0 {
+            encoder::first_edge(bytes, source.to_u64(), left);
+        }
+        else {
+            write_varint(bytes, left - prev_end - 1);
+        }
+        write_varint(bytes, len - MIN_INTERVAL);
+        prev_end = left + len;
+    }
+
+    coder.encode(bytes, source, residuals);
+}
+
+/// Encodes the sorted, deduplicated neighbor lists of the vertices in
+/// `lists`, choosing for each vertex the reference among the `window`
+/// vertices before it that gives the fewest bytes. Returns the number of
+/// edges, the vertices with their number of bytes and degree, and the bytes,
+/// as `CSR::from_buffer_with` does, and the longest chain of references. If
+/// `verified` every vertex is decoded and compared with its list, see
+/// `CsrBuilder::encode_vertex`.
+pub fn encode<V: VertexId>(
+    coder: Coder<'_>,
+    lists: &[(V, Vec<V>)],
+    window: usize,
+    max_chain: usize,
+    verified: bool,
+) -> Result<Encoded<V>, EncodeError>
+{
+    lists
+        .par_chunks(CHUNK_LEN)
+        .map(|chunk| {
+            let mut num_edges = 0;
+            let mut nodes_and_nnzs = Vec::with_capacity(chunk.len());
+            let mut edges = Vec::new();
+
+            // The length of the reference chain of each vertex in the chunk.
+            let mut chains = vec![0; chunk.len()];
+            let mut best = Vec::new();
+            let mut candidate = Vec::new();
+            let mut decoded = Vec::new();
+
+            for (i, (u, list)) in chunk.iter().enumerate() {
+                best.clear();
+                encode_list(coder, &mut best, *u, list, None);
+                let mut chosen = None;
+
+                for j in (0..i).rev() {
+                    let (v, reference) = &chunk[j];
+                    let r = u.to_usize() - v.to_usize();
+                    if r > window {
+                        break;
+                    }
+                    if chains[j] >= max_chain {
+                        continue;
+                    }
+                    candidate.clear();
+                    encode_list(coder, &mut candidate, *u, list, Some((r, reference)));
+                    if candidate.len() < best.len() {
+                        std::mem::swap(&mut best, &mut candidate);
+                        chains[i] = chains[j] + 1;
+                        chosen = Some(j);
+                    }
+                }
+
+                if verified {
+                    // The reference was verified before, so its list is
+                    // what it decodes to.
+                    let reference = chosen.map_or(&[][..], |j| &chunk[j].1[..]);
+                    decoded.clear();
+                    let result = try_decode_list(coder, u64::MAX, *u, Cursor::new(&best, 0), reference, &mut decoded);
+                    let decode_error = result.err().map(|err| DecodeError::new(u.to_usize(), err));
+                    builder::verify(*u, list, &decoded, decode_error)?;
+                }
+
+                edges.extend_from_slice(&best);
+                nodes_and_nnzs.push((*u, best.len(), list.len()));
+                num_edges += list.len();
+            }
+
+            let longest = chains.iter().copied().max().unwrap_or(0);
+            Ok((num_edges, nodes_and_nnzs, edges, longest))
+        })
+        .try_reduce(
+            || (0, vec![], vec![], 0),
+            |left, right| {
+                Ok((
+                    left.0 + right.0,
+                    vec::concat(left.1, right.1),
+                    vec::concat(left.2, right.2),
+                    left.3.max(right.3),
+                ))
+            },
+        )
+}
+
+/// The parts of the bytes of a vertex.
+struct Parts<'a>
+{
+    r: usize,
+    blocks: Vec<usize>,
+    intervals: Vec<(u64, u64)>,
+    residuals: &'a [u8],
+}
+
+fn parse(source: u64, bytes: &[u8]) -> Option<Parts<'_>>
+{
+    let mut bytes = bytes.iter();
+
+    let r = read_varint(&mut bytes)? as usize;
+    let mut blocks = Vec::new();
+    if r > 0 {
+        let num_blocks = read_varint(&mut bytes)?;
+        for i in 0..num_blocks {
+            blocks.push(read_varint(&mut bytes)? as usize + (i > 0) as usize);
+        }
+    }
+
+    let num_intervals = read_varint(&mut bytes)?;
+    let mut intervals = Vec::with_capacity(num_intervals as usize);
+    let mut prev_end = 0;
+    for i in 0..num_intervals {
+        let left = if i == 0 {
+            decoder::first_edge(source, &mut bytes)?
+        }
+        else {
+            prev_end + read_varint(&mut bytes)? + 1
+        };
+        let len = read_varint(&mut bytes)? + MIN_INTERVAL;
+        intervals.push((left, len));
+        prev_end = left + len;
+    }
+
+    Some(Parts {
+        r,
+        blocks,
+        intervals,
+        residuals: bytes.as_slice(),
+    })
+}
+
+/// Two sorted iterators as one.
+struct Merge<A: Iterator, B: Iterator>
+{
+    a: Peekable<A>,
+    b: Peekable<B>,
+}
+
+impl<V: Ord, A: Iterator<Item = V>, B: Iterator<Item = V>> Iterator for Merge<A, B>
+{
+    type Item = V;
+
+    fn next(&mut self) -> Option<V>
+    {
+        match (self.a.peek(), self.b.peek()) {
+            (Some(x), Some(y)) if x > y => self.b.next(),
+            (Some(_), _) => self.a.next(),
+            (None, _) => self.b.next(),
+        }
+    }
+}
+
+fn merge<V: Ord, A: Iterator<Item = V>, B: Iterator<Item = V>>(a: A, b: B) -> Merge<A, B>
+{
+    Merge {
+        a: a.peekable(),
+        b: b.peekable(),
+    }
+}
+
+/// Appends the neighbors of `source` to `out`, where `bytes(u)` are the
+/// bytes of vertex `u`. At most `max_chain` references are followed, a
+/// reference past them decodes as a vertex without neighbors.
+pub fn decode_into<'a, V: VertexId>(
+    coder: Coder<'_>,
+    max_chain: usize,
+    source: V,
+    bytes: &dyn Fn(usize) -> &'a [u8],
+    out: &mut Vec<V>,
+)
+{
+    // The chain is decoded from its end rather than recursively, see
+    // `try_decode_into`.
+    let mut chain = Vec::new();
+    let mut u = source.to_usize();
+    while let Some(parts) = parse(u as u64, bytes(u)) {
+        let r = parts.r;
+        chain.push((u, parts));
+        match u.checked_sub(r) {
+            Some(v) if r > 0 && chain.len() <= max_chain => u = v,
+            _ => break,
+        }
+    }
+
+    let mut reference = Vec::new();
+    let mut list = Vec::new();
+    for (u, parts) in chain.iter().rev() {
+        list.clear();
+        decode_list(coder, V::from_usize(*u), parts, &reference, &mut list);
+        std::mem::swap(&mut reference, &mut list);
+    }
+    out.extend_from_slice(&reference);
+}
+
+/// The neighbors of `source` from its parts with the neighbors of its
+/// reference.
+fn decode_list<V: VertexId>(coder: Coder<'_>, source: V, parts: &Parts<'_>, reference: &[V], out: &mut Vec<V>)
+{
+    let mut copied = Vec::new();
+    if parts.r > 0 {
+        let mut at = 0;
+        for (i, &len) in parts.blocks.iter().enumerate() {
+            if i % 2 == 0 {
+                copied.extend_from_slice(reference.get(at..at + len).unwrap_or(&[]));
+            }
+            at += len;
+        }
+    }
+
+    let intervals = parts
+        .intervals
+        .iter()
+        .flat_map(|&(left, len)| (left..left + len).map(V::from_u64));
+
+    out.extend(merge(
+        merge(copied.into_iter(), intervals),
+        coder.decode(source, parts.residuals),
+    ));
+}
+
+/// Like `decode_into` but checks the bytes of `source` and of its chain of
+/// references, see `checked`, failing if the chain is longer than
+/// `max_chain`. The chain is decoded from its end, so that a corrupt chain
+/// can't overflow the stack.
+pub fn try_decode_into<'a, V: VertexId>(
+    coder: Coder<'_>,
+    order: u64,
+    max_chain: usize,
+    source: V,
+    bytes: &dyn Fn(usize) -> Checked<Cursor<'a>>,
+    out: &mut Vec<V>,
+) -> Result<(), DecodeError>
+{
+    let mut chain = vec![source.to_usize()];
+    loop {
+        let u = chain[chain.len() - 1];
+        let mut cursor = bytes(u).map_err(|err| DecodeError::new(u, err))?;
+        if cursor.is_empty() {
+            break;
+        }
+        let offset = cursor.offset();
+        match cursor.varint().map_err(|err| DecodeError::new(u, err))? {
+            0 => break,
+            r if r > u as u64 => return Err(DecodeError::new(u, (offset, "the reference is not a vertex"))),
+            _ if chain.len() > max_chain => {
+                return Err(DecodeError::new(u, (offset, "the reference chain is too long")))
+            }
+            r => chain.push(u - r as usize),
+        }
+    }
+
+    let mut reference = Vec::new();
+    let mut list = Vec::new();
+    for &u in chain.iter().rev() {
+        list.clear();
+        bytes(u)
+            .and_then(|cursor| try_decode_list(coder, order, V::from_usize(u), cursor, &reference, &mut list))
+            .map_err(|err| DecodeError::new(u, err))?;
+        std::mem::swap(&mut reference, &mut list);
+    }
+    out.extend_from_slice(&reference);
+    Ok(())
+}
+
+/// The neighbors of `source` with the neighbors of its reference.
+fn try_decode_list<V: VertexId>(
+    coder: Coder<'_>,
+    order: u64,
+    source: V,
+    mut bytes: Cursor<'_>,
+    reference: &[V],
+    out: &mut Vec<V>,
+) -> Checked<()>
+{
+    if bytes.is_empty() {
+        return Ok(());
+    }
+    let start = bytes.offset();
+
+    let mut copied = Vec::new();
+    if bytes.varint()? > 0 {
+        let num_blocks = bytes.varint()?;
+        let mut at = 0;
+        for i in 0..num_blocks {
+            let offset = bytes.offset();
+            let end = bytes
+                .varint()?
+                .checked_add(at as u64 + (i > 0) as u64)
+                .filter(|&end| end <= reference.len() as u64)
+                .ok_or((offset, "a copy block is past the reference"))? as usize;
+            if i % 2 == 0 {
+                copied.extend_from_slice(&reference[at..end]);
+            }
+            at = end;
+        }
+    }
+
+    let num_intervals = bytes.varint()?;
+    let mut intervals = Vec::new();
+    let mut prev_end = 0u64;
+    for i in 0..num_intervals {
+        let offset = bytes.offset();
+        let left = if i == 0 {
+            Some(bytes.first_edge(source.to_u64())?)
+        }
+        else {
+            bytes.varint()?.checked_add(prev_end).and_then(|left| left.checked_add(1))
+        };
+        let len = bytes.varint()?.checked_add(MIN_INTERVAL);
+        let (left, end) = left
+            .zip(len)
+            .and_then(|(left, len)| Some((left, left.checked_add(len)?)))
+            .filter(|&(_, end)| end <= order && V::try_from_u64(end - 1).is_some())
+            .ok_or((offset, OUT_OF_RANGE))?;
+        intervals.push((left, end - left));
+        prev_end = end;
+    }
+
+    let mut residuals = Vec::new();
+    coder.try_decode_into(source, bytes, &mut Sink::new(&mut residuals, order))?;
+
+    let intervals = intervals
+        .iter()
+        .flat_map(|&(left, len)| (left..left + len).map(V::from_u64));
+    let len = out.len();
+    out.extend(merge(merge(copied.into_iter(), intervals), residuals.into_iter()));
+    if out[len..].windows(2).any(|w| w[0] >= w[1]) {
+        return Err((start, NOT_INCREASING));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use crate::{codec::Codec, CsrView, Edge, Error, CSR};
+
+    // Vertices that share most of their neighbors with the vertices before
+    // them, and have runs of consecutive ids of their own.
+    fn edges(rng: &mut StdRng) -> Vec<Edge>
+    {
+        let shared: Vec<u32> = (0..200).map(|_| rng.gen_range(0..5000)).collect();
+        (0..200u32)
+            .flat_map(|u| {
+                let copied: Vec<u32> = shared.iter().copied().filter(|_| rng.gen_bool(0.8)).collect();
+                let start = rng.gen_range(0..5000);
+                let len = rng.gen_range(0..10);
+                copied.into_iter().chain(start..start + len).map(move |v| Edge(u, v))
+            })
+            .collect()
+    }
+
+    // The longest chain of references in the header of a written graph.
+    fn longest(buf: &[u8]) -> usize
+    {
+        u32::from_le_bytes(buf[40..44].try_into().unwrap()) as usize
+    }
+
+    #[test]
+    fn round_trips()
+    {
+        let mut rng = StdRng::seed_from_u64(20);
+        let edges = edges(&mut rng);
+        let plain = CSR::from_edges(edges.clone());
+        for codec in [Codec::ByteRle, Codec::Nibble, Codec::Gamma, Codec::Rans] {
+            for (window, max_chain) in [(1, 1), (7, 3), (20, 0), (20, usize::MAX)] {
+                let csr = CSR::builder()
+                    .codec(codec)
+                    .references(window, max_chain)
+                    .build_verified(edges.clone())
+                    .unwrap();
+                csr.validate().unwrap();
+
+                let mut buf = Vec::new();
+                csr.write_to(&mut buf).unwrap();
+                assert!(longest(&buf) <= max_chain);
+                let copy: CSR = CSR::read_from(&buf[..]).unwrap();
+                let view: CsrView = CsrView::new(&buf[..]).unwrap();
+                for u in 0..plain.order() as u32 {
+                    let neighbors: Vec<u32> = plain.neighbors(u).collect();
+                    assert_eq!(neighbors, csr.neighbors(u).collect::<Vec<_>>());
+                    assert_eq!(neighbors, copy.neighbors(u).collect::<Vec<_>>());
+                    assert_eq!(neighbors, view.neighbors(u).collect::<Vec<_>>());
+                    assert_eq!(neighbors.len(), csr.degree(u));
+                    assert_eq!(neighbors.get(3).copied(), csr.neighbor_at(u, 3));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn chains_are_bounded_by_the_header()
+    {
+        let mut rng = StdRng::seed_from_u64(21);
+        let csr = CSR::builder().references(1, usize::MAX).build(edges(&mut rng));
+        let mut buf = Vec::new();
+        csr.write_to(&mut buf).unwrap();
+        let longest = longest(&buf);
+        assert!(longest > 1);
+
+        buf[40..44].copy_from_slice(&(longest as u32 - 1).to_le_bytes());
+        match CSR::<u32>::read_from(&buf[..]) {
+            Err(Error::Corrupt(err)) => assert_eq!("the reference chain is too long", err.reason),
+            Err(err) => panic!("{}", err),
+            Ok(_) => panic!("a chain longer than the header decoded"),
+        }
+        assert!(CsrView::<u32>::new(&buf[..]).is_err());
+
+        // Unchecked decoding stops at the bound rather than following the
+        // chain.
+        let view: CsrView = unsafe { CsrView::new_unchecked(&buf[..]) }.unwrap();
+        for u in 0..view.order() as u32 {
+            view.neighbors(u).for_each(drop);
+        }
+    }
+
+    #[test]
+    fn block_len_fails()
+    {
+        let result = CSR::builder()
+            .references(3, 3)
+            .block_len(10)
+            .build_verified((0..100u32).map(|v| Edge(0, v)));
+        assert!(matches!(result, Err(Error::InvalidOptions(_))));
+    }
+}
@@ -3,7 +3,7 @@ use std::{slice, sync::atomic::{AtomicU64, Ordering}};
 
 use rayon::prelude::*;
 
-use crate::{CSR, Edge};
+use crate::{graph::Graph, vertex::VertexId, CSR, Edge};
 
 #[inline]
 fn atomic_add_f64(x: &AtomicU64, y: f64)
@@ -18,22 +18,23 @@ fn atomic_add_f64(x: &AtomicU64, y: f64)
     }
 }
 
-fn calc_prob(
-    v: u32,
+#[allow(clippy::too_many_arguments)]
+fn calc_prob<V: VertexId>(
+    v: V,
     weight: f64,
     mut sizes: slice::Iter<'_, usize>,
-    incoming: &CSR,
+    incoming: &CSR<V>,
     threshold: usize,
     in_degree: &[usize],
     out_degree: &[usize],
     p: &[AtomicU64]
 ) {
     if let Some(&k) = sizes.next() {
-        let v_ix = v as usize; 
+        let v_ix = v.to_usize(); 
         if in_degree[v_ix] < threshold {
             let prob = (k as f64 / (std::cmp::max(in_degree[v_ix], k) as f64)) * weight;
             for u in incoming.neighbors(v) {
-                let u_ix = u as usize;
+                let u_ix = u.to_usize();
                 if out_degree[u_ix] < threshold {
                     atomic_add_f64(&p[u_ix], prob);
                     calc_prob(
@@ -52,22 +53,22 @@ fn calc_prob(
     }
 }
 
-pub fn by_access_probabilites(csr: &CSR, train_idx: &[bool], sizes: &[usize]) -> (CSR, Vec<u32>)
+pub fn by_access_probabilites<G: Graph + Sync>(csr: &G, train_idx: &[bool], sizes: &[usize]) -> (CSR<G::Vertex>, Vec<G::Vertex>)
 {
     let incoming = CSR::from_edges_with_capacity(csr.size(), csr.edges().map(|e| Edge(e.1, e.0)));
 
     let threshold = (csr.order() as f64).sqrt().ceil() as usize;
 
-    let num_nodes = csr.order() as u32;
+    let num_nodes = csr.order();
 
-    let out_degree: Vec<usize> = (0u32..num_nodes)
+    let out_degree: Vec<usize> = (0..num_nodes)
         .into_par_iter()
-        .map(|v| csr.degree(v))
+        .map(|v| csr.degree(G::Vertex::from_usize(v)))
         .collect();
 
-    let in_degree: Vec<usize> = (0u32..num_nodes)
+    let in_degree: Vec<usize> = (0..num_nodes)
         .into_par_iter()
-        .map(|v| incoming.degree(v))
+        .map(|v| incoming.degree(G::Vertex::from_usize(v)))
         .collect();
 
     let p: Vec<AtomicU64> =
@@ -79,7 +80,7 @@ pub fn by_access_probabilites(csr: &CSR, train_idx: &[bool], sizes: &[usize]) ->
 
     (0..csr.order()).into_par_iter().for_each(|v| {
         if train_idx[v] {
-            calc_prob(v as u32, 1f64, sizes.clone(), &incoming, threshold, &in_degree[..], &out_degree[..], &p[..]);
+            calc_prob(G::Vertex::from_usize(v), 1f64, sizes.clone(), &incoming, threshold, &in_degree[..], &out_degree[..], &p[..]);
         }
     });
 
@@ -96,11 +97,11 @@ pub fn by_access_probabilites(csr: &CSR, train_idx: &[bool], sizes: &[usize]) ->
         })
         .collect();
 
-    let mut vs: Vec<u32> = (0u32..csr.order() as u32).collect();
+    let mut vs: Vec<G::Vertex> = (0..csr.order()).map(G::Vertex::from_usize).collect();
 
-    vs.par_sort_unstable_by(|&a, &b| probs[b as usize].total_cmp(&probs[a as usize]));
+    vs.par_sort_unstable_by(|&a, &b| probs[b.to_usize()].total_cmp(&probs[a.to_usize()]));
 
-    let new_csr = CSR::from_edges_with_capacity(csr.size(), csr.edges().map(|e| Edge(vs[e.0 as usize], vs[e.1 as usize])));
+    let new_csr = CSR::from_edges_with_capacity(csr.size(), csr.edges().map(|e| Edge(vs[e.0.to_usize()], vs[e.1.to_usize()])));
 
     (new_csr, vs)
 }
@@ -0,0 +1,550 @@
+// glzip is a graph compression library for graph learning systems
+// Copyright (C) 2022 Jacob Konrad
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// SIMD unpacking of the groups of `u32` graphs, behind the `simd` feature.
+//
+// The diffs of a group all have the same width of 1 to 4 bytes, so a single
+// byte shuffle turns the next 4 big endian diffs into 4 little endian `u32`
+// lanes, followed by an in-register prefix sum and the previous neighbor.
+// Loads may read past the group, but never past the block, and the lanes
+// past the group are written to spare capacity and then dropped. What the
+// loads can't cover is unpacked by `decoder::unpack_scalar`, so the output
+// is identical to the scalar path.
+//
+// Stream VByte differences are unpacked the same way, 4 at a time, with the
+// shuffle and the byte length of their control byte from a table.
+
+use crate::{
+    decoder::{self, Unpack},
+    stream_vbyte,
+};
+
+// The shuffle of 4 diffs of `N` bytes, `SHUFFLE[N - 1]`. 0x80 zeroes the
+// byte on both x86 and aarch64.
+#[allow(dead_code)]
+static SHUFFLE: [[u8; 16]; 4] = [
+    [0, 0x80, 0x80, 0x80, 1, 0x80, 0x80, 0x80, 2, 0x80, 0x80, 0x80, 3, 0x80, 0x80, 0x80],
+    [1, 0, 0x80, 0x80, 3, 2, 0x80, 0x80, 5, 4, 0x80, 0x80, 7, 6, 0x80, 0x80],
+    [2, 1, 0, 0x80, 5, 4, 3, 0x80, 8, 7, 6, 0x80, 11, 10, 9, 0x80],
+    [3, 2, 1, 0, 7, 6, 5, 4, 11, 10, 9, 8, 15, 14, 13, 12],
+];
+
+// The shuffle of the 4 little endian diffs of each Stream VByte control byte
+// and their total length.
+#[allow(dead_code)]
+static STREAM_SHUFFLE: [[u8; 16]; 256] = stream_shuffles();
+
+#[allow(dead_code)]
+static STREAM_LEN: [u8; 256] = stream_lens();
+
+const fn stream_shuffles() -> [[u8; 16]; 256]
+{
+    let mut table = [[0x80; 16]; 256];
+    let mut control = 0;
+    while control < 256 {
+        let mut at = 0;
+        let mut lane = 0;
+        while lane < 4 {
+            let len = (control >> (2 * lane) & 3) + 1;
+            let mut byte = 0;
+            while byte < len {
+                table[control][4 * lane + byte] = at as u8;
+                at += 1;
+                byte += 1;
+            }
+            lane += 1;
+        }
+        control += 1;
+    }
+    table
+}
+
+const fn stream_lens() -> [u8; 256]
+{
+    let mut table = [0; 256];
+    let mut control = 0;
+    while control < 256 {
+        let mut lane = 0;
+        while lane < 4 {
+            table[control] += (control >> (2 * lane) & 3) as u8 + 1;
+            lane += 1;
+        }
+        control += 1;
+    }
+    table
+}
+
+/// The fastest group unpacking the CPU supports.
+pub fn unpack() -> Unpack<u32>
+{
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            return x86::unpack_avx2;
+        }
+        if is_x86_feature_detected!("sse4.1") {
+            return x86::unpack_sse41;
+        }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    {
+        if std::arch::is_aarch64_feature_detected!("neon") {
+            return aarch64::unpack_neon;
+        }
+    }
+
+    decoder::unpack_scalar::<u32>
+}
+
+/// The fastest Stream VByte unpacking the CPU supports.
+pub fn unpack_stream() -> stream_vbyte::Unpack<u32>
+{
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("sse4.1") {
+            return x86::unpack_stream_sse41;
+        }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    {
+        if std::arch::is_aarch64_feature_detected!("neon") {
+            return aarch64::unpack_stream_neon;
+        }
+    }
+
+    stream_vbyte::unpack_scalar::<u32>
+}
+
+/// Sets the length of `out` to the `done` lanes written past `len` and
+/// unpacks the rest of the group with the scalar code.
+#[allow(dead_code)]
+unsafe fn finish(
+    bytes: &[u8],
+    num_bytes: usize,
+    run_length: usize,
+    done: usize,
+    len: usize,
+    prev_edge: &mut u64,
+    out: &mut Vec<u32>,
+)
+{
+    let done = done.min(run_length);
+    out.set_len(len + done);
+    if done > 0 {
+        *prev_edge = out[len + done - 1] as u64;
+    }
+    if done < run_length {
+        decoder::unpack_scalar(&bytes[done * num_bytes..], num_bytes, run_length - done, prev_edge, out);
+    }
+}
+
+/// Sets the length of `out` to include the lanes of the `quads` control
+/// bytes written past its end, which used `at` bytes of `data`, and unpacks
+/// the rest with the scalar code.
+#[allow(dead_code)]
+unsafe fn finish_stream(
+    controls: &[u8],
+    data: &[u8],
+    count: usize,
+    quads: usize,
+    at: usize,
+    prev_edge: &mut u64,
+    out: &mut Vec<u32>,
+)
+{
+    let len = out.len();
+    out.set_len(len + 4 * quads);
+    if quads > 0 {
+        *prev_edge = out[len + 4 * quads - 1] as u64;
+    }
+    stream_vbyte::unpack_scalar(&controls[quads..], &data[at..], count - 4 * quads, prev_edge, out);
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86
+{
+    use std::arch::x86_64::*;
+
+    use super::{finish, finish_stream, SHUFFLE, STREAM_LEN, STREAM_SHUFFLE};
+
+    pub fn unpack_sse41(bytes: &[u8], num_bytes: usize, run_length: usize, prev_edge: &mut u64, out: &mut Vec<u32>)
+    {
+        unsafe { sse41(bytes, num_bytes, run_length, prev_edge, out) }
+    }
+
+    pub fn unpack_avx2(bytes: &[u8], num_bytes: usize, run_length: usize, prev_edge: &mut u64, out: &mut Vec<u32>)
+    {
+        unsafe { avx2(bytes, num_bytes, run_length, prev_edge, out) }
+    }
+
+    pub fn unpack_stream_sse41(controls: &[u8], data: &[u8], count: usize, prev_edge: &mut u64, out: &mut Vec<u32>)
+    {
+        unsafe { stream_sse41(controls, data, count, prev_edge, out) }
+    }
+
+    #[target_feature(enable = "sse4.1")]
+    unsafe fn sse41(bytes: &[u8], num_bytes: usize, run_length: usize, prev_edge: &mut u64, out: &mut Vec<u32>)
+    {
+        let len = out.len();
+        out.reserve(run_length + 4);
+        let src = bytes.as_ptr();
+        let dst = out.as_mut_ptr().add(len);
+
+        let mask = _mm_loadu_si128(SHUFFLE[num_bytes - 1].as_ptr().cast());
+        let mut prev = _mm_set1_epi32(*prev_edge as u32 as i32);
+
+        let mut i = 0;
+        while i < run_length && i * num_bytes + 16 <= bytes.len() {
+            let x = _mm_shuffle_epi8(_mm_loadu_si128(src.add(i * num_bytes).cast()), mask);
+            let x = _mm_add_epi32(x, _mm_slli_si128::<4>(x));
+            let x = _mm_add_epi32(x, _mm_slli_si128::<8>(x));
+            let x = _mm_add_epi32(x, prev);
+            _mm_storeu_si128(dst.add(i).cast(), x);
+            prev = _mm_shuffle_epi32::<0xff>(x);
+            i += 4;
+        }
+
+        finish(bytes, num_bytes, run_length, i, len, prev_edge, out);
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn avx2(bytes: &[u8], num_bytes: usize, run_length: usize, prev_edge: &mut u64, out: &mut Vec<u32>)
+    {
+        let len = out.len();
+        out.reserve(run_length + 8);
+        let src = bytes.as_ptr();
+        let dst = out.as_mut_ptr().add(len);
+
+        let mask = _mm_loadu_si128(SHUFFLE[num_bytes - 1].as_ptr().cast());
+        let mask = _mm256_set_m128i(mask, mask);
+        let mut prev = _mm256_set1_epi32(*prev_edge as u32 as i32);
+
+        // The shuffle stays within 128 bit lanes, so the two halves of 4
+        // diffs are loaded separately.
+        let mut i = 0;
+        while i < run_length && (i + 4) * num_bytes + 16 <= bytes.len() {
+            let lo = _mm_loadu_si128(src.add(i * num_bytes).cast());
+            let hi = _mm_loadu_si128(src.add((i + 4) * num_bytes).cast());
+            let x = _mm256_shuffle_epi8(_mm256_set_m128i(hi, lo), mask);
+            let x = _mm256_add_epi32(x, _mm256_slli_si256::<4>(x));
+            let x = _mm256_add_epi32(x, _mm256_slli_si256::<8>(x));
+            // Carry the sum of the low half into the high half.
+            let last = _mm256_shuffle_epi32::<0xff>(x);
+            let x = _mm256_add_epi32(x, _mm256_permute2x128_si256::<0x08>(last, last));
+            let x = _mm256_add_epi32(x, prev);
+            _mm256_storeu_si256(dst.add(i).cast(), x);
+            prev = _mm256_permutevar8x32_epi32(x, _mm256_set1_epi32(7));
+            i += 8;
+        }
+
+        finish(bytes, num_bytes, run_length, i, len, prev_edge, out);
+    }
+
+    #[target_feature(enable = "sse4.1")]
+    unsafe fn stream_sse41(controls: &[u8], data: &[u8], count: usize, prev_edge: &mut u64, out: &mut Vec<u32>)
+    {
+        let len = out.len();
+        out.reserve(count);
+        let src = data.as_ptr();
+        let dst = out.as_mut_ptr().add(len);
+
+        let mut prev = _mm_set1_epi32(*prev_edge as u32 as i32);
+
+        // Only full control bytes, and only while 16 bytes can be loaded.
+        let mut quads = 0;
+        let mut at = 0;
+        while quads < count / 4 && at + 16 <= data.len() {
+            let control = controls[quads] as usize;
+            let mask = _mm_loadu_si128(STREAM_SHUFFLE[control].as_ptr().cast());
+            let x = _mm_shuffle_epi8(_mm_loadu_si128(src.add(at).cast()), mask);
+            let x = _mm_add_epi32(x, _mm_slli_si128::<4>(x));
+            let x = _mm_add_epi32(x, _mm_slli_si128::<8>(x));
+            let x = _mm_add_epi32(x, prev);
+            _mm_storeu_si128(dst.add(4 * quads).cast(), x);
+            prev = _mm_shuffle_epi32::<0xff>(x);
+            at += STREAM_LEN[control] as usize;
+            quads += 1;
+        }
+
+        finish_stream(controls, data, count, quads, at, prev_edge, out);
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod aarch64
+{
+    use std::arch::aarch64::*;
+
+    use super::{finish, finish_stream, SHUFFLE, STREAM_LEN, STREAM_SHUFFLE};
+
+    pub fn unpack_neon(bytes: &[u8], num_bytes: usize, run_length: usize, prev_edge: &mut u64, out: &mut Vec<u32>)
+    {
+        unsafe { neon(bytes, num_bytes, run_length, prev_edge, out) }
+    }
+
+    pub fn unpack_stream_neon(controls: &[u8], data: &[u8], count: usize, prev_edge: &mut u64, out: &mut Vec<u32>)
+    {
+        unsafe { stream_neon(controls, data, count, prev_edge, out) }
+    }
+
+    #[target_feature(enable = "neon")]
+    unsafe fn neon(bytes: &[u8], num_bytes: usize, run_length: usize, prev_edge: &mut u64, out: &mut Vec<u32>)
+    {
+        let len = out.len();
+        out.reserve(run_length + 4);
+        let src = bytes.as_ptr();
+        let dst = out.as_mut_ptr().add(len);
+
+        let mask = vld1q_u8(SHUFFLE[num_bytes - 1].as_ptr());
+        let zero = vdupq_n_u32(0);
+        let mut prev = vdupq_n_u32(*prev_edge as u32);
+
+        let mut i = 0;
+        while i < run_length && i * num_bytes + 16 <= bytes.len() {
+            let x = vreinterpretq_u32_u8(vqtbl1q_u8(vld1q_u8(src.add(i * num_bytes)), mask));
+            let x = vaddq_u32(x, vextq_u32::<3>(zero, x));
+            let x = vaddq_u32(x, vextq_u32::<2>(zero, x));
+            let x = vaddq_u32(x, prev);
+            vst1q_u32(dst.add(i), x);
+            prev = vdupq_laneq_u32::<3>(x);
+            i += 4;
+        }
+
+        finish(bytes, num_bytes, run_length, i, len, prev_edge, out);
+    }
+
+    #[target_feature(enable = "neon")]
+    unsafe fn stream_neon(controls: &[u8], data: &[u8], count: usize, prev_edge: &mut u64, out: &mut Vec<u32>)
+    {
+        let len = out.len();
+        out.reserve(count);
+        let src = data.as_ptr();
+        let dst = out.as_mut_ptr().add(len);
+
+        let zero = vdupq_n_u32(0);
+        let mut prev = vdupq_n_u32(*prev_edge as u32);
+
+        let mut quads = 0;
+        let mut at = 0;
+        while quads < count / 4 && at + 16 <= data.len() {
+            let control = controls[quads] as usize;
+            let mask = vld1q_u8(STREAM_SHUFFLE[control].as_ptr());
+            let x = vreinterpretq_u32_u8(vqtbl1q_u8(vld1q_u8(src.add(at)), mask));
+            let x = vaddq_u32(x, vextq_u32::<3>(zero, x));
+            let x = vaddq_u32(x, vextq_u32::<2>(zero, x));
+            let x = vaddq_u32(x, prev);
+            vst1q_u32(dst.add(4 * quads), x);
+            prev = vdupq_laneq_u32::<3>(x);
+            at += STREAM_LEN[control] as usize;
+            quads += 1;
+        }
+
+        finish_stream(controls, data, count, quads, at, prev_edge, out);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::{codec::Codec, encoder, Edge, CSR};
+
+    /// Every unpacking of groups the CPU supports.
+    fn unpacks() -> Vec<(&'static str, Unpack<u32>)>
+    {
+        #[allow(unused_mut)]
+        let mut unpacks: Vec<(&'static str, Unpack<u32>)> = vec![];
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("sse4.1") {
+                unpacks.push(("sse4.1", x86::unpack_sse41));
+            }
+            if is_x86_feature_detected!("avx2") {
+                unpacks.push(("avx2", x86::unpack_avx2));
+            }
+        }
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            if std::arch::is_aarch64_feature_detected!("neon") {
+                unpacks.push(("neon", aarch64::unpack_neon));
+            }
+        }
+
+        unpacks
+    }
+
+    /// Every Stream VByte unpacking the CPU supports.
+    fn stream_unpacks() -> Vec<(&'static str, stream_vbyte::Unpack<u32>)>
+    {
+        #[allow(unused_mut)]
+        let mut unpacks: Vec<(&'static str, stream_vbyte::Unpack<u32>)> = vec![];
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("sse4.1") {
+                unpacks.push(("sse4.1", x86::unpack_stream_sse41));
+            }
+        }
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            if std::arch::is_aarch64_feature_detected!("neon") {
+                unpacks.push(("neon", aarch64::unpack_stream_neon));
+            }
+        }
+
+        unpacks
+    }
+
+    /// A difference of exactly `len` bytes, small enough that 64 of them
+    /// don't overflow.
+    fn diff(rng: &mut StdRng, len: usize) -> u64
+    {
+        let low = 1u64 << (8 * (len - 1));
+        rng.gen_range(low..2 * low)
+    }
+
+    #[test]
+    fn groups_match_scalar()
+    {
+        let mut rng = StdRng::seed_from_u64(16);
+        for (name, unpack) in unpacks() {
+            for num_bytes in 1..=4 {
+                for run_length in 1..=64 {
+                    // Bytes of the next groups after this one, from none to
+                    // more than a vector.
+                    for tail in [0, 1, 3, 4, 15, 16, 17, 40] {
+                        let mut bytes = Vec::new();
+                        for _ in 0..run_length {
+                            bytes.extend_from_slice(&diff(&mut rng, num_bytes).to_be_bytes()[8 - num_bytes..]);
+                        }
+                        bytes.extend((0..tail).map(|_| rng.gen::<u8>()));
+
+                        let start = rng.gen_range(0..1 << 20);
+                        let (mut expected, mut expected_edge) = (vec![7u32, 8], start);
+                        decoder::unpack_scalar(&bytes, num_bytes, run_length, &mut expected_edge, &mut expected);
+                        let (mut out, mut prev_edge) = (vec![7u32, 8], start);
+                        unpack(&bytes, num_bytes, run_length, &mut prev_edge, &mut out);
+
+                        let case = (name, num_bytes, run_length, tail);
+                        assert_eq!(expected, out, "{:?}", case);
+                        assert_eq!(expected_edge, prev_edge, "{:?}", case);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stream_vbyte_matches_scalar()
+    {
+        let mut rng = StdRng::seed_from_u64(18);
+        for (name, unpack) in stream_unpacks() {
+            for count in 0..=70usize {
+                for tail in [0, 1, 16] {
+                    let mut controls = vec![0u8; count.div_ceil(4)];
+                    let mut data = Vec::new();
+                    for i in 0..count {
+                        let len = rng.gen_range(1..=4);
+                        controls[i / 4] |= (len as u8 - 1) << (2 * (i % 4));
+                        data.extend_from_slice(&diff(&mut rng, len).to_le_bytes()[..len]);
+                    }
+                    data.extend((0..tail).map(|_| rng.gen::<u8>()));
+
+                    let start = rng.gen_range(0..1 << 20);
+                    let (mut expected, mut expected_edge) = (vec![7u32], start);
+                    stream_vbyte::unpack_scalar(&controls, &data, count, &mut expected_edge, &mut expected);
+                    let (mut out, mut prev_edge) = (vec![7u32], start);
+                    unpack(&controls, &data, count, &mut prev_edge, &mut out);
+
+                    assert_eq!(expected, out, "{:?}", (name, count, tail));
+                    assert_eq!(expected_edge, prev_edge, "{:?}", (name, count, tail));
+                }
+            }
+        }
+    }
+
+    /// Sorted neighbors apart by 1 to `max_len` bytes, as many as to leave
+    /// partial groups and tails shorter than a vector.
+    fn random_neighbors(rng: &mut StdRng, max_len: usize) -> Vec<u32>
+    {
+        let degree = rng.gen_range(1..300);
+        let mut v: u32 = rng.gen_range(0..1 << 10);
+        let mut neighbors = vec![v];
+        for _ in 1..degree {
+            let len = rng.gen_range(1..=max_len);
+            match v.checked_add(diff(rng, len) as u32) {
+                Some(w) => v = w,
+                None => break,
+            }
+            neighbors.push(v);
+        }
+        neighbors
+    }
+
+    #[test]
+    fn decode_into_matches_decode()
+    {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..500 {
+            let neighbors = random_neighbors(&mut rng, 4);
+            let source = rng.gen_range(0..1 << 20);
+
+            let mut bytes = Vec::new();
+            encoder::encode(&mut bytes, source, neighbors.iter().copied());
+            let mut out = Vec::new();
+            decoder::decode_into(source, &bytes, &mut out);
+            assert_eq!(decoder::decode(source, &bytes).collect::<Vec<_>>(), out);
+            assert_eq!(neighbors, out);
+
+            bytes.clear();
+            stream_vbyte::encode(&mut bytes, source, neighbors.iter().copied());
+            out.clear();
+            stream_vbyte::decode_into(source, &bytes, &mut out);
+            assert_eq!(stream_vbyte::decode(source, &bytes).collect::<Vec<_>>(), out);
+            assert_eq!(neighbors, out);
+        }
+    }
+
+    #[test]
+    fn neighbors_into_matches_neighbors()
+    {
+        // Ids of up to 3 bytes keep the order of the graph small.
+        let mut rng = StdRng::seed_from_u64(2);
+        let edges: Vec<Edge> = (0..300)
+            .flat_map(|u| random_neighbors(&mut rng, 2).into_iter().map(move |v| Edge(u, v)))
+            .collect();
+        for codec in [Codec::ByteRle, Codec::StreamVByte] {
+            for csr in [
+                CSR::builder().codec(codec).build(edges.iter().copied()),
+                CSR::builder().codec(codec).block_len(50).build(edges.iter().copied()),
+            ] {
+                let mut buf = Vec::new();
+                for u in 0..csr.order() as u32 {
+                    buf.clear();
+                    csr.neighbors_into(u, &mut buf);
+                    assert_eq!(csr.neighbors(u).collect::<Vec<_>>(), buf, "{:?} vertex {}", codec, u);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,288 @@
+// glzip is a graph compression library for graph learning systems
+// Copyright (C) 2022 Jacob Konrad
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// A skip index over the group headers of high degree vertices.
+//
+// For every vertex with more than SKIP_LEN neighbors it remembers where the
+// group holding neighbor `k * SKIP_LEN` starts, for k >= 1, together with
+// the neighbor before that group, so that decoding the i-th neighbor only
+// has to unpack the groups after skip `i / SKIP_LEN`, fewer than SKIP_LEN
+// plus a group's worth of neighbors. Vertices with fewer neighbors decode
+// from the start, which is as cheap.
+//
+// Block encoded vertices need no skips, their blocks are found directly.
+// The skips point into byte-RLE groups, graphs with other codecs have none
+// and decode from the start of the vertex or block.
+//
+// The index is kept the way it is written to a file, so that a `CsrView`
+// borrows it from its buffer. Every word is a little endian u64:
+//
+//   H             the number of hubs, the vertices with skips
+//   H words       the hubs in ascending order
+//   H + 1 words   the first skip of each hub, then the number of skips S
+//   3 S words     per skip the index of the first neighbor in the group,
+//                 the position of the group header relative to the
+//                 vertex's bytes and the neighbor before the group
+
+use std::{borrow::Cow, cmp::Ordering, io::Read, marker::PhantomData, ops::Range};
+
+use rayon::prelude::*;
+
+use crate::{
+    codec::{Blocks, Codec, Coder},
+    decoder,
+    error::Error,
+    format,
+    vertex::VertexId,
+};
+
+pub const SKIP_LEN: usize = 256;
+
+const WORD: usize = 8;
+
+#[inline]
+fn read_word(words: &[u8], i: usize) -> u64
+{
+    u64::from_le_bytes(words[i * WORD..(i + 1) * WORD].try_into().unwrap())
+}
+
+pub struct SkipIndex<'a, V>
+{
+    words: Cow<'a, [u8]>,
+    hubs: usize,
+    marker: PhantomData<V>,
+}
+
+impl<V: VertexId> Default for SkipIndex<'_, V>
+{
+    fn default() -> Self
+    {
+        Self::from_words(Cow::Owned(vec![0; 2 * WORD]))
+    }
+}
+
+impl<V: VertexId> SkipIndex<'static, V>
+{
+    /// The index of the first `order` vertices, with their degree and bytes.
+    pub fn new<'e, D, B>(codec: Codec, order: usize, degree: D, bytes: B) -> Self
+    where
+        D: Fn(usize) -> usize + Sync,
+        B: Fn(usize) -> &'e [u8] + Sync,
+    {
+        if codec != Codec::ByteRle {
+            return Self::default();
+        }
+
+        let per_hub: Vec<_> = (0..order)
+            .into_par_iter()
+            .filter_map(|u| {
+                if degree(u) <= SKIP_LEN {
+                    return None;
+                }
+                let bytes = bytes(u);
+                if Blocks::new(bytes).block_len().is_some() {
+                    return None;
+                }
+                let u = V::from_usize(u);
+                Some((u, decoder::skips(u, bytes, SKIP_LEN)))
+            })
+            .collect();
+
+        let num_skips: usize = per_hub.iter().map(|(_, skips)| skips.len()).sum();
+        let mut words = Vec::with_capacity((2 + 2 * per_hub.len() + 3 * num_skips) * WORD);
+        let mut push = |word: u64| words.extend_from_slice(&word.to_le_bytes());
+        push(per_hub.len() as u64);
+        for (u, _) in &per_hub {
+            push(u.to_u64());
+        }
+        let mut start = 0;
+        for (_, skips) in &per_hub {
+            push(start as u64);
+            start += skips.len();
+        }
+        push(start as u64);
+        for (index, offset, prev_edge) in per_hub.into_iter().flat_map(|(_, skips)| skips) {
+            push(index as u64);
+            push(offset as u64);
+            push(prev_edge.to_u64());
+        }
+
+        Self::from_words(Cow::Owned(words))
+    }
+
+    /// Reads an index written as [`SkipIndex::as_bytes`].
+    pub fn read<R: Read>(r: &mut R) -> Result<Self, Error>
+    {
+        let mut words = format::read_bytes(r, WORD as u64)?;
+        let hubs = read_word(&words, 0);
+        let head = hubs.saturating_mul(2).saturating_add(1).saturating_mul(WORD as u64);
+        words.extend(format::read_bytes(r, head)?);
+        let skips = read_word(&words, words.len() / WORD - 1);
+        words.extend(format::read_bytes(r, skips.saturating_mul(3 * WORD as u64))?);
+        Ok(Self::from_words(Cow::Owned(words)))
+    }
+}
+
+impl<'a, V: VertexId> SkipIndex<'a, V>
+{
+    /// Splits the index written at the start of `bytes` from the bytes after
+    /// it, `None` if they are too short for it.
+    pub fn split(bytes: &'a [u8]) -> Option<(&'a [u8], &'a [u8])>
+    {
+        let hubs = read_word(bytes.get(..WORD)?, 0);
+        let head = hubs.checked_mul(2)?.checked_add(2)?.checked_mul(WORD as u64)?;
+        let head = usize::try_from(head).ok().filter(|&len| len <= bytes.len())?;
+        let skips = read_word(bytes, head / WORD - 1);
+        let len = skips.checked_mul(3 * WORD as u64)?.checked_add(head as u64)?;
+        let len = usize::try_from(len).ok().filter(|&len| len <= bytes.len())?;
+        Some(bytes.split_at(len))
+    }
+
+    /// Borrows an index split off by [`SkipIndex::split`].
+    pub fn borrowed(words: &'a [u8]) -> Self
+    {
+        Self::from_words(Cow::Borrowed(words))
+    }
+
+    fn from_words(words: Cow<'a, [u8]>) -> Self
+    {
+        let hubs = read_word(&words, 0) as usize;
+        Self {
+            words,
+            hubs,
+            marker: PhantomData,
+        }
+    }
+
+    /// The index as it is written to a file.
+    pub fn as_bytes(&self) -> &[u8]
+    {
+        &self.words
+    }
+
+    #[inline]
+    fn word(&self, i: usize) -> u64
+    {
+        read_word(&self.words, i)
+    }
+
+    /// The range of the skips of `source` if it is a hub.
+    fn hub_skips(&self, source: V) -> Option<Range<usize>>
+    {
+        let (mut lo, mut hi) = (0, self.hubs);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.word(1 + mid).cmp(&source.to_u64()) {
+                Ordering::Less => lo = mid + 1,
+                Ordering::Greater => hi = mid,
+                Ordering::Equal => {
+                    let start = 1 + self.hubs + mid;
+                    return Some(self.word(start) as usize..self.word(start + 1) as usize);
+                }
+            }
+        }
+        None
+    }
+
+    /// The `k`th skip, the index of the first neighbor in its group, the
+    /// position of the group header and the neighbor before the group.
+    #[inline]
+    fn skip(&self, k: usize) -> (usize, usize, V)
+    {
+        let at = 2 + 2 * self.hubs + 3 * k;
+        (self.word(at) as usize, self.word(at + 1) as usize, V::from_u64(self.word(at + 2)))
+    }
+
+    /// The `i`th neighbor of `source`, whose encoding is `bytes`.
+    pub fn neighbor_at(&self, coder: Coder<'_>, source: V, bytes: &[u8], i: usize) -> Option<V>
+    {
+        let blocks = Blocks::new(bytes);
+        if let Some(block_len) = blocks.block_len() {
+            if i / block_len >= blocks.len() {
+                return None;
+            }
+            return coder
+                .decode_block(source, blocks.get(i / block_len))
+                .nth(i % block_len);
+        }
+
+        if i >= SKIP_LEN {
+            if let Some(skips) = self.hub_skips(source) {
+                let k = skips.start + i / SKIP_LEN - 1;
+                if k < skips.end {
+                    let (index, offset, prev_edge) = self.skip(k);
+                    return decoder::decode_from(prev_edge, &bytes[offset..]).nth(i - index);
+                }
+            }
+        }
+        coder.decode_block(source, bytes).nth(i)
+    }
+
+    /// Whether `target` is a neighbor of `source`, whose encoding is `bytes`.
+    /// The skips are sorted by their neighbors too, so decoding starts from
+    /// the last group that can't be past `target` and stops at `target`.
+    pub fn contains(&self, coder: Coder<'_>, source: V, bytes: &[u8], target: V) -> bool
+    {
+        let blocks = Blocks::new(bytes);
+        if blocks.block_len().is_some() {
+            // Binary search for the last block whose first neighbor isn't
+            // past `target`.
+            let first = |i| coder.decode_block(source, blocks.get(i)).next();
+            let (mut lo, mut hi) = (0, blocks.len());
+            while lo < hi {
+                let mid = lo + (hi - lo) / 2;
+                if first(mid) <= Some(target) {
+                    lo = mid + 1;
+                }
+                else {
+                    hi = mid;
+                }
+            }
+            return lo > 0
+                && coder
+                    .decode_block(source, blocks.get(lo - 1))
+                    .find(|&w| w >= target)
+                    == Some(target);
+        }
+
+        if let Some(skips) = self.hub_skips(source) {
+            // Binary search for the first skip whose neighbor before it isn't
+            // smaller than `target`.
+            let (mut lo, mut hi) = (skips.start, skips.end);
+            while lo < hi {
+                let mid = lo + (hi - lo) / 2;
+                if self.skip(mid).2 < target {
+                    lo = mid + 1;
+                }
+                else {
+                    hi = mid;
+                }
+            }
+            if lo > skips.start {
+                let (_, offset, prev_edge) = self.skip(lo - 1);
+                return decoder::decode_from(prev_edge, &bytes[offset..])
+                    .find(|&w| w >= target)
+                    == Some(target);
+            }
+        }
+        coder.decode_block(source, bytes).find(|&w| w >= target) == Some(target)
+    }
+
+    pub fn nbytes(&self) -> usize
+    {
+        self.words.len()
+    }
+}
@@ -0,0 +1,228 @@
+// glzip is a graph compression library for graph learning systems
+// Copyright (C) 2022 Jacob Konrad
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Based on:
+//
+// Stream VByte: Faster Byte-Oriented Integer Compression
+//
+// by Daniel Lemire, Nathan Kurz and Christoph Rupp
+//
+// https://arxiv.org/abs/1709.08990
+
+// The first neighbor as in `encoder::first_edge`, the number of differences
+// that follow as a varint, a control byte per 4 differences and then the
+// differences. The 2 bit codes of a control byte, lowest bits first, give
+// the byte lengths of its differences, 1 to 4 in `u32` graphs and 1, 2, 4
+// or 8 in `u64` graphs, and the differences are stored little endian after
+// all of the control bytes. With the lengths apart from the data, 4
+// differences are unpacked with a table lookup and a byte shuffle, see
+// `simd`, for a slightly worse ratio than the byte run length encoding.
+
+use crate::{
+    byte_code,
+    checked::{self, Checked, Cursor, Sink},
+    decoder, encoder,
+    vertex::VertexId,
+};
+
+/// The byte length of a difference with the 2 bit code `code`.
+#[inline]
+fn len<V: VertexId>(code: u8) -> usize
+{
+    if V::BYTES == 4 {
+        code as usize + 1
+    }
+    else {
+        1 << code
+    }
+}
+
+/// The 2 bit code of the shortest length that holds `diff`.
+fn code<V: VertexId>(diff: u64) -> u8
+{
+    let bytes = (64 - diff.leading_zeros()).div_ceil(8).max(1);
+    if V::BYTES == 4 {
+        bytes as u8 - 1
+    }
+    else {
+        bytes.next_power_of_two().trailing_zeros() as u8
+    }
+}
+
+/// Reads a little endian difference of `len` bytes, with a single load if
+/// 8 bytes are left.
+#[inline]
+fn read_le(data: &[u8], len: usize) -> u64
+{
+    match data.first_chunk::<8>() {
+        Some(&chunk) => u64::from_le_bytes(chunk) & (u64::MAX >> (64 - 8 * len)),
+        None => {
+            let mut buf = [0u8; 8];
+            buf[..len].copy_from_slice(&data[..len]);
+            u64::from_le_bytes(buf)
+        }
+    }
+}
+
+pub fn encode<V, I>(bytes: &mut Vec<u8>, source: V, edges: I)
+where
+    V: VertexId,
+    I: IntoIterator<Item = V>,
+{
+    let mut edges = edges.into_iter().map(V::to_u64);
+
+    let mut prev_edge = match edges.next() {
+        Some(edge) => edge,
+        None => return,
+    };
+
+    encoder::first_edge(bytes, source.to_u64(), prev_edge);
+
+    let diffs: Vec<u64> = edges
+        .map(|edge| {
+            let diff = edge - prev_edge;
+            prev_edge = edge;
+            diff
+        })
+        .collect();
+
+    byte_code::write_varint(bytes, diffs.len() as u64);
+
+    let controls = bytes.len();
+    bytes.resize(controls + diffs.len().div_ceil(4), 0);
+    for (i, &diff) in diffs.iter().enumerate() {
+        let code = code::<V>(diff);
+        bytes[controls + i / 4] |= code << (2 * (i % 4));
+        bytes.extend_from_slice(&diff.to_le_bytes()[..len::<V>(code)]);
+    }
+}
+
+/// The first neighbor, the number of differences, the control bytes and the
+/// differences of a block.
+fn parts(source: u64, bytes: &[u8]) -> Option<(u64, usize, &[u8], &[u8])>
+{
+    let mut bytes = bytes.iter();
+    let first = decoder::first_edge(source, &mut bytes)?;
+    let count = byte_code::read_varint(&mut bytes)? as usize;
+    let (controls, data) = bytes.as_slice().split_at(count.div_ceil(4));
+    Some((first, count, controls, data))
+}
+
+pub struct Iter<'a, V>
+{
+    first: Option<V>,
+    prev_edge: u64,
+    index: usize,
+    count: usize,
+    controls: &'a [u8],
+    data: &'a [u8],
+}
+
+impl<'a, V: VertexId> Iterator for Iter<'a, V>
+{
+    type Item = V;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item>
+    {
+        if let Some(first) = self.first.take() {
+            return Some(first);
+        }
+        if self.index == self.count {
+            return None;
+        }
+        let len = len::<V>(self.controls[self.index / 4] >> (2 * (self.index % 4)) & 3);
+        self.prev_edge += read_le(self.data, len);
+        self.data = &self.data[len..];
+        self.index += 1;
+        Some(V::from_u64(self.prev_edge))
+    }
+}
+
+pub fn decode<V: VertexId>(source: V, bytes: &[u8]) -> Iter<'_, V>
+{
+    match parts(source.to_u64(), bytes) {
+        Some((first, count, controls, data)) => Iter {
+            first: Some(V::from_u64(first)),
+            prev_edge: first,
+            index: 0,
+            count,
+            controls,
+            data,
+        },
+        None => Iter {
+            first: None,
+            prev_edge: 0,
+            index: 0,
+            count: 0,
+            controls: &[],
+            data: &[],
+        },
+    }
+}
+
+/// Appends the neighbors of the `count` differences with the control bytes
+/// `controls`, which start at `data` and run to the end of the block.
+pub type Unpack<V> = fn(controls: &[u8], data: &[u8], count: usize, prev_edge: &mut u64, out: &mut Vec<V>);
+
+pub fn unpack_scalar<V: VertexId>(controls: &[u8], mut data: &[u8], count: usize, prev_edge: &mut u64, out: &mut Vec<V>)
+{
+    out.reserve(count);
+    for i in 0..count {
+        let len = len::<V>(controls[i / 4] >> (2 * (i % 4)) & 3);
+        *prev_edge += read_le(data, len);
+        data = &data[len..];
+        out.push(V::from_u64(*prev_edge));
+    }
+}
+
+fn decode_block_into<V: VertexId>(source: V, bytes: &[u8], out: &mut Vec<V>, unpack: Unpack<V>)
+{
+    if let Some((mut prev_edge, count, controls, data)) = parts(source.to_u64(), bytes) {
+        out.push(V::from_u64(prev_edge));
+        unpack(controls, data, count, &mut prev_edge, out);
+    }
+}
+
+/// Like `decode` but appends the neighbors to `out`. With the `simd`
+/// feature, the differences of `u32` graphs are unpacked 4 at a time, see
+/// `VertexId::unpack_stream`.
+pub fn decode_into<V: VertexId>(source: V, bytes: &[u8], out: &mut Vec<V>)
+{
+    decode_block_into(source, bytes, out, V::unpack_stream())
+}
+
+/// Like `decode_into` but checks the number of differences against the
+/// control bytes and the data, see `checked`.
+pub fn try_decode_into<V: VertexId>(source: V, mut bytes: Cursor<'_>, sink: &mut Sink<'_, V>) -> Checked<()>
+{
+    let offset = bytes.offset();
+    let mut prev_edge = bytes.first_edge(source.to_u64())?;
+    sink.push(prev_edge, offset)?;
+
+    let count = bytes.varint()?;
+    let controls = bytes.take(count.div_ceil(4))?.rest();
+    for (i, &control) in controls.iter().enumerate() {
+        for j in 0..(count - 4 * i as u64).min(4) as usize {
+            let len = len::<V>(control >> (2 * j) & 3);
+            let offset = bytes.offset();
+            let diff = read_le(bytes.take(len as u64)?.rest(), len);
+            prev_edge = checked::add(prev_edge, diff, offset)?;
+            sink.push(prev_edge, offset)?;
+        }
+    }
+    bytes.finish()
+}
@@ -23,7 +23,7 @@ pub fn concat<T>(mut xs: Vec<T>, ys: Vec<T>) -> Vec<T>
         xs
     }
     else {
-        xs.extend(ys.into_iter());
+        xs.extend(ys);
         xs
     }
 }
@@ -0,0 +1,167 @@
+// glzip is a graph compression library for graph learning systems
+// Copyright (C) 2022 Jacob Konrad
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The integer types that can be used as vertex ids.
+
+use std::{
+    fmt::{Debug, Display},
+    hash::Hash,
+    str::FromStr,
+};
+
+mod private
+{
+    use crate::{decoder, stream_vbyte};
+
+    /// Keeps `VertexId` to `u32` and `u64`, which the unchecked decoders rely
+    /// on: the group headers only have widths of up to `BYTES` bytes and
+    /// runs of up to 64 diffs for `u32` ids and 32 for `u64` ids.
+    ///
+    /// It also picks the decoders of each id type, so that `u32` graphs are
+    /// unpacked with SIMD with the `simd` feature, see `simd`.
+    pub trait Sealed: Sized
+    {
+        /// Unpacks the byte-RLE groups, see `decoder::decode_into`.
+        fn unpack_groups() -> decoder::Unpack<Self>;
+
+        /// Unpacks the Stream VByte differences, see
+        /// `stream_vbyte::decode_into`.
+        fn unpack_stream() -> stream_vbyte::Unpack<Self>;
+    }
+
+    impl Sealed for u32
+    {
+        #[cfg(feature = "simd")]
+        fn unpack_groups() -> decoder::Unpack<Self>
+        {
+            crate::simd::unpack()
+        }
+
+        #[cfg(not(feature = "simd"))]
+        fn unpack_groups() -> decoder::Unpack<Self>
+        {
+            decoder::unpack_scalar
+        }
+
+        #[cfg(feature = "simd")]
+        fn unpack_stream() -> stream_vbyte::Unpack<Self>
+        {
+            crate::simd::unpack_stream()
+        }
+
+        #[cfg(not(feature = "simd"))]
+        fn unpack_stream() -> stream_vbyte::Unpack<Self>
+        {
+            stream_vbyte::unpack_scalar
+        }
+    }
+
+    impl Sealed for u64
+    {
+        fn unpack_groups() -> decoder::Unpack<Self>
+        {
+            decoder::unpack_scalar
+        }
+
+        fn unpack_stream() -> stream_vbyte::Unpack<Self>
+        {
+            stream_vbyte::unpack_scalar
+        }
+    }
+}
+
+/// A vertex id, `u32` or `u64`. The trait is sealed, it can't be implemented
+/// for other types.
+///
+/// Graphs with more than 4B vertices need `u64` ids, which cost one bit of
+/// run length per group header, see `encoder`.
+pub trait VertexId:
+    private::Sealed + Copy + Ord + Hash + Default + Debug + Display + FromStr + Send + Sync + 'static
+{
+    /// The number of bytes of an id.
+    const BYTES: usize;
+
+    /// The largest id.
+    const MAX: Self;
+
+    /// Truncates `x` like an `as` cast.
+    fn from_u64(x: u64) -> Self;
+
+    fn to_u64(self) -> u64;
+
+    /// `None` if `x` doesn't fit.
+    fn try_from_u64(x: u64) -> Option<Self>;
+
+    #[inline]
+    fn from_usize(x: usize) -> Self
+    {
+        Self::from_u64(x as u64)
+    }
+
+    #[inline]
+    fn to_usize(self) -> usize
+    {
+        self.to_u64() as usize
+    }
+}
+
+impl VertexId for u32
+{
+    const BYTES: usize = 4;
+    const MAX: Self = u32::MAX;
+
+    #[inline]
+    fn from_u64(x: u64) -> Self
+    {
+        x as u32
+    }
+
+    #[inline]
+    fn to_u64(self) -> u64
+    {
+        self as u64
+    }
+
+    #[inline]
+    fn try_from_u64(x: u64) -> Option<Self>
+    {
+        u32::try_from(x).ok()
+    }
+}
+
+impl VertexId for u64
+{
+    const BYTES: usize = 8;
+    const MAX: Self = u64::MAX;
+
+    #[inline]
+    fn from_u64(x: u64) -> Self
+    {
+        x
+    }
+
+    #[inline]
+    fn to_u64(self) -> u64
+    {
+        self
+    }
+
+    #[inline]
+    fn try_from_u64(x: u64) -> Option<Self>
+    {
+        Some(x)
+    }
+}
@@ -0,0 +1,687 @@
+// glzip is a graph compression library for graph learning systems
+// Copyright (C) 2022 Jacob Konrad
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! A borrowed, zero-copy CSR over the bytes written by
+//! [`CSR::write_to`](crate::CSR::write_to), e.g. a memory mapped file.
+
+use std::{borrow::Cow, io, marker::PhantomData};
+
+use rayon::iter::Either;
+
+use crate::{
+    checked::{self, Checked, Cursor},
+    codec::{Codec, Coder},
+    error::{DecodeError, Error},
+    format,
+    graph::Graph,
+    reference,
+    skip::SkipIndex,
+    vertex::VertexId,
+    Edge,
+};
+
+/// A CSR that borrows its offsets and edges from a byte buffer.
+#[derive(Clone, Copy)]
+pub struct CsrView<'a, V: VertexId = u32>
+{
+    vertices: &'a [u8],
+    offset_width: usize,
+    // The index of each vertex's first edge in decode order.
+    edge_offsets: &'a [u8],
+    edge_offset_width: usize,
+    num_edges: usize,
+    edges: &'a [u8],
+    // The id of every edge as a little endian u64, in decode order.
+    edge_ids: Option<&'a [u8]>,
+    // The words of the skip index, see `SkipIndex`.
+    skips: &'a [u8],
+    codec: Codec,
+    // Whether vertices may be block encoded.
+    blocked: bool,
+    // The longest chain of references if vertices are encoded against
+    // references.
+    max_chain: Option<usize>,
+    marker: PhantomData<V>,
+}
+
+impl<'a, V: VertexId> CsrView<'a, V>
+{
+    /// Interprets `bytes` as a graph in glzip's binary format without
+    /// copying. Bytes after the skip index fail with [`Error::Corrupt`].
+    ///
+    /// This is not cheap: every vertex is decoded with checks and the skip
+    /// index is rebuilt to compare it, see [`CsrView::validate`], which
+    /// takes about as long as decoding the whole graph. A corrupt buffer
+    /// fails with [`Error::Corrupt`]. [`CsrView::new_unchecked`] only
+    /// checks the header and the lengths, in constant time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glzip::{CSR, CsrView};
+    ///
+    /// let csr = CSR::from(vec![
+    ///     [0u32,1],
+    ///     [0,2],
+    ///     [1,0],
+    ///     [2,1],
+    /// ]);
+    ///
+    /// let mut buf = Vec::new();
+    /// csr.write_to(&mut buf).unwrap();
+    ///
+    /// let view: CsrView = CsrView::new(&buf[..]).unwrap();
+    ///
+    /// assert_eq!(3, view.order());
+    /// assert_eq!(4, view.size());
+    /// assert_eq!(vec![1,2], view.neighbors(0).collect::<Vec<_>>());
+    /// ```
+    pub fn new(bytes: &'a [u8]) -> Result<Self, Error>
+    {
+        let view = Self::parse(bytes)?;
+        view.validate()?;
+        Ok(view)
+    }
+
+    /// Like [`CsrView::new`] but only checks the header and the lengths, for
+    /// buffers that are known to be valid, e.g. validated when the file was
+    /// first mapped.
+    ///
+    /// # Safety
+    ///
+    /// The neighbors are decoded without bounds checks, so the bytes must
+    /// pass [`CsrView::validate`], e.g. be written by
+    /// [`CSR::write_to`](crate::CSR::write_to) and not changed since.
+    pub unsafe fn new_unchecked(bytes: &'a [u8]) -> Result<Self, Error>
+    {
+        Self::parse(bytes)
+    }
+
+    /// Splits `bytes` into the offsets, the edges, the edge ids and the skip
+    /// index, failing if there are bytes after it.
+    fn parse(bytes: &'a [u8]) -> Result<Self, Error>
+    {
+        let truncated = || io::Error::new(io::ErrorKind::UnexpectedEof, "glzip buffer is truncated").into();
+
+        let (header, rest) = bytes.split_at_checked(format::HEADER_LEN).ok_or_else(truncated)?;
+        let header = format::Header::from_bytes(header)?;
+        header.check_vertex_id::<V>()?;
+
+        let split = |rest: &'a [u8], width: usize| {
+            header
+                .order
+                .checked_add(1)
+                .and_then(|len| len.checked_mul(width as u64))
+                .filter(|&len| len <= rest.len() as u64)
+                .map(|len| rest.split_at(len as usize))
+                .ok_or_else(truncated)
+        };
+
+        let offset_width = header.offset_width as usize;
+        let (vertices, rest) = split(rest, offset_width)?;
+
+        let edge_offset_width = header.edge_offset_width();
+        let (edge_offsets, rest) = split(rest, edge_offset_width)?;
+
+        if (rest.len() as u64) < header.num_bytes {
+            return Err(truncated());
+        }
+        let edges_start = bytes.len() - rest.len();
+        let (edges, rest) = rest.split_at(header.num_bytes as usize);
+
+        let (edge_ids, rest) = if header.flags & format::FLAG_EDGE_IDS != 0 {
+            let len = header.size.checked_mul(8).filter(|&len| len <= rest.len() as u64);
+            let (edge_ids, rest) = rest.split_at(len.ok_or_else(truncated)? as usize);
+            (Some(edge_ids), rest)
+        }
+        else {
+            (None, rest)
+        };
+
+        let (skips, rest) = SkipIndex::<V>::split(rest).ok_or_else(truncated)?;
+        // The offset is in the edges buffer as for the vertices.
+        Cursor::new(rest, bytes.len() - rest.len() - edges_start)
+            .finish()
+            .map_err(|err| DecodeError::new(header.order as usize, err))?;
+
+        Ok(Self {
+            vertices,
+            offset_width,
+            edge_offsets,
+            edge_offset_width,
+            num_edges: header.size as usize,
+            edges,
+            edge_ids,
+            skips,
+            codec: header.codec,
+            blocked: header.flags & format::FLAG_BLOCKS != 0,
+            max_chain: header.max_chain(),
+            marker: PhantomData,
+        })
+    }
+
+    #[inline]
+    fn offset(&self, i: usize) -> Option<usize>
+    {
+        let start = i * self.offset_width;
+        self.vertices
+            .get(start..start + self.offset_width)
+            .map(|bytes| format::read_offset(bytes, self.offset_width))
+    }
+
+    #[inline]
+    fn edge_offset(&self, i: usize) -> Option<usize>
+    {
+        let start = i * self.edge_offset_width;
+        self.edge_offsets
+            .get(start..start + self.edge_offset_width)
+            .map(|bytes| format::read_offset(bytes, self.edge_offset_width))
+    }
+
+    /// The codec with the model stored in front of the first vertex.
+    fn coder(&self) -> Coder<'a>
+    {
+        Coder {
+            codec: self.codec,
+            model: self.edges.get(..self.offset(0).unwrap_or(0)).unwrap_or(&[]),
+        }
+    }
+
+    /// The bytes of a vertex, empty if there is no such vertex or its
+    /// offsets are out of bounds.
+    fn vertex_bytes(&self, u: usize) -> &'a [u8]
+    {
+        self.offset(u)
+            .zip(self.offset(u + 1))
+            .and_then(|(start, end)| self.edges.get(start..end))
+            .unwrap_or(&[])
+    }
+
+    /// The neighbors of a vertex.
+    pub fn neighbors(&self, source: V) -> impl Iterator<Item = V> + 'a
+    {
+        let bytes = self.vertex_bytes(source.to_usize());
+        if let Some(max_chain) = self.max_chain {
+            let mut buf = Vec::new();
+            let view = *self;
+            reference::decode_into(self.coder(), max_chain, source, &|u| view.vertex_bytes(u), &mut buf);
+            return Either::Left(buf.into_iter());
+        }
+
+        Either::Right(self.coder().decode(source, bytes))
+    }
+
+    /// Appends the neighbors of a vertex to `buf`, see
+    /// [`CSR::neighbors_into`](crate::CSR::neighbors_into).
+    pub fn neighbors_into(&self, source: V, buf: &mut Vec<V>)
+    {
+        let bytes = self.vertex_bytes(source.to_usize());
+        buf.reserve(self.degree(source));
+        if let Some(max_chain) = self.max_chain {
+            reference::decode_into(self.coder(), max_chain, source, &|u| self.vertex_bytes(u), buf);
+        }
+        else {
+            self.coder().decode_into(source, bytes, buf);
+        }
+    }
+
+    /// The bytes of a vertex with their offset, failing if its offsets are
+    /// out of bounds.
+    fn try_vertex_cursor(&self, u: usize) -> Checked<Cursor<'a>>
+    {
+        match self.offset(u).zip(self.offset(u + 1)) {
+            Some((start, end)) if start > end || end > self.edges.len() => {
+                Err((start, "the offsets of the vertex are out of bounds"))
+            }
+            Some((start, end)) => Ok(Cursor::new(&self.edges[start..end], start)),
+            None => Ok(Cursor::new(&[], 0)),
+        }
+    }
+
+    /// Appends the neighbors of a vertex to `buf` with every byte checked,
+    /// see [`CSR::try_neighbors_into`](crate::CSR::try_neighbors_into).
+    pub fn try_neighbors_into(&self, source: V, buf: &mut Vec<V>) -> Result<(), DecodeError>
+    {
+        let model = self
+            .offset(0)
+            .filter(|&start| start <= self.edges.len())
+            .ok_or(DecodeError::new(0, (0, "the offsets of the vertex are out of bounds")))?;
+        let coder = Coder {
+            codec: self.codec,
+            model: &self.edges[..model],
+        };
+        let bytes = |u| self.try_vertex_cursor(u);
+        checked::decode_into(coder, self.max_chain, self.order(), source, &bytes, buf)
+    }
+
+    /// Checks that the bytes of every vertex decode to neighbors that are
+    /// increasing and vertices of the graph, as many as its stored degree,
+    /// that there are as many as the size of the graph, and that the skip
+    /// index is the one of the bytes.
+    /// [`CsrView::new`] checks this, [`CsrView::new_unchecked`] doesn't.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glzip::{CSR, CsrView, Edge};
+    ///
+    /// let csr = CSR::from_edges((0..1000u32).map(|v| Edge(v % 10, v)));
+    ///
+    /// let mut buf = Vec::new();
+    /// csr.write_to(&mut buf).unwrap();
+    ///
+    /// let view: CsrView = CsrView::new(&buf[..]).unwrap();
+    /// assert_eq!(Ok(()), view.validate());
+    ///
+    /// // The last neighbors of vertex 9 are past the last vertex. The edges
+    /// // are followed by the 16 bytes of an empty skip index.
+    /// let len = buf.len() - 16;
+    /// buf[len - 8..len].fill(0xff);
+    ///
+    /// let view: CsrView = unsafe { CsrView::new_unchecked(&buf[..]) }.unwrap();
+    /// assert_eq!(9, view.validate().unwrap_err().vertex);
+    /// assert!(CsrView::<u32>::new(&buf[..]).is_err());
+    /// assert!(CSR::<u32>::read_from(&buf[..]).is_err());
+    /// ```
+    pub fn validate(&self) -> Result<(), DecodeError>
+    {
+        let end = self.offset(self.order()).unwrap_or(0);
+        if end != self.edges.len() {
+            return Err(DecodeError::new(self.order(), (end, "the offsets don't end with the edges")));
+        }
+        if self.edge_offset(0) != Some(0) {
+            return Err(DecodeError::new(0, (0, "the degree doesn't match")));
+        }
+        let degrees = checked::degrees(self.order(), |u, buf| {
+            self.try_neighbors_into(V::from_usize(u), buf)?;
+            if Some(buf.len()) != self.stored_degree(u) {
+                let offset = self.offset(u).unwrap_or(0);
+                return Err(DecodeError::new(u, (offset, "the degree doesn't match")));
+            }
+            Ok(())
+        })?;
+        if degrees.iter().sum::<usize>() != self.num_edges {
+            return Err(DecodeError::new(self.order(), (end, "the size doesn't match")));
+        }
+        // The skips are decoded from without checks, so they must be the
+        // ones of the bytes that were just checked.
+        let skips = if self.max_chain.is_some() {
+            SkipIndex::default()
+        }
+        else {
+            SkipIndex::<V>::new(
+                self.codec,
+                self.order(),
+                |u| self.stored_degree(u).unwrap_or(0),
+                |u| self.vertex_bytes(u),
+            )
+        };
+        if skips.as_bytes() != self.skips {
+            return Err(DecodeError::new(self.order(), (end, "the skip index doesn't match")));
+        }
+        Ok(())
+    }
+
+    /// The degree of a vertex from the edge offsets.
+    fn stored_degree(&self, u: usize) -> Option<usize>
+    {
+        let (start, end) = self.edge_offset(u).zip(self.edge_offset(u + 1))?;
+        end.checked_sub(start)
+    }
+
+    /// The degree of a vertex, in constant time from the edge offsets.
+    pub fn degree(&self, source: V) -> usize
+    {
+        self.stored_degree(source.to_usize()).unwrap_or(0)
+    }
+
+    /// The `i`th neighbor of a vertex in ascending order, from the skip
+    /// index like [`CSR::neighbor_at`](crate::CSR::neighbor_at).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glzip::{CSR, CsrView, Edge};
+    ///
+    /// let csr = CSR::from_edges((0..1000u32).map(|v| Edge(0, 2 * v)));
+    ///
+    /// let mut buf = Vec::new();
+    /// csr.write_to(&mut buf).unwrap();
+    ///
+    /// let view: CsrView = CsrView::new(&buf[..]).unwrap();
+    /// assert_eq!(Some(1000), view.neighbor_at(0, 500));
+    /// assert_eq!(None, view.neighbor_at(0, 1000));
+    /// assert!(view.has_edge(0, 1998));
+    /// assert!(!view.has_edge(0, 1999));
+    /// ```
+    pub fn neighbor_at(&self, source: V, i: usize) -> Option<V>
+    {
+        if self.max_chain.is_some() {
+            return self.neighbors(source).nth(i);
+        }
+
+        let u = source.to_usize();
+        self.offset(u + 1)?;
+        self.skip_index()
+            .neighbor_at(self.coder(), source, self.vertex_bytes(u), i)
+    }
+
+    /// Whether the graph has the edge from `source` to `target`, from the
+    /// skip index like [`CSR::has_edge`](crate::CSR::has_edge).
+    pub fn has_edge(&self, source: V, target: V) -> bool
+    {
+        if self.max_chain.is_some() {
+            return self.neighbors(source).find(|&v| v >= target) == Some(target);
+        }
+
+        let u = source.to_usize();
+        self.offset(u + 1).is_some()
+            && self
+                .skip_index()
+                .contains(self.coder(), source, self.vertex_bytes(u), target)
+    }
+
+    fn skip_index(&self) -> SkipIndex<'a, V>
+    {
+        SkipIndex::borrowed(self.skips)
+    }
+
+    /// The bytes of the ids of the edges of a vertex, empty if there is no
+    /// such vertex or its edge offsets are out of bounds.
+    fn edge_id_bytes(&self, source: V) -> Option<&'a [u8]>
+    {
+        let edge_ids = self.edge_ids?;
+        let u = source.to_usize();
+        let bytes = self
+            .edge_offset(u)
+            .zip(self.edge_offset(u + 1))
+            .and_then(|(start, end)| edge_ids.get(start.checked_mul(8)?..end.checked_mul(8)?));
+        Some(bytes.unwrap_or(&[]))
+    }
+
+    /// The ids of the edges of a vertex in the order of its neighbors, `None`
+    /// if the graph was built without edge ids, see
+    /// [`CSR::edge_ids`](crate::CSR::edge_ids). They are borrowed from the
+    /// buffer if it is aligned for them and copied otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glzip::{CSR, CsrView, Edge};
+    ///
+    /// let csr = CSR::from_edges_with_ids(vec![Edge(2u32, 1), Edge(0, 2), Edge(0, 1)]);
+    ///
+    /// let mut buf = Vec::new();
+    /// csr.write_to(&mut buf).unwrap();
+    ///
+    /// let view: CsrView = CsrView::new(&buf[..]).unwrap();
+    /// assert_eq!(Some(&[2, 1][..]), view.edge_ids(0).as_deref());
+    /// assert_eq!(
+    ///     vec![(1, 2), (2, 1)],
+    ///     view.neighbors_with_eid(0).unwrap().collect::<Vec<_>>()
+    /// );
+    /// ```
+    pub fn edge_ids(&self, source: V) -> Option<Cow<'a, [u64]>>
+    {
+        let bytes = self.edge_id_bytes(source)?;
+        // Every bit pattern is a valid `u64`.
+        match unsafe { bytes.align_to::<u64>() } {
+            ([], ids, []) if cfg!(target_endian = "little") => Some(Cow::Borrowed(ids)),
+            _ => Some(Cow::Owned(bytes.chunks_exact(8).map(read_edge_id).collect())),
+        }
+    }
+
+    /// The neighbors of a vertex with the ids of the edges to them, `None`
+    /// if the graph was built without edge ids.
+    pub fn neighbors_with_eid(&self, source: V) -> Option<impl Iterator<Item = (V, u64)> + 'a>
+    {
+        let bytes = self.edge_id_bytes(source)?;
+        Some(self.neighbors(source).zip(bytes.chunks_exact(8).map(read_edge_id)))
+    }
+
+    /// The edges of the graph.
+    pub fn edges(&self) -> impl Iterator<Item = Edge<V>> + 'a
+    {
+        let view = *self;
+        (0..self.order()).flat_map(move |u| {
+            let u = V::from_usize(u);
+            view.neighbors(u).map(move |v| Edge(u, v))
+        })
+    }
+
+    /// The number of vertices in the graph.
+    pub fn order(&self) -> usize
+    {
+        (self.vertices.len() / self.offset_width).saturating_sub(1)
+    }
+
+    /// The number of edges in the graph.
+    pub fn size(&self) -> usize
+    {
+        self.num_edges
+    }
+}
+
+impl<'a, V: VertexId> Graph for CsrView<'a, V>
+{
+    type Vertex = V;
+
+    fn neighbors(&self, source: V) -> impl Iterator<Item = V> + '_
+    {
+        CsrView::neighbors(self, source)
+    }
+
+    fn degree(&self, source: V) -> usize
+    {
+        CsrView::degree(self, source)
+    }
+
+    fn neighbors_into(&self, source: V, buf: &mut Vec<V>)
+    {
+        CsrView::neighbors_into(self, source, buf)
+    }
+
+    fn neighbor_at(&self, source: V, i: usize) -> Option<V>
+    {
+        CsrView::neighbor_at(self, source, i)
+    }
+
+    fn random_access(&self) -> bool
+    {
+        self.max_chain.is_none() && (self.codec == Codec::ByteRle || self.blocked)
+    }
+
+    fn has_edge(&self, source: V, target: V) -> bool
+    {
+        CsrView::has_edge(self, source, target)
+    }
+
+    fn order(&self) -> usize
+    {
+        CsrView::order(self)
+    }
+
+    fn size(&self) -> usize
+    {
+        CsrView::size(self)
+    }
+
+    fn edge_ids(&self, source: V) -> Option<Cow<'_, [u64]>>
+    {
+        CsrView::edge_ids(self, source)
+    }
+}
+
+#[inline]
+fn read_edge_id(bytes: &[u8]) -> u64
+{
+    u64::from_le_bytes(bytes.try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{skip::SKIP_LEN, CSR};
+
+    fn bytes() -> Vec<u8>
+    {
+        let csr = CSR::from_edges((0..500u32).map(|v| Edge(v % 7, (13 * v) % 500)));
+        let mut buf = Vec::new();
+        csr.write_to(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn degrees_are_stored()
+    {
+        let buf = bytes();
+        let csr: CSR = CSR::read_from(&buf[..]).unwrap();
+        let view: CsrView = CsrView::new(&buf[..]).unwrap();
+        for u in 0..view.order() as u32 {
+            assert_eq!(csr.degree(u), view.degree(u));
+            assert_eq!(csr.neighbors(u).count(), view.degree(u));
+        }
+    }
+
+    #[test]
+    fn truncated_buffers_fail()
+    {
+        let buf = bytes();
+        for len in 0..buf.len() {
+            assert!(CsrView::<u32>::new(&buf[..len]).is_err(), "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn trailing_bytes_fail()
+    {
+        let mut buf = bytes();
+        let view: CsrView = CsrView::new(&buf[..]).unwrap();
+        // The offset of the end in the edges buffer.
+        let end = buf.len() - format::HEADER_LEN - view.vertices.len() - view.edge_offsets.len();
+        buf.push(0);
+        for view in [CsrView::<u32>::new(&buf[..]), unsafe { CsrView::<u32>::new_unchecked(&buf[..]) }] {
+            match view {
+                Err(Error::Corrupt(err)) => {
+                    assert_eq!(checked::TRAILING, err.reason);
+                    assert_eq!(end, err.offset);
+                }
+                Err(err) => panic!("{}", err),
+                Ok(_) => panic!("a buffer with a trailing byte was accepted"),
+            }
+        }
+    }
+
+    #[test]
+    fn corrupt_buffers_fail_or_decode()
+    {
+        let buf = bytes();
+        for i in format::HEADER_LEN..buf.len() {
+            for bit in 0..8 {
+                let mut corrupt = buf.clone();
+                corrupt[i] ^= 1 << bit;
+                if let Ok(view) = CsrView::<u32>::new(&corrupt[..]) {
+                    let size: usize = (0..view.order() as u32).map(|u| view.neighbors(u).count()).sum();
+                    assert_eq!(view.size(), size);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn degrees_must_match()
+    {
+        let mut buf = bytes();
+        // The edge offsets follow the 501 offsets of the graph. Lowering the
+        // one of vertex 1 moves an edge of vertex 0 to it.
+        let at = format::HEADER_LEN + 4 * 501 + 4;
+        buf[at] -= 1;
+        let err = CsrView::<u32>::new(&buf[..]).err().unwrap();
+        assert!(matches!(err, Error::Corrupt(DecodeError { vertex: 0, .. })), "{}", err);
+        assert!(CSR::<u32>::read_from(&buf[..]).is_err());
+    }
+
+    #[test]
+    fn edge_ids_match_the_csr()
+    {
+        let csr = CSR::from_edges_with_ids((0..500u32).map(|v| Edge(v % 7, (13 * v) % 500)));
+        let mut buf = Vec::new();
+        csr.write_to(&mut buf).unwrap();
+
+        // The ids are copied rather than borrowed at least at one of the two
+        // alignments.
+        let mut shifted = vec![0];
+        shifted.extend_from_slice(&buf);
+        for bytes in [&buf[..], &shifted[1..]] {
+            let view: CsrView = CsrView::new(bytes).unwrap();
+            for u in 0..view.order() as u32 {
+                assert_eq!(csr.edge_ids(u), view.edge_ids(u).as_deref());
+                assert_eq!(csr.edge_ids(u), Graph::edge_ids(&view, u).as_deref());
+                assert_eq!(
+                    csr.neighbors_with_eid(u).unwrap().collect::<Vec<_>>(),
+                    view.neighbors_with_eid(u).unwrap().collect::<Vec<_>>()
+                );
+            }
+        }
+
+        for len in 0..buf.len() {
+            assert!(CsrView::<u32>::new(&buf[..len]).is_err(), "{} bytes", len);
+        }
+
+        let buf = bytes();
+        let view: CsrView = CsrView::new(&buf[..]).unwrap();
+        assert!(view.edge_ids(0).is_none());
+        assert!(view.neighbors_with_eid(0).is_none());
+    }
+
+    #[test]
+    fn lookups_match_the_csr()
+    {
+        // Vertex 0 has skips, vertex 1 has 30 neighbors.
+        let edges = || (0..3000u32).map(|v| Edge(u32::from(v % 100 == 0), 3 * v));
+        for builder in [
+            CSR::builder(),
+            CSR::builder().block_len(100),
+            CSR::builder().codec(Codec::Nibble),
+            CSR::builder().references(3, 3),
+        ] {
+            let csr = builder.build(edges());
+            let mut buf = Vec::new();
+            csr.write_to(&mut buf).unwrap();
+            let view: CsrView = CsrView::new(&buf[..]).unwrap();
+            for u in 0..4 {
+                for i in (0..csr.degree(u) + 2).step_by(7) {
+                    assert_eq!(csr.neighbor_at(u, i), view.neighbor_at(u, i));
+                }
+                for v in (0..9000).step_by(5) {
+                    assert_eq!(csr.has_edge(u, v), view.has_edge(u, v));
+                }
+            }
+        }
+
+        let csr = CSR::from_edges(edges());
+        assert!(csr.degree(0) > 2 * SKIP_LEN);
+        let mut buf = Vec::new();
+        csr.write_to(&mut buf).unwrap();
+        // The neighbor before the last skip.
+        let len = buf.len();
+        buf[len - 8] ^= 1;
+        let err = CsrView::<u32>::new(&buf[..]).err().unwrap();
+        assert!(matches!(err, Error::Corrupt(DecodeError { reason: "the skip index doesn't match", .. })));
+        assert!(CSR::<u32>::read_from(&buf[..]).is_err());
+    }
+}
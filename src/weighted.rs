@@ -0,0 +1,342 @@
+// glzip is a graph compression library for graph learning systems
+// Copyright (C) 2022 Jacob Konrad
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! A CSR with a weight per edge. The targets are compressed as in [`CSR`],
+//! the weights are stored uncompressed per vertex in the same order as the
+//! targets, i.e. ascending.
+
+use std::{
+    fmt::Display,
+    io::{self, BufRead, Write},
+};
+
+use rayon::prelude::*;
+
+use crate::{
+    builder::CsrBuilder,
+    error::Error,
+    graph::Graph,
+    mtx::MatrixMarket,
+    vertex::VertexId,
+    Edge,
+    CSR,
+};
+
+/// Sorts `buf` stably, merges the payloads of parallel edges with `combine`
+/// in the order of `buf` and compresses the edges. The payloads are returned
+/// in decode order, those of a vertex are at [`CSR::edge_range`].
+pub(crate) fn compress_with<V, W, F>(mut buf: Vec<(Edge<V>, W)>, combine: F) -> Result<(CSR<V>, Vec<W>), Error>
+where
+    V: VertexId,
+    W: Copy + Send,
+    F: Fn(W, W) -> W,
+{
+    buf.par_sort_by_key(|&(e, _)| e);
+
+    let mut edges: Vec<Edge<V>> = Vec::new();
+    edges.try_reserve_exact(buf.len())?;
+    let mut payloads: Vec<W> = Vec::new();
+    payloads.try_reserve_exact(buf.len())?;
+    for (e, w) in buf {
+        match (edges.last(), payloads.last_mut()) {
+            (Some(&last), Some(acc)) if last == e => *acc = combine(*acc, w),
+            _ => {
+                edges.push(e);
+                payloads.push(w);
+            }
+        }
+    }
+    payloads.shrink_to_fit();
+
+    // The edges are sorted and deduplicated already.
+    let csr = CSR::from_sorted_with(&edges, &CsrBuilder::new())?;
+
+    Ok((csr, payloads))
+}
+
+/// The weighted Compressed Sparse Row struct.
+pub struct WeightedCSR<W = f32, V: VertexId = u32>
+{
+    csr: CSR<V>,
+    weights: Vec<W>,
+}
+
+impl<W: Copy + Send + Sync, V: VertexId> WeightedCSR<W, V>
+{
+    /// Builds a graph from weighted edges, the weights of parallel edges are
+    /// merged with `combine` in the order of `iter`, e.g. `|a, b| a + b` to
+    /// sum them or `|a, _| a` to keep the first one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glzip::{weighted::WeightedCSR, Edge};
+    ///
+    /// let csr = WeightedCSR::from_weighted_edges(
+    ///     vec![
+    ///         (Edge(0u32, 2), 1.0f32),
+    ///         (Edge(0, 1), 0.5),
+    ///         (Edge(0, 2), 2.0),
+    ///         (Edge(2, 1), 0.25),
+    ///     ],
+    ///     |a, b| a + b,
+    /// );
+    ///
+    /// assert_eq!(vec![(1, 0.5), (2, 3.0)], csr.neighbors(0).collect::<Vec<_>>());
+    /// assert_eq!(vec![(1, 0.25)], csr.neighbors(2).collect::<Vec<_>>());
+    /// assert_eq!(3, csr.size());
+    ///
+    /// let csr = WeightedCSR::from_weighted_edges(
+    ///     (0..1000u32).map(|w| (Edge(0u32, 1), w)),
+    ///     |a, _| a,
+    /// );
+    ///
+    /// assert_eq!(vec![(1, 0)], csr.neighbors(0).collect::<Vec<_>>());
+    /// ```
+    pub fn from_weighted_edges<I, F>(iter: I, combine: F) -> Self
+    where
+        I: IntoIterator<Item = (Edge<V>, W)>,
+        F: Fn(W, W) -> W,
+    {
+        let graph: Result<Self, Error> = Self::try_from_weighted_edges(iter.into_iter().map(Ok), combine);
+
+        graph.unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like [`from_weighted_edges`](Self::from_weighted_edges) for weighted
+    /// edges that may fail, the first error of an edge is returned as
+    /// [`Error::Source`]. Fails with [`Error::Capacity`] if the edges or the
+    /// graph don't fit into memory and [`Error::IdOverflow`] if its largest
+    /// vertex can't be indexed.
+    pub fn try_from_weighted_edges<E, I, F>(iter: I, combine: F) -> Result<Self, Error<E>>
+    where
+        I: IntoIterator<Item = Result<(Edge<V>, W), E>>,
+        F: Fn(W, W) -> W,
+    {
+        let mut buf = Vec::new();
+        for edge in iter {
+            buf.try_reserve(1)?;
+            buf.push(edge.map_err(Error::Source)?);
+        }
+        Self::from_buffer(buf, combine).map_err(Error::widen)
+    }
+
+    fn from_buffer<F>(buf: Vec<(Edge<V>, W)>, combine: F) -> Result<Self, Error>
+    where
+        F: Fn(W, W) -> W,
+    {
+        let (csr, weights) = compress_with(buf, combine)?;
+
+        Ok(Self { csr, weights })
+    }
+
+    /// The neighbors of a vertex with the weights of the edges to them.
+    pub fn neighbors(&self, source: V) -> impl Iterator<Item = (V, W)> + '_
+    {
+        self.csr
+            .neighbors(source)
+            .zip(self.weights(source).iter().copied())
+    }
+
+    /// The weights of the edges of a vertex, in the order of its neighbors.
+    pub fn weights(&self, source: V) -> &[W]
+    {
+        &self.weights[self.csr.edge_range(source)]
+    }
+
+    /// The degree of a vertex.
+    pub fn degree(&self, source: V) -> usize
+    {
+        self.csr.degree(source)
+    }
+
+    /// The edges of the graph with their weights.
+    pub fn edges(&self) -> impl Iterator<Item = (Edge<V>, W)> + '_
+    {
+        (0..self.order()).flat_map(move |u| {
+            let u = V::from_usize(u);
+            self.neighbors(u).map(move |(v, w)| (Edge(u, v), w))
+        })
+    }
+
+    /// The unweighted graph.
+    pub fn as_csr(&self) -> &CSR<V>
+    {
+        &self.csr
+    }
+
+    pub fn nbytes(&self) -> usize
+    {
+        let mut bytes = std::mem::size_of_val(self);
+        bytes += self.csr.nbytes() - std::mem::size_of_val(&self.csr);
+        bytes += std::mem::size_of_val(&self.weights[..]);
+        bytes
+    }
+
+    /// The number of vertices in the graph.
+    pub fn order(&self) -> usize
+    {
+        self.csr.order()
+    }
+
+    /// The number of edges in the graph.
+    pub fn size(&self) -> usize
+    {
+        self.csr.size()
+    }
+
+    /// Builds a graph from a MatrixMarket coordinate file, the values are
+    /// converted to weights with `weight` and the weights of duplicate
+    /// entries are merged with `combine` in the order of the file. Pattern
+    /// files have the value `1.0`.
+    /// The order and the errors are those of
+    /// [`CSR::from_matrix_market`](crate::CSR::from_matrix_market).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use glzip::weighted::WeightedCSR;
+    ///
+    /// let text = "%%MatrixMarket matrix coordinate real symmetric\n\
+    ///             2 2 1\n\
+    ///             2 1 0.5\n";
+    ///
+    /// let csr: WeightedCSR =
+    ///     WeightedCSR::from_matrix_market(text.as_bytes(), |x| x as f32, |a, b| a + b).unwrap();
+    ///
+    /// assert_eq!(vec![(1, 0.5)], csr.neighbors(0).collect::<Vec<_>>());
+    /// assert_eq!(vec![(0, 0.5)], csr.neighbors(1).collect::<Vec<_>>());
+    /// ```
+    pub fn from_matrix_market<R, G, F>(reader: R, weight: G, combine: F) -> Result<Self, Error>
+    where
+        R: BufRead,
+        G: Fn(f64) -> W,
+        F: Fn(W, W) -> W,
+    {
+        let mtx: MatrixMarket<R, V> = MatrixMarket::new(reader)?;
+        let order = mtx.order()?;
+        let mut buf = Vec::new();
+        buf.try_reserve(mtx.capacity())?;
+        mtx.read_into(&mut buf, |e, x| (e, weight(x)))?;
+        let mut csr = Self::from_buffer(buf, combine)?;
+        csr.csr.pad_order(order)?;
+        Ok(csr)
+    }
+}
+
+impl<W: Copy + Send + Sync + Display, V: VertexId> WeightedCSR<W, V>
+{
+    /// Writes the graph as a square `coordinate real general` MatrixMarket file.
+    pub fn write_matrix_market<T: Write>(&self, w: T) -> io::Result<()>
+    {
+        let mut w = io::BufWriter::new(w);
+        writeln!(w, "%%MatrixMarket matrix coordinate real general")?;
+        writeln!(w, "{} {} {}", self.order(), self.order(), self.size())?;
+        for (Edge(u, v), x) in self.edges() {
+            writeln!(w, "{} {} {}", u.to_u64() + 1, v.to_u64() + 1, x)?;
+        }
+        w.flush()
+    }
+}
+
+impl<W: Copy + Send + Sync, V: VertexId> Graph for WeightedCSR<W, V>
+{
+    type Vertex = V;
+
+    fn neighbors(&self, source: V) -> impl Iterator<Item = V> + '_
+    {
+        self.csr.neighbors(source)
+    }
+
+    fn degree(&self, source: V) -> usize
+    {
+        WeightedCSR::degree(self, source)
+    }
+
+    fn neighbors_into(&self, source: V, buf: &mut Vec<V>)
+    {
+        self.csr.neighbors_into(source, buf)
+    }
+
+    fn neighbor_at(&self, source: V, i: usize) -> Option<V>
+    {
+        self.csr.neighbor_at(source, i)
+    }
+
+    fn random_access(&self) -> bool
+    {
+        Graph::random_access(&self.csr)
+    }
+
+    fn has_edge(&self, source: V, target: V) -> bool
+    {
+        self.csr.has_edge(source, target)
+    }
+
+    fn order(&self) -> usize
+    {
+        WeightedCSR::order(self)
+    }
+
+    fn size(&self) -> usize
+    {
+        WeightedCSR::size(self)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn parallel_edges_merge_in_input_order()
+    {
+        // Sorting must keep the order of the weights of each edge, which
+        // only shows with a `combine` that isn't commutative.
+        let edges = (0..1000u32).map(|w| (Edge(1u32, 3 - w % 3), w));
+        let csr: WeightedCSR<u32> = WeightedCSR::from_weighted_edges(edges, |a, b| a.wrapping_mul(31).wrapping_add(b));
+
+        for v in 1..=3 {
+            let weight = (0..1000u32)
+                .filter(|w| 3 - w % 3 == v)
+                .reduce(|a, b| a.wrapping_mul(31).wrapping_add(b));
+            assert_eq!(weight, csr.neighbors(1).find(|&(w, _)| w == v).map(|(_, weight)| weight));
+        }
+        assert_eq!(vec![1, 2, 3], csr.as_csr().neighbors(1).collect::<Vec<_>>());
+        assert_eq!(3, csr.size());
+    }
+
+    #[test]
+    fn empty_input()
+    {
+        let csr: WeightedCSR = WeightedCSR::from_weighted_edges([], |a, _| a);
+        assert_eq!(0, csr.order());
+        assert_eq!(0, csr.size());
+        assert_eq!(0, csr.edges().count());
+    }
+
+    #[test]
+    fn u64_ids()
+    {
+        let edges = vec![(Edge(7u64, 1 << 20), 1.0f32), (Edge(7, 3), 2.0), (Edge(7, 1 << 20), 0.5)];
+        let csr: WeightedCSR<f32, u64> = WeightedCSR::from_weighted_edges(edges, |a, b| a + b);
+
+        assert_eq!((1 << 20) + 1, csr.order());
+        assert_eq!(vec![(3, 2.0), (1 << 20, 1.5)], csr.neighbors(7).collect::<Vec<_>>());
+        assert_eq!(&[2.0, 1.5], csr.weights(7));
+    }
+}